@@ -0,0 +1,218 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The query a checkpoint belongs to, written as its first line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanHeader {
+    pub network: String,
+    pub query: String,
+    pub address: Option<String>,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub raw: bool,
+    /// Only transactions that succeeded or failed
+    #[serde(default)]
+    pub success: Option<bool>,
+    pub start_sequence: u32,
+    pub end_sequence: u32,
+}
+
+/// A fully processed ledger: its results, or why it could not be fetched
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerRecord {
+    pub ledger: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed: Option<Value>,
+    /// RPC endpoint the ledger came from, if not the data lake
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub served_by: Option<String>,
+}
+
+/// Progress of a long range scan, so it can be resumed after a crash
+///
+/// The file holds one JSON document per line: the `ScanHeader`, then a
+/// `LedgerRecord` for each ledger in order, appended once the ledger has
+/// been processed. Only whole lines count, so a line cut short by a crash
+/// is dropped on resume and its ledger processed again.
+pub struct Checkpoint {
+    file: BufWriter<File>,
+}
+
+impl Checkpoint {
+    /// Start a checkpoint for a new scan
+    ///
+    /// Fails if the file exists, so a scan isn't thrown away by mistake.
+    pub fn create(path: &Path, header: &ScanHeader) -> Result<Self> {
+        let file = OpenOptions::new().write(true).create_new(true).open(path)
+            .with_context(|| match path.exists() {
+                true => format!("Checkpoint {} exists; use --resume to continue it, or delete it", path.display()),
+                false => format!("Failed to create checkpoint {}", path.display()),
+            })?;
+
+        let mut checkpoint = Checkpoint { file: BufWriter::new(file) };
+        checkpoint.write_line(header)
+            .with_context(|| format!("Failed to write checkpoint {}", path.display()))?;
+        Ok(checkpoint)
+    }
+
+    /// Open a checkpoint to resume its scan
+    ///
+    /// Finds the last ledger recorded, so the scan can pick up after it
+    /// before the records are read back.
+    pub fn resume(path: &Path) -> Result<Replay> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open checkpoint {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let header: ScanHeader = serde_json::from_str(&line)
+            .with_context(|| format!("{} is not a checkpoint file", path.display()))?;
+        let start = line.len() as u64;
+
+        #[derive(Deserialize)]
+        struct Ledger {
+            ledger: u32,
+        }
+
+        let mut end = start;
+        let mut last_ledger = None;
+        loop {
+            line.clear();
+            // A line cut short by a crash is dropped
+            if reader.read_line(&mut line)? == 0 || !line.ends_with('\n') {
+                break;
+            }
+            let record: Ledger = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid record in checkpoint {}: {}", path.display(), line.trim_end()))?;
+            end += line.len() as u64;
+            last_ledger = Some(record.ledger);
+        }
+
+        reader.seek(SeekFrom::Start(start))?;
+        Ok(Replay { header, path: path.to_path_buf(), reader, position: start, end, last_ledger })
+    }
+
+    /// Record a processed ledger
+    pub fn record(&mut self, record: &LedgerRecord) -> io::Result<()> {
+        self.write_line(record)
+    }
+
+    fn write_line(&mut self, value: &impl Serialize) -> io::Result<()> {
+        serde_json::to_writer(&mut self.file, value)?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()
+    }
+}
+
+/// The ledgers recorded in a checkpoint, read back in order
+///
+/// `into_checkpoint` carries on recording after the last ledger recorded.
+pub struct Replay {
+    pub header: ScanHeader,
+    path: PathBuf,
+    reader: BufReader<File>,
+    position: u64,
+    /// End of the last whole line
+    end: u64,
+    last_ledger: Option<u32>,
+}
+
+impl Replay {
+    /// Last ledger recorded, if any
+    pub fn last_ledger(&self) -> Option<u32> {
+        self.last_ledger
+    }
+
+    /// Continue recording after the ledgers recorded
+    pub fn into_checkpoint(self) -> Result<Checkpoint> {
+        drop(self.reader);
+
+        let mut file = OpenOptions::new().write(true).open(&self.path)
+            .with_context(|| format!("Failed to open checkpoint {}", self.path.display()))?;
+        file.set_len(self.end)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(Checkpoint { file: BufWriter::new(file) })
+    }
+}
+
+impl Iterator for Replay {
+    type Item = Result<LedgerRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.end {
+            return None;
+        }
+
+        let mut line = String::new();
+        if let Err(e) = self.reader.read_line(&mut line) {
+            return Some(Err(e.into()));
+        }
+        self.position += line.len() as u64;
+        Some(serde_json::from_str(&line)
+            .with_context(|| format!("Invalid record in checkpoint {}", self.path.display())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::testing::TempDir;
+
+    fn header() -> ScanHeader {
+        ScanHeader {
+            network: "pubnet".to_string(),
+            query: "address".to_string(),
+            address: Some("GALPCCZN4YXA3YMJHKL6CVIECKPLJJCTVMSNYWBTKJW4K5HQLYLDMZTB".to_string()),
+            name: None,
+            raw: false,
+            success: None,
+            start_sequence: 100,
+            end_sequence: 110,
+        }
+    }
+
+    fn record(ledger: u32) -> LedgerRecord {
+        LedgerRecord { ledger, results: vec![json!({ "ledger": ledger })], failed: None, served_by: None }
+    }
+
+    #[test]
+    fn test_resume_after_crash() {
+        let dir = TempDir::new("checkpoint");
+        let path = dir.join("scan.jsonl");
+
+        let mut checkpoint = Checkpoint::create(&path, &header()).unwrap();
+        checkpoint.record(&record(100)).unwrap();
+        checkpoint.record(&LedgerRecord { ledger: 101, results: Vec::new(), failed: Some(json!({ "ledger": 101 })), served_by: None }).unwrap();
+        drop(checkpoint);
+        assert!(Checkpoint::create(&path, &header()).is_err());
+
+        // A crash part way through writing a record
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"ledger":102,"resu"#).unwrap();
+        drop(file);
+
+        let mut replay = Checkpoint::resume(&path).unwrap();
+        assert_eq!(replay.header, header());
+        assert_eq!(replay.last_ledger(), Some(101));
+        let records: Vec<LedgerRecord> = replay.by_ref().map(Result::unwrap).collect();
+        assert_eq!(records.len(), 2);
+        assert!(records[1].failed.is_some());
+
+        let mut checkpoint = replay.into_checkpoint().unwrap();
+        checkpoint.record(&record(102)).unwrap();
+        drop(checkpoint);
+
+        let replay = Checkpoint::resume(&path).unwrap();
+        let ledgers: Vec<u32> = replay.map(|record| record.unwrap().ledger).collect();
+        assert_eq!(ledgers, vec![100, 101, 102]);
+    }
+}
@@ -1,4 +1,8 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use crate::config::{Config, Network, Overrides};
+use crate::config::overrides::{CacheOverrides, RetryOverrides, S3Overrides};
 
 pub const LONG_ABOUT: &str = r#"                       /   \
                )      ((   ))     (
@@ -22,7 +26,12 @@ Query Stellar blockchain data using RPC & Public data lake.
     stellar-data --query price --asset CB23WRD...
     stellar-data --query transactions --ledger 50000000
     stellar-data --query address --ledger 63864-63900 --address GABC...
+    stellar-data --query address --ledger 63864-638900 --address GABC... --checkpoint scan.jsonl [--resume]
     stellar-data --server --port 8080
+    stellar-data config show
+    stellar-data tokens validate
+    stellar-data cache stats
+    stellar-data status
     stellar-data --help (Provides more detailed options)
 
     For more information: https://github.com/jamesbachini/Stellar-Data"#;
@@ -61,6 +70,7 @@ pub struct Args {
     ///   address      - Transactions involving a specific address (requires --address)
     ///   contract     - Transactions involving a specific contract (requires --address)
     ///   function     - Transactions calling a specific function (requires --name)
+    ///   operations   - One record per operation, those involving --address if given
     ///   balance      - Token balance for an address (requires --address and --token)
     ///   price        - Oracle price for an asset (requires --asset)
     #[arg(
@@ -68,7 +78,7 @@ pub struct Args {
         long,
         default_value = "all",
         value_name = "TYPE",
-        help = "Query type: 'all', 'transactions', 'address', 'contract', 'function', 'balance', or 'price'"
+        help = "Query type: 'all', 'transactions', 'address', 'contract', 'function', 'operations', 'balance', or 'price'"
     )]
     pub query: String,
 
@@ -81,6 +91,7 @@ pub struct Args {
     ///   - Payment destination
     ///   - Asset issuer
     ///   - And other address-related fields
+    ///
     /// For 'contract': Searches for transactions that invoke the specified contract
     #[arg(
         short,
@@ -102,15 +113,16 @@ pub struct Args {
     )]
     pub name: Option<String>,
 
-    /// Token contract address or shortcut
+    /// Token contract address or registry symbol
     ///
     /// Required when using --query balance
-    /// Can be a full contract address (C...) or a shortcut: xlm, usdc, kale (case-insensitive)
+    /// Can be a full contract address (C...) or a symbol from the token
+    /// registry (built in: xlm, usdc, kale; case-insensitive)
     #[arg(
         short = 't',
         long,
         value_name = "TOKEN",
-        help = "Token contract address or shortcut (xlm, usdc, kale)"
+        help = "Token contract address or registry symbol (e.g. xlm, usdc, kale)"
     )]
     pub token: Option<String>,
 
@@ -129,6 +141,57 @@ pub struct Args {
     )]
     pub asset: Option<String>,
 
+    /// Include the raw XDR of each transaction
+    ///
+    /// Transactions are written as normalized records (ledger, index, hash,
+    /// source, fee source, fee charged, memo, time bounds, operations,
+    /// success and result code) that keep the same shape whatever the
+    /// ledger version. This adds the envelope, result, fee changes and meta
+    /// XDR, as JSON, under `raw`. Applies to transaction queries
+    /// (transactions, address, contract and function).
+    #[arg(long, help = "Include raw envelope, result and meta XDR with each transaction")]
+    pub raw: bool,
+
+    /// Only include transactions that failed
+    ///
+    /// Applies to transaction queries (transactions, address, contract and
+    /// function), and to operations, which keeps the operations of failed
+    /// transactions. Each transaction has a `result_code` such as `tx_failed`
+    /// or `tx_bad_seq`, and each of its operations one such as
+    /// `op_underfunded`.
+    #[arg(long, conflicts_with = "only_successful", help = "Only include failed transactions, or operations of failed transactions")]
+    pub only_failed: bool,
+
+    /// Only include transactions that succeeded
+    ///
+    /// Applies to the same queries as --only-failed.
+    #[arg(long, help = "Only include successful transactions, or operations of successful transactions")]
+    pub only_successful: bool,
+
+    /// Record the progress of a range scan in this file
+    ///
+    /// Each fully processed ledger is appended with its results, so a scan
+    /// that crashes can be continued with --resume instead of starting over.
+    #[arg(long, value_name = "FILE", help = "Checkpoint file for resumable range scans")]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Continue the scan recorded in the --checkpoint file
+    ///
+    /// Results recorded before the crash are written out first, then the
+    /// scan carries on after the last ledger recorded, so the output is the
+    /// same as that of an uninterrupted scan. Ranges relative to the latest
+    /// ledger (--ledger -N) resume the range that was recorded.
+    #[arg(long, requires = "checkpoint", help = "Resume the scan recorded in --checkpoint")]
+    pub resume: bool,
+
+    /// Don't show the progress of range queries on stderr
+    ///
+    /// Progress shows ledgers done, ledgers per second, bytes downloaded,
+    /// matches, missing ledgers and an ETA. It is redrawn in place on a
+    /// terminal and written every 10 seconds otherwise.
+    #[arg(long, help = "Hide range query progress")]
+    pub no_progress: bool,
+
     /// Start API server mode instead of CLI mode
     ///
     /// When enabled, starts an HTTP server that exposes REST API endpoints
@@ -151,16 +214,229 @@ pub struct Args {
         help = "Port number for API server"
     )]
     pub port: u16,
+
+    /// Stellar network profile to query
+    ///
+    /// Each profile carries its own network passphrase, data lake path,
+    /// RPC endpoints, token shortcuts and Reflector oracle addresses.
+    /// 'custom' requires --network-passphrase, --ledgers-path, --rpc-url
+    /// and --soroban-rpc-url (or the equivalent config file settings).
+    /// Defaults to pubnet unless set in the config file or environment.
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_name = "NETWORK",
+        help = "Network profile: 'pubnet', 'testnet', 'futurenet', or 'custom'"
+    )]
+    pub network: Option<Network>,
+
+    /// Path to a TOML config file
+    ///
+    /// Defaults to ~/.config/stellar-data/config.toml (or STELLAR_DATA_CONFIG).
+    /// Settings are layered: built-in defaults, then the config file, then
+    /// STELLAR_DATA_* environment variables, then command line flags.
+    #[arg(long, global = true, value_name = "PATH", help = "Path to a TOML config file")]
+    pub config: Option<PathBuf>,
+
+    /// Override the network passphrase of the selected profile
+    #[arg(long, global = true, value_name = "PASSPHRASE", help = "Network passphrase")]
+    pub network_passphrase: Option<String>,
+
+    /// Override the data lake base URL of the selected profile
+    ///
+    /// Accepts an `https://` URL, an `s3://bucket` URL, a `file://` URL or a
+    /// plain path to a local mirror.
+    #[arg(long, global = true, value_name = "URL", help = "Data lake base URL, s3:// bucket, file:// URL or local path")]
+    pub base_url: Option<String>,
+
+    /// Override the ledgers path within the data lake
+    #[arg(long, global = true, value_name = "PATH", help = "Ledgers path within the data lake")]
+    pub ledgers_path: Option<String>,
+
+    /// Override the archive RPC used for ledgers not yet in the data lake
+    #[arg(long, global = true, value_name = "URL", help = "Archive RPC URL for ledger fallback")]
+    pub rpc_url: Option<String>,
+
+    /// Override the Soroban RPC used for balance and price queries
+    #[arg(long, global = true, value_name = "URL", help = "Soroban RPC URL for contract calls")]
+    pub soroban_rpc_url: Option<String>,
+
+    /// Override the Horizon URL used to find the latest ledger when no RPC answers
+    #[arg(long, global = true, value_name = "URL", help = "Horizon URL for latest ledger fallback (empty to disable)")]
+    pub horizon_url: Option<String>,
+
+    /// S3-compatible endpoint for `s3://` data lake URLs (e.g. a MinIO server)
+    ///
+    /// Credentials are read from the config file ([s3] access_key_id and
+    /// secret_access_key) or STELLAR_DATA_S3_ACCESS_KEY_ID and
+    /// STELLAR_DATA_S3_SECRET_ACCESS_KEY; requests are signed with SigV4.
+    #[arg(long, global = true, value_name = "URL", help = "S3-compatible endpoint for s3:// data lakes")]
+    pub s3_endpoint: Option<String>,
+
+    /// Region used to address and sign S3 requests
+    #[arg(long, global = true, value_name = "REGION", help = "S3 region (default: us-east-1)")]
+    pub s3_region: Option<String>,
+
+    /// Address buckets as {endpoint}/{bucket} instead of {bucket}.{endpoint}
+    #[arg(long, global = true, help = "Use path-style S3 addressing")]
+    pub s3_path_style: bool,
+
+    /// Ledgers fetched in parallel during range queries (default: 4)
+    ///
+    /// Results are still returned in ledger order.
+    #[arg(short = 'j', long, global = true, value_name = "N", help = "Parallel fetches for range queries")]
+    pub concurrency: Option<u32>,
+
+    /// Retries for transient data lake and RPC failures (default: 3)
+    ///
+    /// Timeouts, HTTP 5xx and HTTP 429 responses are retried with exponential
+    /// backoff and jitter; 429 responses wait for their Retry-After header.
+    #[arg(long, global = true, value_name = "N", help = "Retries for transient fetch failures")]
+    pub retries: Option<u32>,
+
+    /// Timeout in seconds for each data lake or RPC request (default: 30)
+    #[arg(long, global = true, value_name = "SECS", help = "Per-request timeout in seconds")]
+    pub timeout: Option<u32>,
+
+    /// Fetch every ledger from its source instead of the on-disk cache
+    #[arg(long, global = true, help = "Don't read or write the ledger cache")]
+    pub no_cache: bool,
+
+    /// Directory of the ledger cache (default: ~/.cache/stellar-data)
+    ///
+    /// Downloaded ledgers are kept here, decompressed, up to the configured
+    /// size cap ([cache] max_size_mb, default 2048); the least recently used
+    /// entries are evicted beyond it.
+    #[arg(long, global = true, value_name = "PATH", help = "Ledger cache directory")]
+    pub cache_dir: Option<String>,
+
+    /// Token registry file with [[token]] entries
+    ///
+    /// Each entry has a symbol, contract, decimals, classic asset (CODE:ISSUER
+    /// or native) and display name. Defaults to ~/.config/stellar-data/tokens.toml.
+    #[arg(long, global = true, value_name = "PATH", help = "Token registry file")]
+    pub token_registry: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands that don't query ledger data
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Inspect the effective configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Inspect the token registry
+    Tokens {
+        #[command(subcommand)]
+        action: TokensAction,
+    },
+    /// Inspect or empty the ledger cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Show the latest ledger of the network and of the data lake
+    Status,
+}
+
+/// Actions for the config subcommand
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Print the effective merged configuration as TOML
+    Show,
+}
+
+/// Actions for the tokens subcommand
+#[derive(Subcommand, Debug)]
+pub enum TokensAction {
+    /// List the tokens in the registry
+    List,
+    /// Check registry entries against the chain
+    Validate {
+        /// Only validate this symbol or contract address
+        token: Option<String>,
+    },
+}
+
+/// Actions for the cache subcommand
+#[derive(Subcommand, Debug)]
+pub enum CacheAction {
+    /// Show the cache location, size and number of entries
+    Stats,
+    /// Delete every cached ledger
+    Clear,
 }
 
 impl Args {
+    /// Build the effective configuration from defaults, the config file,
+    /// the environment and command line flags
+    pub fn config(&self) -> anyhow::Result<Config> {
+        let cli = Overrides {
+            network: self.network,
+            network_passphrase: self.network_passphrase.clone(),
+            base_url: self.base_url.clone(),
+            ledgers_path: self.ledgers_path.clone(),
+            rpc_url: self.rpc_url.clone(),
+            soroban_rpc_url: self.soroban_rpc_url.clone(),
+            horizon_url: self.horizon_url.clone(),
+            token_registry: self.token_registry.clone(),
+            concurrency: self.concurrency,
+            s3: S3Overrides {
+                endpoint: self.s3_endpoint.clone(),
+                region: self.s3_region.clone(),
+                path_style: self.s3_path_style.then_some(true),
+                ..S3Overrides::default()
+            },
+            retry: RetryOverrides {
+                retries: self.retries,
+                timeout_secs: self.timeout,
+                ..RetryOverrides::default()
+            },
+            cache: CacheOverrides {
+                enabled: self.no_cache.then_some(false),
+                dir: self.cache_dir.clone(),
+                ..CacheOverrides::default()
+            },
+            ..Overrides::default()
+        };
+
+        Config::load(self.config.as_deref(), &cli)
+    }
+
+    /// Whether to keep only transactions that succeeded (`Some(true)`) or
+    /// failed (`Some(false)`)
+    pub fn only_success(&self) -> Option<bool> {
+        match (self.only_successful, self.only_failed) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }
+    }
+
     /// Validate arguments based on query type
     pub fn validate(&self) -> anyhow::Result<()> {
-        // In server mode, we don't need to validate query-specific args
-        if self.server {
+        // In server mode or for subcommands, we don't need to validate query-specific args
+        if self.server || self.command.is_some() {
             return Ok(());
         }
 
+        if self.raw && !matches!(self.query.as_str(), "transactions" | "address" | "contract" | "function") {
+            anyhow::bail!("--raw only applies to the transaction queries, not --query {}", self.query);
+        }
+        if self.only_success().is_some()
+            && !matches!(self.query.as_str(), "transactions" | "address" | "contract" | "function" | "operations")
+        {
+            anyhow::bail!(
+                "--only-failed and --only-successful only apply to the transaction and operation queries, not --query {}",
+                self.query
+            );
+        }
+
         match self.query.as_str() {
             "address" | "contract" => {
                 if self.address.is_none() {
@@ -193,14 +469,14 @@ impl Args {
                 }
                 // price doesn't require ledger
             }
-            "all" | "transactions" => {
+            "all" | "transactions" | "operations" => {
                 if self.ledger.is_none() {
                     anyhow::bail!("--ledger is required when using --query {}", self.query);
                 }
             }
             _ => {
                 anyhow::bail!(
-                    "Unsupported query type: {}. Use 'all', 'transactions', 'address', 'contract', 'function', 'balance', or 'price'",
+                    "Unsupported query type: {}. Use 'all', 'transactions', 'address', 'contract', 'function', 'operations', 'balance', or 'price'",
                     self.query
                 );
             }
@@ -208,3 +484,30 @@ impl Args {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(args: &[&str]) -> anyhow::Result<()> {
+        Args::try_parse_from(["stellar-data"].iter().chain(args))?.validate()
+    }
+
+    #[test]
+    fn test_raw_only_applies_to_transaction_queries() {
+        assert!(validate(&["--ledger", "5", "--query", "transactions", "--raw"]).is_ok());
+        assert!(validate(&["--ledger", "5", "--query", "function", "--name", "swap", "--raw"]).is_ok());
+
+        let err = validate(&["--ledger", "5", "--query", "all", "--raw"]).unwrap_err();
+        assert_eq!(err.to_string(), "--raw only applies to the transaction queries, not --query all");
+        assert!(validate(&["--ledger", "5", "--query", "operations", "--raw"]).is_err());
+    }
+
+    #[test]
+    fn test_success_filters_only_apply_to_transaction_and_operation_queries() {
+        assert!(validate(&["--ledger", "5", "--query", "operations", "--only-failed"]).is_ok());
+        assert!(validate(&["--ledger", "5", "--query", "transactions", "--only-successful"]).is_ok());
+        assert!(validate(&["--ledger", "5", "--query", "all", "--only-failed"]).is_err());
+        assert!(validate(&["--query", "balance", "--address", "GABC", "--token", "xlm", "--only-successful"]).is_err());
+    }
+}
@@ -0,0 +1,785 @@
+use serde::{Deserialize, Serialize};
+
+pub mod overrides;
+pub mod tokens;
+
+pub use overrides::Overrides;
+pub use tokens::{TokenInfo, TokenRegistry};
+
+/// Stellar network profile selecting passphrase, data lake and RPC endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Pubnet,
+    Testnet,
+    Futurenet,
+    Custom,
+}
+
+impl Network {
+    /// Lowercase profile name as used on the command line
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Network::Pubnet => "pubnet",
+            Network::Testnet => "testnet",
+            Network::Futurenet => "futurenet",
+            Network::Custom => "custom",
+        }
+    }
+}
+
+/// Reflector oracle contract addresses for a network
+///
+/// A network may have some of the oracles and not others, such as when
+/// only one is configured for a network without built-in oracles.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReflectorContracts {
+    /// Oracle quoting Stellar assets (by contract address)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stellar: Option<String>,
+    /// Oracle quoting external crypto assets (by symbol)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crypto: Option<String>,
+    /// Oracle quoting fiat currencies (by symbol)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fiat: Option<String>,
+}
+
+/// What an RPC endpoint is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RpcRole {
+    /// Ledgers not yet in the data lake, and the latest ledger
+    Archive,
+    /// Contract calls (price queries, balance queries)
+    Soroban,
+    #[default]
+    Both,
+}
+
+impl RpcRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RpcRole::Archive => "archive",
+            RpcRole::Soroban => "soroban",
+            RpcRole::Both => "both",
+        }
+    }
+
+    /// Parse a role name as used in `STELLAR_DATA_RPC_ENDPOINTS`
+    pub fn parse(role: &str) -> Option<Self> {
+        match role.to_lowercase().as_str() {
+            "archive" => Some(RpcRole::Archive),
+            "soroban" => Some(RpcRole::Soroban),
+            "both" => Some(RpcRole::Both),
+            _ => None,
+        }
+    }
+
+    /// Whether an endpoint with this role serves `role`
+    pub fn serves(&self, role: RpcRole) -> bool {
+        *self == RpcRole::Both || *self == role
+    }
+}
+
+/// An RPC endpoint tried after, or instead of, `rpc_url` and `soroban_rpc_url`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RpcEndpoint {
+    pub url: String,
+    #[serde(default)]
+    pub role: RpcRole,
+    /// Endpoints are tried in ascending priority; `rpc_url` and
+    /// `soroban_rpc_url` have priority 0 and go first among equals
+    #[serde(default)]
+    pub priority: u32,
+}
+
+/// Addressing and credentials for S3-compatible data lakes
+///
+/// `s3://bucket` base URLs are resolved against `endpoint` (AWS when unset),
+/// using virtual-hosted or path-style addressing. Data lake requests are
+/// signed with SigV4 whenever an access key is configured.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct S3Settings {
+    pub region: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Address buckets as `{endpoint}/{bucket}` rather than `{bucket}.{endpoint}`
+    pub path_style: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_key_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "redact")]
+    pub secret_access_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "redact")]
+    pub session_token: Option<String>,
+}
+
+impl Default for S3Settings {
+    fn default() -> Self {
+        S3Settings {
+            region: "us-east-1".to_string(),
+            endpoint: None,
+            path_style: false,
+            access_key_id: None,
+            secret_access_key: None,
+            session_token: None,
+        }
+    }
+}
+
+/// Retry, timeout and circuit breaker settings for data lake and RPC fetches
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RetrySettings {
+    /// Attempts after the first for transient failures (timeouts, 5xx, 429)
+    pub retries: u32,
+    /// Backoff before the first retry, doubled for each following one
+    pub base_delay_ms: u32,
+    /// Upper bound for a single backoff delay
+    pub max_delay_ms: u32,
+    /// Timeout for a single request
+    pub timeout_secs: u32,
+    /// Consecutive transient failures that open the circuit breaker (0 disables it)
+    pub circuit_breaker_threshold: u32,
+    /// How long an open circuit breaker fails requests before trying again
+    pub circuit_breaker_cooldown_secs: u32,
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        RetrySettings {
+            retries: 3,
+            base_delay_ms: 250,
+            max_delay_ms: 10_000,
+            timeout_secs: 30,
+            circuit_breaker_threshold: 10,
+            circuit_breaker_cooldown_secs: 30,
+        }
+    }
+}
+
+/// Local cache of decompressed ledger data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CacheSettings {
+    pub enabled: bool,
+    /// Cache directory, `~/.cache/stellar-data` when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    /// Size cap; the least recently used entries are evicted beyond it
+    pub max_size_mb: u32,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        CacheSettings {
+            enabled: true,
+            dir: None,
+            max_size_mb: 2048,
+        }
+    }
+}
+
+impl CacheSettings {
+    /// Cache directory, falling back to `~/.cache/stellar-data`
+    pub fn dir(&self) -> Option<std::path::PathBuf> {
+        match &self.dir {
+            Some(dir) => Some(std::path::PathBuf::from(dir)),
+            None => std::env::var_os("HOME")
+                .map(|home| std::path::PathBuf::from(home).join(".cache").join("stellar-data")),
+        }
+    }
+
+    /// Size cap in bytes
+    pub fn max_bytes(&self) -> u64 {
+        u64::from(self.max_size_mb) * 1024 * 1024
+    }
+}
+
+/// Hide secrets when the configuration is printed
+fn redact<S: serde::Serializer>(_: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("<redacted>")
+}
+
+/// Configuration for Stellar data sources
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    pub network: Network,
+    pub network_passphrase: String,
+    pub ledgers_per_batch: u32,
+    pub batches_per_partition: u32,
+    pub base_url: String,
+    pub ledgers_path: String,
+    /// Archive RPC used as a fallback for ledgers not yet in the data lake
+    pub rpc_url: String,
+    /// Soroban RPC used for contract calls (price queries, balance queries)
+    pub soroban_rpc_url: String,
+    /// More RPC endpoints to fail over to
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rpc_endpoints: Vec<RpcEndpoint>,
+    /// How long an RPC endpoint that failed is skipped before its health is
+    /// checked again, and how often the server checks every endpoint
+    pub rpc_health_check_secs: u32,
+    pub horizon_url: String,
+    /// Number of decimals in Reflector oracle prices
+    pub reflector_decimals: u32,
+    /// Token registry file extending the built-in token shortcuts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_registry: Option<String>,
+    pub reflector: Option<ReflectorContracts>,
+    /// Known tokens used by --token, /balance and price queries
+    pub tokens: TokenRegistry,
+    /// S3 addressing and credentials for private data lakes
+    pub s3: S3Settings,
+    /// Retries and timeouts for data lake and RPC fetches
+    pub retry: RetrySettings,
+    /// On-disk cache of fetched ledgers
+    pub cache: CacheSettings,
+    /// Worker threads fetching ledgers of a range in parallel
+    pub concurrency: u32,
+    /// Ledgers requested per RPC `getLedgers` page
+    pub rpc_page_size: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::for_network(Network::Pubnet)
+    }
+}
+
+impl Config {
+    /// Build the built-in profile for a network
+    ///
+    /// The custom profile only carries the data lake layout; passphrase and
+    /// endpoints must be supplied by the caller before use.
+    pub fn for_network(network: Network) -> Self {
+        let base = Config {
+            network,
+            network_passphrase: String::new(),
+            ledgers_per_batch: 1,
+            batches_per_partition: 64000,
+            base_url: "https://aws-public-blockchain.s3.us-east-2.amazonaws.com".to_string(),
+            ledgers_path: String::new(),
+            rpc_url: String::new(),
+            soroban_rpc_url: String::new(),
+            rpc_endpoints: Vec::new(),
+            rpc_health_check_secs: 60,
+            horizon_url: String::new(),
+            reflector_decimals: 14,
+            token_registry: None,
+            reflector: None,
+            tokens: TokenRegistry::default(),
+            s3: S3Settings::default(),
+            retry: RetrySettings::default(),
+            cache: CacheSettings::default(),
+            concurrency: 4,
+            rpc_page_size: 100,
+        };
+
+        match network {
+            Network::Pubnet => Config {
+                network_passphrase: "Public Global Stellar Network ; September 2015".to_string(),
+                ledgers_path: "v1.1/stellar/ledgers/pubnet".to_string(),
+                rpc_url: "https://archive-rpc.lightsail.network/".to_string(),
+                soroban_rpc_url: "https://rpc.lightsail.network/".to_string(),
+                horizon_url: "https://horizon.stellar.org".to_string(),
+                tokens: [
+                    TokenInfo::classic(
+                        "xlm",
+                        "CAS3J7GYLGXMF6TDJBBYYSE3HQ6BBSMLNUQ34T6TZMYMW2EVH34XOWMA",
+                        "native",
+                        "Stellar Lumens",
+                    ),
+                    TokenInfo::classic(
+                        "usdc",
+                        "CCW67TSZV3SSS2HXMBQ5JFGCKJNXKZM7UQUWUZPUTHXSTZLEO7SJMI75",
+                        "USDC:GA5ZSEJYB37JRC5AVCIA5MOP4RHTM335X2KGX3IHOJAPP5RE34K4KZVN",
+                        "USD Coin",
+                    ),
+                    TokenInfo::classic(
+                        "kale",
+                        "CB23WRDQWGSP6YPMY4UV5C4OW5CBTXKYN3XEATG7KJEZCXMJBYEHOUOV",
+                        "KALE:GBDVX4VELCDSQ54KQJYTNHXAHFLBCA77ZY2USQBM4CSHTTV7DME7KALE",
+                        "Kale",
+                    ),
+                ].into_iter().collect(),
+                reflector: Some(ReflectorContracts {
+                    stellar: Some("CALI2BYU2JE6WVRUFYTS6MSBNEHGJ35P4AVCZYF3B6QOE3QKOB2PLE6M".to_string()),
+                    crypto: Some("CAFJZQWSED6YAWZU3GWRTOCNPPCGBN32L7QV43XX5LZLFTK6JLN34DLN".to_string()),
+                    fiat: Some("CBKGPWGKSKZF52CFHMTRR23TBWTPMRDIYZ4O2P5VS65BMHYH4DXMCJZC".to_string()),
+                }),
+                ..base
+            },
+            Network::Testnet => Config {
+                network_passphrase: "Test SDF Network ; September 2015".to_string(),
+                ledgers_path: "v1.1/stellar/ledgers/testnet".to_string(),
+                rpc_url: "https://soroban-testnet.stellar.org/".to_string(),
+                soroban_rpc_url: "https://soroban-testnet.stellar.org/".to_string(),
+                horizon_url: "https://horizon-testnet.stellar.org".to_string(),
+                tokens: [
+                    TokenInfo::classic(
+                        "xlm",
+                        "CDLZFC3SYJYDZT7K67VZ75HPJVIEUVNIXF47ZG2FB2RMQQVU2HHGCYSC",
+                        "native",
+                        "Stellar Lumens",
+                    ),
+                    TokenInfo::classic(
+                        "usdc",
+                        "CBIELTK6YBZJU5UP2WWQEUCYKLPU6AUNZ2BQ4WWFEIE3USCIHMXQDAMA",
+                        "USDC:GBBD47IF6LWK7P7MDEVSCWR7DPUWV3NY3DTQEVFL4NAT4AQH3ZLLFLA5",
+                        "USD Coin",
+                    ),
+                ].into_iter().collect(),
+                reflector: Some(ReflectorContracts {
+                    stellar: Some("CAVLP5DH2GJPZMVO7IJY4CVOD5MWEFTJFVPD2YY2FQXOQHRGHK4D6HLP".to_string()),
+                    crypto: Some("CCYOZJCOPG34LLQQ7N24YXBM7LL62R7ONMZ3G6WZAAYPB5OYKOMJRN63".to_string()),
+                    fiat: Some("CCSSOHTBL3LEWUCBBEB5NJFC2OKFRC74OWEIJIZLRJBGAAU4VMU5NV4W".to_string()),
+                }),
+                ..base
+            },
+            Network::Futurenet => Config {
+                network_passphrase: "Test SDF Future Network ; October 2022".to_string(),
+                ledgers_path: "v1.1/stellar/ledgers/futurenet".to_string(),
+                rpc_url: "https://rpc-futurenet.stellar.org/".to_string(),
+                soroban_rpc_url: "https://rpc-futurenet.stellar.org/".to_string(),
+                horizon_url: "https://horizon-futurenet.stellar.org".to_string(),
+                tokens: [
+                    TokenInfo::classic(
+                        "xlm",
+                        "CB64D3G7SM2RTH6JSGG34DDTFTQ5CFDKVDZJZSODMCX4NJ2HV2KN7OHT",
+                        "native",
+                        "Stellar Lumens",
+                    ),
+                ].into_iter().collect(),
+                ..base
+            },
+            Network::Custom => base,
+        }
+    }
+
+    /// Check that every setting needed to talk to the network is present
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let required = [
+            (&self.network_passphrase, "network_passphrase", "--network-passphrase"),
+            (&self.base_url, "base_url", "--base-url"),
+            (&self.ledgers_path, "ledgers_path", "--ledgers-path"),
+            (&self.rpc_url, "rpc_url", "--rpc-url"),
+            (&self.soroban_rpc_url, "soroban_rpc_url", "--soroban-rpc-url"),
+        ];
+
+        for (value, key, flag) in required {
+            if value.is_empty() {
+                anyhow::bail!(
+                    "{} is not configured for network {} (set it with {} or in the config file)",
+                    key,
+                    self.network.as_str(),
+                    flag
+                );
+            }
+        }
+
+        if self.ledgers_per_batch == 0 || self.batches_per_partition == 0 {
+            anyhow::bail!("ledgers_per_batch and batches_per_partition must be at least 1");
+        }
+
+        if self.concurrency == 0 {
+            anyhow::bail!("concurrency must be at least 1");
+        }
+
+        if self.rpc_page_size == 0 {
+            anyhow::bail!("rpc_page_size must be at least 1");
+        }
+
+        if self.rpc_health_check_secs == 0 {
+            anyhow::bail!("rpc_health_check_secs must be at least 1");
+        }
+
+        if self.rpc_endpoints.iter().any(|endpoint| endpoint.url.is_empty()) {
+            anyhow::bail!("rpc_endpoints entries need a url");
+        }
+
+        if self.cache.max_size_mb == 0 {
+            anyhow::bail!("cache.max_size_mb must be at least 1");
+        }
+
+        if self.retry.timeout_secs == 0 {
+            anyhow::bail!("retry.timeout_secs must be at least 1");
+        }
+
+        if self.s3.access_key_id.is_some() != self.s3.secret_access_key.is_some() {
+            anyhow::bail!("s3.access_key_id and s3.secret_access_key must be set together");
+        }
+        Ok(())
+    }
+
+    /// Archive RPC endpoints in the order they are tried
+    pub fn archive_rpc_urls(&self) -> Vec<&str> {
+        self.rpc_urls_for(&self.rpc_url, RpcRole::Archive)
+    }
+
+    /// Soroban RPC endpoints in the order they are tried
+    pub fn soroban_rpc_urls(&self) -> Vec<&str> {
+        self.rpc_urls_for(&self.soroban_rpc_url, RpcRole::Soroban)
+    }
+
+    fn rpc_urls_for<'a>(&'a self, primary: &'a str, role: RpcRole) -> Vec<&'a str> {
+        let mut endpoints: Vec<(u32, &str)> = std::iter::once((0, primary))
+            .chain(self.rpc_endpoints.iter()
+                .filter(|endpoint| endpoint.role.serves(role))
+                .map(|endpoint| (endpoint.priority, endpoint.url.as_str())))
+            .filter(|(_, url)| !url.is_empty())
+            .collect();
+        // Stable, so the primary endpoint stays first among equal priorities
+        endpoints.sort_by_key(|(priority, _)| *priority);
+
+        let mut urls: Vec<&str> = Vec::new();
+        for (_, url) in endpoints {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+        urls
+    }
+
+    /// Get the contract of a Reflector oracle (`stellar`, `crypto` or
+    /// `fiat`), failing if the network doesn't have it
+    pub fn reflector(&self, oracle: &str) -> anyhow::Result<&str> {
+        let contract = self.reflector.as_ref().and_then(|reflector| match oracle {
+            "stellar" => reflector.stellar.as_deref(),
+            "crypto" => reflector.crypto.as_deref(),
+            "fiat" => reflector.fiat.as_deref(),
+            _ => None,
+        });
+        contract.ok_or_else(|| {
+            anyhow::anyhow!("No Reflector {} oracle configured for network {}", oracle, self.network.as_str())
+        })
+    }
+
+    /// Resolve a token symbol (case-insensitive) or contract address
+    pub fn resolve_token(&self, token: &str) -> Option<&TokenInfo> {
+        self.tokens.resolve(token)
+    }
+
+    /// First ledger of the batch file holding a ledger
+    pub fn batch_start(&self, ledger_seq: u32) -> u32 {
+        (ledger_seq / self.ledgers_per_batch) * self.ledgers_per_batch
+    }
+
+    /// Generate the S3 URL for a given ledger sequence number
+    ///
+    /// Follows the Galexie datastore layout: batches of `ledgers_per_batch`
+    /// ledgers, grouped into partitions of `batches_per_partition` batches.
+    pub fn generate_url(&self, ledger_seq: u32) -> String {
+        let batch_start = self.batch_start(ledger_seq);
+        let batch_end = batch_start + self.ledgers_per_batch - 1;
+
+        let batch_key = if self.ledgers_per_batch == 1 {
+            format!("{:08X}--{}.xdr.zst", u32::MAX - batch_start, batch_start)
+        } else {
+            format!(
+                "{:08X}--{}-{}.xdr.zst",
+                u32::MAX - batch_start,
+                batch_start,
+                batch_end
+            )
+        };
+
+        // Layouts with one batch per partition have no partition directory
+        if self.batches_per_partition <= 1 {
+            return format!("{}/{}/{}", self.base_url, self.ledgers_path, batch_key);
+        }
+
+        // Calculate partition boundaries
+        let partition_size = self.ledgers_per_batch * self.batches_per_partition;
+        let partition_start = (ledger_seq / partition_size) * partition_size;
+        let partition_end = partition_start + partition_size - 1;
+
+        let partition_key = format!(
+            "{:08X}--{}-{}",
+            u32::MAX - partition_start,
+            partition_start,
+            partition_end
+        );
+
+        format!(
+            "{}/{}/{}/{}",
+            self.base_url, self.ledgers_path, partition_key, batch_key
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config() {
+        let config = Config::default();
+        assert_eq!(config.network_passphrase, "Public Global Stellar Network ; September 2015");
+        assert_eq!(config.ledgers_per_batch, 1);
+        assert_eq!(config.batches_per_partition, 64000);
+        assert_eq!(config.base_url, "https://aws-public-blockchain.s3.us-east-2.amazonaws.com");
+        assert_eq!(config.ledgers_path, "v1.1/stellar/ledgers/pubnet");
+        assert_eq!(config.network, Network::Pubnet);
+    }
+
+    #[test]
+    fn test_testnet_profile() {
+        let config = Config::for_network(Network::Testnet);
+        assert_eq!(config.network_passphrase, "Test SDF Network ; September 2015");
+        assert_eq!(config.ledgers_path, "v1.1/stellar/ledgers/testnet");
+        assert_eq!(config.rpc_url, "https://soroban-testnet.stellar.org/");
+        assert_eq!(
+            config.resolve_token("xlm").map(|t| t.contract.as_str()),
+            Some("CDLZFC3SYJYDZT7K67VZ75HPJVIEUVNIXF47ZG2FB2RMQQVU2HHGCYSC")
+        );
+        assert!(config.reflector("fiat").is_ok());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_futurenet_profile_has_no_reflector() {
+        let config = Config::for_network(Network::Futurenet);
+        assert_eq!(config.network_passphrase, "Test SDF Future Network ; October 2022");
+        assert!(config.resolve_token("usdc").is_none());
+        assert_eq!(
+            config.reflector("crypto").unwrap_err().to_string(),
+            "No Reflector crypto oracle configured for network futurenet"
+        );
+    }
+
+    #[test]
+    fn test_custom_profile_requires_passphrase() {
+        let config = Config::for_network(Network::Custom);
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("--network-passphrase"));
+    }
+
+    #[test]
+    fn test_custom_profile_validates_when_filled_in() {
+        let config = Config {
+            network_passphrase: "Standalone Network ; February 2017".to_string(),
+            ledgers_path: "ledgers".to_string(),
+            rpc_url: "http://localhost:8000/rpc".to_string(),
+            soroban_rpc_url: "http://localhost:8000/rpc".to_string(),
+            ..Config::for_network(Network::Custom)
+        };
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_s3_credentials_must_be_paired() {
+        let mut config = Config::default();
+        config.s3.access_key_id = Some("minioadmin".to_string());
+        assert!(config.validate().unwrap_err().to_string().contains("secret_access_key"));
+
+        config.s3.secret_access_key = Some("minioadmin".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_s3_secrets_are_redacted() {
+        let mut config = Config::default();
+        config.s3.access_key_id = Some("minioadmin".to_string());
+        config.s3.secret_access_key = Some("supersecret".to_string());
+
+        let text = toml::to_string_pretty(&config).unwrap();
+        assert!(text.contains("access_key_id = \"minioadmin\""));
+        assert!(text.contains("secret_access_key = \"<redacted>\""));
+        assert!(!text.contains("supersecret"));
+    }
+
+    #[test]
+    fn test_rpc_endpoint_order() {
+        let config = Config {
+            rpc_endpoints: vec![
+                RpcEndpoint { url: "https://backup.example.com/".to_string(), role: RpcRole::Both, priority: 2 },
+                RpcEndpoint { url: "https://archive.example.com/".to_string(), role: RpcRole::Archive, priority: 1 },
+                RpcEndpoint { url: "https://rpc.lightsail.network/".to_string(), role: RpcRole::Soroban, priority: 0 },
+            ],
+            ..Config::default()
+        };
+
+        assert_eq!(config.archive_rpc_urls(), vec![
+            "https://archive-rpc.lightsail.network/",
+            "https://archive.example.com/",
+            "https://backup.example.com/",
+        ]);
+        // The primary Soroban RPC is listed again, but only tried once
+        assert_eq!(config.soroban_rpc_urls(), vec![
+            "https://rpc.lightsail.network/",
+            "https://backup.example.com/",
+        ]);
+
+        // rpc_endpoints is serialized after every plain value
+        assert!(toml::to_string_pretty(&config).unwrap().contains("[[rpc_endpoints]]"));
+    }
+
+    #[test]
+    fn test_resolve_token_xlm() {
+        let config = Config::default();
+        let result = config.resolve_token("xlm");
+        assert_eq!(result.map(|t| t.contract.as_str()), Some("CAS3J7GYLGXMF6TDJBBYYSE3HQ6BBSMLNUQ34T6TZMYMW2EVH34XOWMA"));
+    }
+
+    #[test]
+    fn test_resolve_token_xlm_uppercase() {
+        let config = Config::default();
+        let result = config.resolve_token("XLM");
+        assert_eq!(result.map(|t| t.contract.as_str()), Some("CAS3J7GYLGXMF6TDJBBYYSE3HQ6BBSMLNUQ34T6TZMYMW2EVH34XOWMA"));
+    }
+
+    #[test]
+    fn test_resolve_token_usdc() {
+        let config = Config::default();
+        let result = config.resolve_token("usdc");
+        assert_eq!(result.map(|t| t.contract.as_str()), Some("CCW67TSZV3SSS2HXMBQ5JFGCKJNXKZM7UQUWUZPUTHXSTZLEO7SJMI75"));
+    }
+
+    #[test]
+    fn test_resolve_token_unknown() {
+        let config = Config::default();
+        let result = config.resolve_token("unknown_token");
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_builtin_tokens_match_derived_asset_contracts() {
+        for network in [Network::Pubnet, Network::Testnet, Network::Futurenet] {
+            let config = Config::for_network(network);
+            for token in config.tokens.iter() {
+                let classic = token.classic.as_deref().unwrap();
+                let derived = tokens::stellar_asset_contract(classic, &config.network_passphrase).unwrap();
+                assert_eq!(derived, token.contract, "{} on {}", token.symbol, network.as_str());
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate_url_ledger_0() {
+        let config = Config::default();
+        let url = config.generate_url(0);
+        assert_eq!(
+            url,
+            "https://aws-public-blockchain.s3.us-east-2.amazonaws.com/v1.1/stellar/ledgers/pubnet/FFFFFFFF--0-63999/FFFFFFFF--0.xdr.zst"
+        );
+    }
+
+    #[test]
+    fn test_generate_url_ledger_63864() {
+        let config = Config::default();
+        let url = config.generate_url(63864);
+        // Partition: 0-63999
+        // Batch: 63864
+        // Hex for 63864: F978
+        // Inverted: FFFF0687
+        assert_eq!(
+            url,
+            "https://aws-public-blockchain.s3.us-east-2.amazonaws.com/v1.1/stellar/ledgers/pubnet/FFFFFFFF--0-63999/FFFF0687--63864.xdr.zst"
+        );
+    }
+
+    #[test]
+    fn test_generate_url_ledger_64000() {
+        let config = Config::default();
+        let url = config.generate_url(64000);
+        // Partition: 64000-127999
+        // u32::MAX - 64000 = 4294903295 = 0xFFFF05FF
+        assert_eq!(
+            url,
+            "https://aws-public-blockchain.s3.us-east-2.amazonaws.com/v1.1/stellar/ledgers/pubnet/FFFF05FF--64000-127999/FFFF05FF--64000.xdr.zst"
+        );
+    }
+
+    #[test]
+    fn test_generate_url_ledger_50000000() {
+        let config = Config::default();
+        let url = config.generate_url(50000000);
+        // Partition: (50000000 / 64000) * 64000 = 781 * 64000 = 49984000
+        // Partition end: 49984000 + 63999 = 50047999
+        // u32::MAX - 49984000 = 4244983295 = 0xFD054DFF
+        // u32::MAX - 50000000 = 4244967295 = 0xFD050F7F
+        assert_eq!(
+            url,
+            "https://aws-public-blockchain.s3.us-east-2.amazonaws.com/v1.1/stellar/ledgers/pubnet/FD054DFF--49984000-50047999/FD050F7F--50000000.xdr.zst"
+        );
+    }
+
+    #[test]
+    fn test_generate_url_partition_boundaries() {
+        let config = Config::default();
+
+        // Test first ledger of second partition
+        let url = config.generate_url(64000);
+        assert!(url.contains("64000-127999"));
+        assert!(url.contains("64000.xdr.zst"));
+
+        // Test last ledger of first partition
+        let url = config.generate_url(63999);
+        assert!(url.contains("0-63999"));
+        assert!(url.contains("63999.xdr.zst"));
+    }
+
+    #[test]
+    fn test_generate_url_custom_config() {
+        let config = Config {
+            network_passphrase: "Test Network".to_string(),
+            ledgers_per_batch: 10,
+            batches_per_partition: 1000,
+            base_url: "https://test.example.com".to_string(),
+            ledgers_path: "test/path".to_string(),
+            ..Config::for_network(Network::Custom)
+        };
+
+        let url = config.generate_url(100);
+        // Partition: 0-999 (batches) but ledgers_per_batch = 10
+        // So partition 0 covers ledgers 0-9999
+        // Batch 100 covers ledgers 100-109
+        assert!(url.starts_with("https://test.example.com/test/path/"));
+        assert!(url.contains("100-109.xdr.zst"));
+    }
+
+    #[test]
+    fn test_generate_url_multi_ledger_batch() {
+        let config = Config {
+            ledgers_per_batch: 64,
+            batches_per_partition: 1000,
+            ..Config::default()
+        };
+
+        // Ledger 100 sits in batch 64-127 of partition 0-63999
+        assert_eq!(config.batch_start(100), 64);
+        assert!(config.generate_url(100).ends_with("/FFFFFFFF--0-63999/FFFFFFBF--64-127.xdr.zst"));
+        assert_eq!(config.generate_url(64), config.generate_url(127));
+
+        // Partitions span ledgers_per_batch * batches_per_partition ledgers
+        assert!(config.generate_url(64000).contains("/FFFF05FF--64000-127999/"));
+    }
+
+    #[test]
+    fn test_generate_url_without_partitions() {
+        let config = Config {
+            ledgers_per_batch: 8,
+            batches_per_partition: 1,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            config.generate_url(10),
+            "https://aws-public-blockchain.s3.us-east-2.amazonaws.com/v1.1/stellar/ledgers/pubnet/FFFFFFF7--8-15.xdr.zst"
+        );
+    }
+
+    #[test]
+    fn test_generate_url_hex_inversion() {
+        let config = Config::default();
+
+        // Test that hex values are properly inverted
+        // For ledger 0: u32::MAX - 0 = 0xFFFFFFFF
+        let url = config.generate_url(0);
+        assert!(url.contains("FFFFFFFF--0.xdr.zst"));
+
+        // For ledger 1: u32::MAX - 1 = 0xFFFFFFFE
+        let url = config.generate_url(1);
+        assert!(url.contains("FFFFFFFE--1.xdr.zst"));
+    }
+}
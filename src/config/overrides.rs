@@ -0,0 +1,692 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use super::tokens::{default_registry_path, TokenEntry};
+use super::{Config, Network, ReflectorContracts, RpcEndpoint, RpcRole};
+
+/// Prefix for environment variables that override configuration values
+pub const ENV_PREFIX: &str = "STELLAR_DATA_";
+
+/// A partial configuration layer
+///
+/// Built-in network defaults are overlaid with the config file, then
+/// `STELLAR_DATA_*` environment variables, then command line flags. Only the
+/// values a layer sets replace the ones beneath it.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Overrides {
+    pub network: Option<Network>,
+    pub network_passphrase: Option<String>,
+    pub base_url: Option<String>,
+    pub ledgers_path: Option<String>,
+    pub ledgers_per_batch: Option<u32>,
+    pub batches_per_partition: Option<u32>,
+    pub rpc_url: Option<String>,
+    pub soroban_rpc_url: Option<String>,
+    /// Replaces the RPC endpoints of the layers beneath
+    pub rpc_endpoints: Option<Vec<RpcEndpoint>>,
+    pub rpc_health_check_secs: Option<u32>,
+    pub horizon_url: Option<String>,
+    pub reflector_decimals: Option<u32>,
+    pub concurrency: Option<u32>,
+    pub rpc_page_size: Option<u32>,
+    pub token_registry: Option<String>,
+    #[serde(default)]
+    pub reflector: ReflectorOverrides,
+    #[serde(default)]
+    pub s3: S3Overrides,
+    #[serde(default)]
+    pub retry: RetryOverrides,
+    #[serde(default)]
+    pub cache: CacheOverrides,
+    /// Tokens to add or replace, keyed by symbol
+    #[serde(default)]
+    pub tokens: BTreeMap<String, TokenEntry>,
+}
+
+/// Reflector oracle addresses set by a configuration layer
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReflectorOverrides {
+    pub stellar: Option<String>,
+    pub crypto: Option<String>,
+    pub fiat: Option<String>,
+}
+
+/// S3 data lake settings set by a configuration layer
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct S3Overrides {
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
+    pub path_style: Option<bool>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+}
+
+/// Retry settings set by a configuration layer
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryOverrides {
+    pub retries: Option<u32>,
+    pub base_delay_ms: Option<u32>,
+    pub max_delay_ms: Option<u32>,
+    pub timeout_secs: Option<u32>,
+    pub circuit_breaker_threshold: Option<u32>,
+    pub circuit_breaker_cooldown_secs: Option<u32>,
+}
+
+/// Ledger cache settings set by a configuration layer
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheOverrides {
+    pub enabled: Option<bool>,
+    pub dir: Option<String>,
+    pub max_size_mb: Option<u32>,
+}
+
+/// Contents of `config.toml`
+///
+/// Top-level keys apply to every network; `[networks.<name>]` tables apply
+/// only when that network is selected and take precedence over the top level.
+#[derive(Debug, Default)]
+pub struct ConfigFile {
+    pub defaults: Overrides,
+    pub networks: BTreeMap<Network, Overrides>,
+}
+
+impl ConfigFile {
+    /// Parse a config file from TOML text
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut table: toml::Table = toml::from_str(text).context("Failed to parse config file")?;
+
+        // Split off the per-network sections so unknown keys are still rejected
+        let networks = match table.remove("networks") {
+            Some(sections) => sections.try_into().context("Invalid [networks] section")?,
+            None => BTreeMap::new(),
+        };
+        let defaults = table.try_into().context("Invalid config file")?;
+
+        Ok(ConfigFile { defaults, networks })
+    }
+
+    /// Read a config file from disk
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+}
+
+/// Default config file location: `~/.config/stellar-data/config.toml`
+pub fn default_config_path() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join(".config").join("stellar-data").join("config.toml"))
+}
+
+impl Overrides {
+    /// Read overrides from `STELLAR_DATA_*` variables using the given lookup
+    pub fn from_env(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let var = |name: &str| lookup(&format!("{}{}", ENV_PREFIX, name)).filter(|v| !v.is_empty());
+
+        let network = var("NETWORK")
+            .map(|value| {
+                <Network as clap::ValueEnum>::from_str(&value, true)
+                    .map_err(|_| anyhow::anyhow!("Invalid {}NETWORK: {}", ENV_PREFIX, value))
+            })
+            .transpose()?;
+
+        let number = |name: &str| {
+            var(name)
+                .map(|value| {
+                    value.parse::<u32>()
+                        .with_context(|| format!("Invalid {}{}: {}", ENV_PREFIX, name, value))
+                })
+                .transpose()
+        };
+
+        let flag = |name: &str| {
+            var(name)
+                .map(|value| match value.to_lowercase().as_str() {
+                    "1" | "true" | "yes" => Ok(true),
+                    "0" | "false" | "no" => Ok(false),
+                    _ => Err(anyhow::anyhow!("Invalid {}{}: {}", ENV_PREFIX, name, value)),
+                })
+                .transpose()
+        };
+
+        // Tokens are given as a comma separated list of symbol=contract pairs
+        let mut tokens = BTreeMap::new();
+        if let Some(list) = var("TOKENS") {
+            for pair in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                let (symbol, contract) = pair.split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("Invalid {}TOKENS entry '{}', expected symbol=contract", ENV_PREFIX, pair))?;
+                tokens.insert(symbol.trim().to_string(), TokenEntry::Contract(contract.trim().to_string()));
+            }
+        }
+
+        // RPC endpoints are given as a comma separated list of URLs, each
+        // optionally prefixed with its role (archive=, soroban= or both=)
+        let rpc_endpoints = var("RPC_ENDPOINTS").map(|list| {
+            list.split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(|entry| {
+                    let (role, url) = entry.split_once('=')
+                        .and_then(|(role, url)| Some((RpcRole::parse(role.trim())?, url.trim())))
+                        .unwrap_or((RpcRole::Both, entry));
+                    RpcEndpoint { url: url.to_string(), role, priority: 0 }
+                })
+                .collect()
+        });
+
+        Ok(Overrides {
+            network,
+            network_passphrase: var("NETWORK_PASSPHRASE"),
+            base_url: var("BASE_URL"),
+            ledgers_path: var("LEDGERS_PATH"),
+            ledgers_per_batch: number("LEDGERS_PER_BATCH")?,
+            batches_per_partition: number("BATCHES_PER_PARTITION")?,
+            rpc_url: var("RPC_URL"),
+            soroban_rpc_url: var("SOROBAN_RPC_URL"),
+            rpc_endpoints,
+            rpc_health_check_secs: number("RPC_HEALTH_CHECK_SECS")?,
+            horizon_url: var("HORIZON_URL"),
+            reflector_decimals: number("REFLECTOR_DECIMALS")?,
+            concurrency: number("CONCURRENCY")?,
+            rpc_page_size: number("RPC_PAGE_SIZE")?,
+            token_registry: var("TOKEN_REGISTRY"),
+            reflector: ReflectorOverrides {
+                stellar: var("REFLECTOR_STELLAR"),
+                crypto: var("REFLECTOR_CRYPTO"),
+                fiat: var("REFLECTOR_FIAT"),
+            },
+            s3: S3Overrides {
+                region: var("S3_REGION"),
+                endpoint: var("S3_ENDPOINT"),
+                path_style: flag("S3_PATH_STYLE")?,
+                access_key_id: var("S3_ACCESS_KEY_ID"),
+                secret_access_key: var("S3_SECRET_ACCESS_KEY"),
+                session_token: var("S3_SESSION_TOKEN"),
+            },
+            retry: RetryOverrides {
+                retries: number("RETRIES")?,
+                base_delay_ms: number("RETRY_BASE_DELAY_MS")?,
+                max_delay_ms: number("RETRY_MAX_DELAY_MS")?,
+                timeout_secs: number("TIMEOUT_SECS")?,
+                circuit_breaker_threshold: number("CIRCUIT_BREAKER_THRESHOLD")?,
+                circuit_breaker_cooldown_secs: number("CIRCUIT_BREAKER_COOLDOWN_SECS")?,
+            },
+            cache: CacheOverrides {
+                enabled: flag("CACHE_ENABLED")?,
+                dir: var("CACHE_DIR"),
+                max_size_mb: number("CACHE_MAX_SIZE_MB")?,
+            },
+            tokens,
+        })
+    }
+}
+
+impl Config {
+    /// Overlay the values set in a configuration layer
+    pub fn apply(&mut self, layer: &Overrides) {
+        let strings = [
+            (&layer.network_passphrase, &mut self.network_passphrase),
+            (&layer.base_url, &mut self.base_url),
+            (&layer.ledgers_path, &mut self.ledgers_path),
+            (&layer.rpc_url, &mut self.rpc_url),
+            (&layer.soroban_rpc_url, &mut self.soroban_rpc_url),
+            (&layer.horizon_url, &mut self.horizon_url),
+        ];
+
+        for (value, field) in strings {
+            if let Some(value) = value {
+                *field = value.clone();
+            }
+        }
+
+        let retry = &layer.retry;
+        let numbers = [
+            (layer.ledgers_per_batch, &mut self.ledgers_per_batch),
+            (layer.batches_per_partition, &mut self.batches_per_partition),
+            (layer.reflector_decimals, &mut self.reflector_decimals),
+            (layer.concurrency, &mut self.concurrency),
+            (layer.rpc_page_size, &mut self.rpc_page_size),
+            (layer.rpc_health_check_secs, &mut self.rpc_health_check_secs),
+            (retry.retries, &mut self.retry.retries),
+            (retry.base_delay_ms, &mut self.retry.base_delay_ms),
+            (retry.max_delay_ms, &mut self.retry.max_delay_ms),
+            (retry.timeout_secs, &mut self.retry.timeout_secs),
+            (retry.circuit_breaker_threshold, &mut self.retry.circuit_breaker_threshold),
+            (retry.circuit_breaker_cooldown_secs, &mut self.retry.circuit_breaker_cooldown_secs),
+            (layer.cache.max_size_mb, &mut self.cache.max_size_mb),
+        ];
+
+        for (value, field) in numbers {
+            if let Some(value) = value {
+                *field = value;
+            }
+        }
+
+        let reflector = &layer.reflector;
+        if reflector.stellar.is_some() || reflector.crypto.is_some() || reflector.fiat.is_some() {
+            let current = self.reflector.take().unwrap_or(ReflectorContracts {
+                stellar: None,
+                crypto: None,
+                fiat: None,
+            });
+            self.reflector = Some(ReflectorContracts {
+                stellar: reflector.stellar.clone().or(current.stellar),
+                crypto: reflector.crypto.clone().or(current.crypto),
+                fiat: reflector.fiat.clone().or(current.fiat),
+            });
+        }
+
+        if let Some(endpoints) = &layer.rpc_endpoints {
+            self.rpc_endpoints.clone_from(endpoints);
+        }
+
+        if let Some(path) = &layer.token_registry {
+            self.token_registry = Some(path.clone());
+        }
+
+        if let Some(enabled) = layer.cache.enabled {
+            self.cache.enabled = enabled;
+        }
+        if layer.cache.dir.is_some() {
+            self.cache.dir.clone_from(&layer.cache.dir);
+        }
+
+        let s3 = &layer.s3;
+        if let Some(region) = &s3.region {
+            self.s3.region = region.clone();
+        }
+        if let Some(path_style) = s3.path_style {
+            self.s3.path_style = path_style;
+        }
+        let optional = [
+            (&s3.endpoint, &mut self.s3.endpoint),
+            (&s3.access_key_id, &mut self.s3.access_key_id),
+            (&s3.secret_access_key, &mut self.s3.secret_access_key),
+            (&s3.session_token, &mut self.s3.session_token),
+        ];
+
+        for (value, field) in optional {
+            if value.is_some() {
+                field.clone_from(value);
+            }
+        }
+    }
+
+    /// Add the tokens set in a configuration layer to the registry
+    ///
+    /// Classic-only entries derive their contract address from the network
+    /// passphrase, so tokens are merged once every other setting is known.
+    pub fn apply_tokens(&mut self, layer: &Overrides) -> Result<()> {
+        for (symbol, entry) in &layer.tokens {
+            self.tokens.insert_entry(symbol, entry, &self.network_passphrase)?;
+        }
+        Ok(())
+    }
+
+    /// Merge configuration layers into the effective configuration
+    ///
+    /// The network is chosen by the highest layer that names one, defaulting
+    /// to pubnet, and its built-in profile is the base every layer overlays.
+    pub fn layered(file: &ConfigFile, env: &Overrides, cli: &Overrides) -> Result<Self> {
+        let network = cli.network
+            .or(env.network)
+            .or(file.defaults.network)
+            .unwrap_or(Network::Pubnet);
+
+        let layers: Vec<&Overrides> = [Some(&file.defaults), file.networks.get(&network), Some(env), Some(cli)]
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let mut config = Config::for_network(network);
+        for layer in &layers {
+            config.apply(layer);
+        }
+        for layer in &layers {
+            config.apply_tokens(layer)?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Load the effective configuration from defaults, the config file,
+    /// the environment and command line overrides
+    ///
+    /// An explicit `config_path` (or `STELLAR_DATA_CONFIG`) must exist; the
+    /// default location is optional.
+    pub fn load(config_path: Option<&Path>, cli: &Overrides) -> Result<Self> {
+        let explicit = config_path
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::var_os(format!("{}CONFIG", ENV_PREFIX)).map(PathBuf::from));
+
+        let file = match explicit {
+            Some(path) => ConfigFile::read(&path)?,
+            None => match default_config_path() {
+                Some(path) if path.exists() => ConfigFile::read(&path)?,
+                _ => ConfigFile::default(),
+            },
+        };
+
+        let env = Overrides::from_env(|name| std::env::var(name).ok())?;
+
+        let mut config = Self::layered(&file, &env, cli)?;
+
+        // The token registry file extends and replaces the configured tokens
+        let registry = match &config.token_registry {
+            Some(path) => Some(PathBuf::from(path)),
+            None => default_registry_path().filter(|path| path.exists()),
+        };
+        if let Some(path) = registry {
+            let passphrase = config.network_passphrase.clone();
+            config.tokens.load_file(&path, &passphrase)?;
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> Overrides {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Overrides::from_env(|name| vars.get(name).cloned()).unwrap()
+    }
+
+    #[test]
+    fn test_defaults_without_layers() {
+        let config = Config::layered(&ConfigFile::default(), &Overrides::default(), &Overrides::default()).unwrap();
+        assert_eq!(config.network, Network::Pubnet);
+        assert_eq!(config.rpc_url, "https://archive-rpc.lightsail.network/");
+    }
+
+    #[test]
+    fn test_parse_config_file() {
+        let file = ConfigFile::parse(r#"
+            network = "testnet"
+            rpc_url = "https://rpc.example.com/"
+            reflector_decimals = 8
+
+            [tokens]
+            eurc = "CDTKPWPLOURQA2SGTKTUQOWRCBZEORB4BWBOMJ3D3ZTQQSGE5F6JBQLV"
+
+            [reflector]
+            crypto = "CAFJZQWSED6YAWZU3GWRTOCNPPCGBN32L7QV43XX5LZLFTK6JLN34DLN"
+
+            [networks.testnet]
+            horizon_url = "https://horizon.example.com"
+        "#).unwrap();
+
+        assert_eq!(file.defaults.network, Some(Network::Testnet));
+        assert_eq!(file.defaults.reflector_decimals, Some(8));
+        assert_eq!(file.defaults.tokens.len(), 1);
+        assert!(file.networks.contains_key(&Network::Testnet));
+
+        let config = Config::layered(&file, &Overrides::default(), &Overrides::default()).unwrap();
+        assert_eq!(config.network, Network::Testnet);
+        assert_eq!(config.rpc_url, "https://rpc.example.com/");
+        assert_eq!(config.horizon_url, "https://horizon.example.com");
+        assert_eq!(config.reflector_decimals, 8);
+        assert_eq!(config.reflector("crypto").unwrap(), "CAFJZQWSED6YAWZU3GWRTOCNPPCGBN32L7QV43XX5LZLFTK6JLN34DLN");
+        // Testnet profile values not overridden are kept
+        assert_eq!(config.network_passphrase, "Test SDF Network ; September 2015");
+        assert!(config.resolve_token("eurc").is_some());
+        assert!(config.resolve_token("xlm").is_some());
+    }
+
+    #[test]
+    fn test_config_file_token_details() {
+        let file = ConfigFile::parse(r#"
+            network = "custom"
+            network_passphrase = "Standalone Network ; February 2017"
+            ledgers_path = "ledgers"
+            rpc_url = "http://localhost:8000/rpc"
+            soroban_rpc_url = "http://localhost:8000/rpc"
+
+            [tokens.xlm]
+            classic = "native"
+            name = "Lumens"
+        "#).unwrap();
+
+        let config = Config::layered(&file, &Overrides::default(), &Overrides::default()).unwrap();
+        let xlm = config.resolve_token("xlm").unwrap();
+        // Contract derived from the custom network passphrase
+        assert_eq!(xlm.contract, "CDMLFMKMMD7MWZP3FKUBZPVHTUEDLSX4BYGYKH4GCESXYHS3IHQ4EIG4");
+        assert_eq!(xlm.name.as_deref(), Some("Lumens"));
+    }
+
+    #[test]
+    fn test_parse_config_file_rejects_unknown_keys() {
+        assert!(ConfigFile::parse("rpc_ulr = \"https://typo.example.com\"").is_err());
+    }
+
+    #[test]
+    fn test_network_section_only_applies_to_selected_network() {
+        let file = ConfigFile::parse(r#"
+            [networks.testnet]
+            rpc_url = "https://testnet-rpc.example.com/"
+        "#).unwrap();
+
+        let config = Config::layered(&file, &Overrides::default(), &Overrides::default()).unwrap();
+        assert_eq!(config.rpc_url, "https://archive-rpc.lightsail.network/");
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let overrides = env(&[
+            ("STELLAR_DATA_NETWORK", "Futurenet"),
+            ("STELLAR_DATA_SOROBAN_RPC_URL", "https://soroban.example.com/"),
+            ("STELLAR_DATA_REFLECTOR_DECIMALS", "7"),
+            ("STELLAR_DATA_LEDGERS_PER_BATCH", "64"),
+            ("STELLAR_DATA_TOKENS", "aqua=CAUIKL3IYGMERDRUN6YSCLWVAKIFG5Q4YJHUKM4S4NJZQIA3BAS6OJPK, yusdc = CDOFW7HNKLUZRLFZST4EW7V3AV4JI5IHMT6BPXXSY2IEFZ4NE5TWU2P4"),
+            ("STELLAR_DATA_HORIZON_URL", ""),
+        ]);
+
+        assert_eq!(overrides.network, Some(Network::Futurenet));
+        assert_eq!(overrides.reflector_decimals, Some(7));
+        assert_eq!(overrides.ledgers_per_batch, Some(64));
+        assert_eq!(overrides.tokens.len(), 2);
+        assert!(matches!(
+            &overrides.tokens["yusdc"],
+            TokenEntry::Contract(contract) if contract == "CDOFW7HNKLUZRLFZST4EW7V3AV4JI5IHMT6BPXXSY2IEFZ4NE5TWU2P4"
+        ));
+        // Empty variables are ignored
+        assert!(overrides.horizon_url.is_none());
+    }
+
+    #[test]
+    fn test_env_invalid_values_fail() {
+        let result = Overrides::from_env(|name| {
+            (name == "STELLAR_DATA_NETWORK").then(|| "mainnet".to_string())
+        });
+        assert!(result.unwrap_err().to_string().contains("STELLAR_DATA_NETWORK"));
+
+        let result = Overrides::from_env(|name| {
+            (name == "STELLAR_DATA_TOKENS").then(|| "xlm".to_string())
+        });
+        assert!(result.unwrap_err().to_string().contains("symbol=contract"));
+    }
+
+    #[test]
+    fn test_layer_precedence() {
+        let file = ConfigFile::parse(r#"
+            network = "testnet"
+            rpc_url = "https://file.example.com/"
+            soroban_rpc_url = "https://file.example.com/"
+            horizon_url = "https://file.example.com"
+        "#).unwrap();
+        let env = env(&[
+            ("STELLAR_DATA_RPC_URL", "https://env.example.com/"),
+            ("STELLAR_DATA_SOROBAN_RPC_URL", "https://env.example.com/"),
+        ]);
+        let cli = Overrides {
+            rpc_url: Some("https://cli.example.com/".to_string()),
+            ..Overrides::default()
+        };
+
+        let config = Config::layered(&file, &env, &cli).unwrap();
+        assert_eq!(config.rpc_url, "https://cli.example.com/");
+        assert_eq!(config.soroban_rpc_url, "https://env.example.com/");
+        assert_eq!(config.horizon_url, "https://file.example.com");
+    }
+
+    #[test]
+    fn test_rpc_endpoints_from_file_and_env() {
+        let file = ConfigFile::parse(r#"
+            rpc_health_check_secs = 10
+
+            [[rpc_endpoints]]
+            url = "https://archive.example.com/"
+            role = "archive"
+            priority = 1
+
+            [[rpc_endpoints]]
+            url = "https://rpc.example.com/"
+        "#).unwrap();
+
+        let config = Config::layered(&file, &Overrides::default(), &Overrides::default()).unwrap();
+        assert_eq!(config.rpc_health_check_secs, 10);
+        assert_eq!(config.rpc_endpoints.len(), 2);
+        assert_eq!(config.rpc_endpoints[1].role, RpcRole::Both);
+        assert_eq!(config.archive_rpc_urls(), vec![
+            "https://archive-rpc.lightsail.network/",
+            "https://rpc.example.com/",
+            "https://archive.example.com/",
+        ]);
+
+        // The environment replaces the list from the file
+        let env = env(&[("STELLAR_DATA_RPC_ENDPOINTS", "soroban=https://soroban.example.com/, https://any.example.com/?a=b")]);
+        let config = Config::layered(&file, &env, &Overrides::default()).unwrap();
+        assert_eq!(config.rpc_endpoints, vec![
+            RpcEndpoint { url: "https://soroban.example.com/".to_string(), role: RpcRole::Soroban, priority: 0 },
+            RpcEndpoint { url: "https://any.example.com/?a=b".to_string(), role: RpcRole::Both, priority: 0 },
+        ]);
+        assert_eq!(config.archive_rpc_urls(), vec!["https://archive-rpc.lightsail.network/", "https://any.example.com/?a=b"]);
+    }
+
+    #[test]
+    fn test_cli_network_beats_env_and_file() {
+        let file = ConfigFile::parse("network = \"testnet\"").unwrap();
+        let env = env(&[("STELLAR_DATA_NETWORK", "futurenet")]);
+        let cli = Overrides {
+            network: Some(Network::Pubnet),
+            ..Overrides::default()
+        };
+
+        let config = Config::layered(&file, &env, &cli).unwrap();
+        assert_eq!(config.network, Network::Pubnet);
+    }
+
+    #[test]
+    fn test_reflector_override_on_network_without_reflector() {
+        let cli = Overrides {
+            network: Some(Network::Futurenet),
+            reflector: ReflectorOverrides {
+                stellar: Some("CALI2BYU2JE6WVRUFYTS6MSBNEHGJ35P4AVCZYF3B6QOE3QKOB2PLE6M".to_string()),
+                ..ReflectorOverrides::default()
+            },
+            ..Overrides::default()
+        };
+
+        let config = Config::layered(&ConfigFile::default(), &Overrides::default(), &cli).unwrap();
+        assert_eq!(config.reflector("stellar").unwrap(), "CALI2BYU2JE6WVRUFYTS6MSBNEHGJ35P4AVCZYF3B6QOE3QKOB2PLE6M");
+        // The oracles left unset stay unset rather than becoming empty addresses
+        assert_eq!(
+            config.reflector("fiat").unwrap_err().to_string(),
+            "No Reflector fiat oracle configured for network futurenet"
+        );
+    }
+
+    #[test]
+    fn test_s3_settings_from_file_and_env() {
+        let file = ConfigFile::parse(r#"
+            base_url = "s3://stellar-ledgers"
+
+            [s3]
+            endpoint = "http://localhost:9000"
+            path_style = true
+            access_key_id = "minioadmin"
+            secret_access_key = "minioadmin"
+        "#).unwrap();
+        let env = env(&[
+            ("STELLAR_DATA_S3_REGION", "eu-west-1"),
+            ("STELLAR_DATA_S3_SECRET_ACCESS_KEY", "from-env"),
+        ]);
+
+        let config = Config::layered(&file, &env, &Overrides::default()).unwrap();
+        assert_eq!(config.base_url, "s3://stellar-ledgers");
+        assert_eq!(config.s3.endpoint.as_deref(), Some("http://localhost:9000"));
+        assert!(config.s3.path_style);
+        assert_eq!(config.s3.region, "eu-west-1");
+        assert_eq!(config.s3.access_key_id.as_deref(), Some("minioadmin"));
+        assert_eq!(config.s3.secret_access_key.as_deref(), Some("from-env"));
+    }
+
+    #[test]
+    fn test_retry_settings_layering() {
+        let file = ConfigFile::parse(r#"
+            [retry]
+            retries = 5
+            max_delay_ms = 2000
+        "#).unwrap();
+        let env = env(&[("STELLAR_DATA_TIMEOUT_SECS", "10")]);
+        let cli = Overrides {
+            retry: RetryOverrides { retries: Some(0), ..RetryOverrides::default() },
+            ..Overrides::default()
+        };
+
+        let config = Config::layered(&file, &env, &cli).unwrap();
+        assert_eq!(config.retry.retries, 0);
+        assert_eq!(config.retry.max_delay_ms, 2000);
+        assert_eq!(config.retry.timeout_secs, 10);
+        assert_eq!(config.retry.base_delay_ms, 250);
+    }
+
+    #[test]
+    fn test_cache_settings_layering() {
+        let file = ConfigFile::parse(r#"
+            [cache]
+            dir = "/var/cache/stellar-data"
+            max_size_mb = 512
+        "#).unwrap();
+        let env = env(&[("STELLAR_DATA_CACHE_ENABLED", "no")]);
+
+        let config = Config::layered(&file, &env, &Overrides::default()).unwrap();
+        assert!(!config.cache.enabled);
+        assert_eq!(config.cache.dir().unwrap(), PathBuf::from("/var/cache/stellar-data"));
+        assert_eq!(config.cache.max_bytes(), 512 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_env_invalid_path_style_fails() {
+        let result = Overrides::from_env(|name| {
+            (name == "STELLAR_DATA_S3_PATH_STYLE").then(|| "sometimes".to_string())
+        });
+        assert!(result.unwrap_err().to_string().contains("S3_PATH_STYLE"));
+    }
+
+    #[test]
+    fn test_effective_config_serializes_to_toml() {
+        let config = Config::default();
+        let text = toml::to_string_pretty(&config).unwrap();
+        assert!(text.contains("network = \"pubnet\""));
+        assert!(text.contains("[tokens.xlm]"));
+        assert!(text.contains("[reflector]"));
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use stellar_xdr::curr::{
+    AccountId, AlphaNum12, AlphaNum4, Asset, AssetCode12, AssetCode4, ContractIdPreimage, Hash,
+    HashIdPreimage, HashIdPreimageContractId, Limits, WriteXdr,
+};
+
+/// Decimals used by Stellar Asset Contracts and most Soroban tokens
+pub const DEFAULT_DECIMALS: u32 = 7;
+
+/// A token known to the registry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TokenInfo {
+    pub symbol: String,
+    pub contract: String,
+    pub decimals: u32,
+    /// Classic asset wrapped by the contract, as `CODE:ISSUER` or `native`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub classic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl TokenInfo {
+    /// Token with only a contract address and default decimals
+    pub fn contract(symbol: &str, contract: &str) -> Self {
+        TokenInfo {
+            symbol: symbol.to_string(),
+            contract: contract.to_string(),
+            decimals: DEFAULT_DECIMALS,
+            classic: None,
+            name: None,
+        }
+    }
+
+    /// Stellar Asset Contract token wrapping a classic asset
+    pub fn classic(symbol: &str, contract: &str, classic: &str, name: &str) -> Self {
+        TokenInfo {
+            classic: Some(classic.to_string()),
+            name: Some(name.to_string()),
+            ..TokenInfo::contract(symbol, contract)
+        }
+    }
+}
+
+/// A token entry as written in a config or registry file
+///
+/// Either a bare contract address (`usdc = "C..."`) or a table with the full
+/// details. The contract may be omitted when `classic` is given, in which case
+/// the Stellar Asset Contract address is derived from the network passphrase.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum TokenEntry {
+    Contract(String),
+    Details(TokenSpec),
+}
+
+/// Full token details in a config or registry file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TokenSpec {
+    pub symbol: Option<String>,
+    pub contract: Option<String>,
+    pub decimals: Option<u32>,
+    pub classic: Option<String>,
+    pub name: Option<String>,
+}
+
+/// Contents of a token registry file (`[[token]]` entries)
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegistryFile {
+    #[serde(default)]
+    pub token: Vec<TokenSpec>,
+}
+
+/// Default registry location: `~/.config/stellar-data/tokens.toml`
+pub fn default_registry_path() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join(".config").join("stellar-data").join("tokens.toml"))
+}
+
+/// Tokens keyed by lowercase symbol
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct TokenRegistry {
+    tokens: BTreeMap<String, TokenInfo>,
+}
+
+impl TokenRegistry {
+    /// Add a token, replacing any existing entry with the same symbol
+    pub fn insert(&mut self, token: TokenInfo) {
+        self.tokens.insert(token.symbol.to_lowercase(), token);
+    }
+
+    /// Add a file entry, resolving defaults against the network passphrase
+    ///
+    /// A bare contract entry for an existing symbol keeps its other details.
+    pub fn insert_entry(&mut self, symbol: &str, entry: &TokenEntry, passphrase: &str) -> Result<()> {
+        let spec = match entry {
+            TokenEntry::Contract(contract) => TokenSpec {
+                contract: Some(contract.clone()),
+                ..TokenSpec::default()
+            },
+            TokenEntry::Details(spec) => spec.clone(),
+        };
+        self.insert_spec(symbol, &spec, passphrase)
+    }
+
+    /// Add a token from its file details
+    pub fn insert_spec(&mut self, symbol: &str, spec: &TokenSpec, passphrase: &str) -> Result<()> {
+        let existing = self.tokens.get(&symbol.to_lowercase()).cloned();
+
+        let contract = match (&spec.contract, &spec.classic) {
+            (Some(contract), _) => contract.clone(),
+            (None, Some(classic)) => stellar_asset_contract(classic, passphrase)
+                .with_context(|| format!("Invalid classic asset for token {}", symbol))?,
+            (None, None) => existing.as_ref().map(|t| t.contract.clone()).ok_or_else(|| {
+                anyhow::anyhow!("Token {} needs a contract address or a classic asset", symbol)
+            })?,
+        };
+
+        // Details only carry over when the entry still points at the same contract
+        let existing = existing.filter(|t| t.contract == contract);
+
+        let token = TokenInfo {
+            symbol: symbol.to_string(),
+            decimals: spec.decimals
+                .or(existing.as_ref().map(|t| t.decimals))
+                .unwrap_or(DEFAULT_DECIMALS),
+            classic: spec.classic.clone().or(existing.as_ref().and_then(|t| t.classic.clone())),
+            name: spec.name.clone().or(existing.as_ref().and_then(|t| t.name.clone())),
+            contract,
+        };
+
+        self.insert(token);
+        Ok(())
+    }
+
+    /// Read `[[token]]` entries from a registry file
+    pub fn load_file(&mut self, path: &Path, passphrase: &str) -> Result<()> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read token registry {}", path.display()))?;
+        self.load_str(&text, passphrase)
+            .with_context(|| format!("Invalid token registry {}", path.display()))
+    }
+
+    /// Parse `[[token]]` entries from registry file text
+    pub fn load_str(&mut self, text: &str, passphrase: &str) -> Result<()> {
+        let file: RegistryFile = toml::from_str(text).context("Failed to parse token registry")?;
+
+        for spec in &file.token {
+            let symbol = spec.symbol.as_deref()
+                .ok_or_else(|| anyhow::anyhow!("Token registry entry is missing a symbol"))?;
+            self.insert_spec(symbol, spec, passphrase)?;
+        }
+        Ok(())
+    }
+
+    /// Look up a token by symbol (case-insensitive) or contract address
+    pub fn resolve(&self, token: &str) -> Option<&TokenInfo> {
+        self.tokens.get(&token.to_lowercase())
+            .or_else(|| self.tokens.values().find(|t| t.contract == token))
+    }
+
+    /// Iterate over tokens ordered by symbol
+    pub fn iter(&self) -> impl Iterator<Item = &TokenInfo> {
+        self.tokens.values()
+    }
+}
+
+impl FromIterator<TokenInfo> for TokenRegistry {
+    fn from_iter<I: IntoIterator<Item = TokenInfo>>(iter: I) -> Self {
+        let mut registry = TokenRegistry::default();
+        for token in iter {
+            registry.insert(token);
+        }
+        registry
+    }
+}
+
+/// Parse a classic asset written as `native` or `CODE:ISSUER`
+pub fn parse_classic_asset(classic: &str) -> Result<Asset> {
+    if classic.eq_ignore_ascii_case("native") {
+        return Ok(Asset::Native);
+    }
+
+    let (code, issuer) = classic.split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("Classic asset must be 'native' or CODE:ISSUER, got '{}'", classic))?;
+
+    let issuer: AccountId = issuer.parse()
+        .map_err(|_| anyhow::anyhow!("Invalid issuer account: {}", issuer))?;
+
+    if code.is_empty() || code.len() > 12 || !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+        anyhow::bail!("Invalid asset code: {}", code);
+    }
+
+    let mut bytes = [0u8; 12];
+    bytes[..code.len()].copy_from_slice(code.as_bytes());
+
+    if code.len() <= 4 {
+        let mut code4 = [0u8; 4];
+        code4.copy_from_slice(&bytes[..4]);
+        Ok(Asset::CreditAlphanum4(AlphaNum4 { asset_code: AssetCode4(code4), issuer }))
+    } else {
+        Ok(Asset::CreditAlphanum12(AlphaNum12 { asset_code: AssetCode12(bytes), issuer }))
+    }
+}
+
+/// Network ID: SHA-256 of the network passphrase
+pub fn network_id(passphrase: &str) -> [u8; 32] {
+    Sha256::digest(passphrase.as_bytes()).into()
+}
+
+/// Derive the Stellar Asset Contract address for a classic asset on a network
+pub fn stellar_asset_contract(classic: &str, passphrase: &str) -> Result<String> {
+    let preimage = HashIdPreimage::ContractId(HashIdPreimageContractId {
+        network_id: Hash(network_id(passphrase)),
+        contract_id_preimage: ContractIdPreimage::Asset(parse_classic_asset(classic)?),
+    });
+
+    let bytes = preimage.to_xdr(Limits::none())
+        .context("Failed to encode contract ID preimage")?;
+    let contract_id: [u8; 32] = Sha256::digest(&bytes).into();
+
+    Ok(stellar_strkey::Contract(contract_id).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBNET: &str = "Public Global Stellar Network ; September 2015";
+    const TESTNET: &str = "Test SDF Network ; September 2015";
+    const USDC_ISSUER: &str = "GA5ZSEJYB37JRC5AVCIA5MOP4RHTM335X2KGX3IHOJAPP5RE34K4KZVN";
+
+    #[test]
+    fn test_native_asset_contract_per_network() {
+        assert_eq!(
+            stellar_asset_contract("native", PUBNET).unwrap(),
+            "CAS3J7GYLGXMF6TDJBBYYSE3HQ6BBSMLNUQ34T6TZMYMW2EVH34XOWMA"
+        );
+        assert_eq!(
+            stellar_asset_contract("native", TESTNET).unwrap(),
+            "CDLZFC3SYJYDZT7K67VZ75HPJVIEUVNIXF47ZG2FB2RMQQVU2HHGCYSC"
+        );
+    }
+
+    #[test]
+    fn test_credit_asset_contract() {
+        let classic = format!("USDC:{}", USDC_ISSUER);
+        assert_eq!(
+            stellar_asset_contract(&classic, PUBNET).unwrap(),
+            "CCW67TSZV3SSS2HXMBQ5JFGCKJNXKZM7UQUWUZPUTHXSTZLEO7SJMI75"
+        );
+    }
+
+    #[test]
+    fn test_parse_classic_asset() {
+        assert_eq!(parse_classic_asset("NATIVE").unwrap(), Asset::Native);
+        assert!(matches!(
+            parse_classic_asset(&format!("USDC:{}", USDC_ISSUER)).unwrap(),
+            Asset::CreditAlphanum4(_)
+        ));
+        assert!(matches!(
+            parse_classic_asset(&format!("LONGERCODE:{}", USDC_ISSUER)).unwrap(),
+            Asset::CreditAlphanum12(_)
+        ));
+        assert!(parse_classic_asset("USDC").is_err());
+        assert!(parse_classic_asset("USDC:GBAD").is_err());
+        assert!(parse_classic_asset(&format!("THIRTEENCHARS:{}", USDC_ISSUER)).is_err());
+    }
+
+    #[test]
+    fn test_load_registry_file() {
+        let mut registry = TokenRegistry::default();
+        registry.load_str(&format!(r#"
+            [[token]]
+            symbol = "USDC"
+            classic = "USDC:{}"
+            name = "USD Coin"
+
+            [[token]]
+            symbol = "BLND"
+            contract = "CD25MNVTZDL4Y3XBCPCJXGXATV5WUHHOWMYFF4YBEGU5FCPGMYTVG5JY"
+            decimals = 7
+        "#, USDC_ISSUER), PUBNET).unwrap();
+
+        assert_eq!(registry.iter().count(), 2);
+        let usdc = registry.resolve("usdc").unwrap();
+        assert_eq!(usdc.contract, "CCW67TSZV3SSS2HXMBQ5JFGCKJNXKZM7UQUWUZPUTHXSTZLEO7SJMI75");
+        assert_eq!(usdc.decimals, DEFAULT_DECIMALS);
+        assert_eq!(usdc.name.as_deref(), Some("USD Coin"));
+        assert_eq!(usdc.symbol, "USDC");
+    }
+
+    #[test]
+    fn test_registry_entry_requires_symbol_and_contract() {
+        let mut registry = TokenRegistry::default();
+        assert!(registry.load_str("[[token]]\ncontract = \"C\"", PUBNET).is_err());
+        assert!(registry.load_str("[[token]]\nsymbol = \"abc\"", PUBNET).is_err());
+        assert!(registry.load_str("[[token]]\nsymbol = \"abc\"\ncontarct = \"C\"", PUBNET).is_err());
+    }
+
+    #[test]
+    fn test_resolve_by_contract() {
+        let registry: TokenRegistry = [
+            TokenInfo::contract("kale", "CB23WRDQWGSP6YPMY4UV5C4OW5CBTXKYN3XEATG7KJEZCXMJBYEHOUOV"),
+        ].into_iter().collect();
+
+        assert_eq!(
+            registry.resolve("CB23WRDQWGSP6YPMY4UV5C4OW5CBTXKYN3XEATG7KJEZCXMJBYEHOUOV").unwrap().symbol,
+            "kale"
+        );
+        assert_eq!(registry.resolve("KALE").unwrap().symbol, "kale");
+        assert!(registry.resolve("unknown").is_none());
+    }
+
+    #[test]
+    fn test_contract_entry_keeps_details_of_same_contract() {
+        let mut registry: TokenRegistry = [
+            TokenInfo::classic("usdc", "CCW67TSZV3SSS2HXMBQ5JFGCKJNXKZM7UQUWUZPUTHXSTZLEO7SJMI75", "USDC:X", "USD Coin"),
+        ].into_iter().collect();
+
+        let same = TokenEntry::Contract("CCW67TSZV3SSS2HXMBQ5JFGCKJNXKZM7UQUWUZPUTHXSTZLEO7SJMI75".to_string());
+        registry.insert_entry("usdc", &same, PUBNET).unwrap();
+        assert_eq!(registry.resolve("usdc").unwrap().name.as_deref(), Some("USD Coin"));
+
+        let other = TokenEntry::Contract("CB23WRDQWGSP6YPMY4UV5C4OW5CBTXKYN3XEATG7KJEZCXMJBYEHOUOV".to_string());
+        registry.insert_entry("usdc", &other, PUBNET).unwrap();
+        assert!(registry.resolve("usdc").unwrap().name.is_none());
+    }
+}
@@ -0,0 +1,438 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+
+use crate::config::CacheSettings;
+
+/// Extension of cached ledger data files
+const ENTRY_EXTENSION: &str = "xdr";
+
+/// File holding the total size of the cached data
+const SIZE_INDEX: &str = "size";
+
+/// On-disk cache of decompressed ledger data
+///
+/// Data is content addressed: each distinct blob is stored once, named by
+/// the SHA-256 of its bytes, and looked up through keys naming where it came
+/// from (the data lake object URL, or the network and sequence for RPC
+/// ledgers). Closed ledgers never change, so entries never go stale. Reading
+/// an entry bumps its modification time, and once the cache grows past its
+/// size cap the least recently used data is deleted.
+///
+/// The total size is kept in an index file, so enforcing the cap doesn't
+/// mean walking the cache in every process that writes to it. Eviction walks
+/// it anyway and writes the exact total back, correcting any drift from
+/// processes writing at the same time.
+#[derive(Debug)]
+pub struct LedgerCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Serializes updates to the size index within this process
+    index: Mutex<()>,
+}
+
+/// Summary of the cache contents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub entries: u64,
+    pub bytes: u64,
+}
+
+/// An entry being written, added to the cache by `LedgerCache::commit`
+///
+/// The data goes to a temporary file so readers never see partial entries,
+/// and is hashed on the way to find where it belongs. Dropping the writer
+/// without committing it removes the file.
+pub struct CacheWriter {
+    file: BufWriter<File>,
+    temp: PathBuf,
+    key: PathBuf,
+    hasher: Sha256,
+    written: u64,
+}
+
+impl Write for CacheWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.temp);
+    }
+}
+
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    accessed: SystemTime,
+}
+
+impl LedgerCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        LedgerCache { dir, max_bytes, index: Mutex::new(()) }
+    }
+
+    /// The cache described by the settings, or `None` when it is disabled
+    pub fn from_settings(settings: &CacheSettings) -> Option<Self> {
+        if !settings.enabled {
+            return None;
+        }
+        settings.dir().map(|dir| Self::new(dir, settings.max_bytes()))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+
+    /// Cached data for a key, marking it as recently used
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let mut data = Vec::new();
+        self.open(key)?.read_to_end(&mut data).ok()?;
+        Some(data)
+    }
+
+    /// Open cached data for a key to read it as a stream, marking it as recently used
+    pub fn open(&self, key: &str) -> Option<File> {
+        let key_path = self.key_path(key);
+        let path = self.object_path(&fs::read_to_string(&key_path).ok()?)?;
+        let Ok(file) = File::open(&path) else {
+            // The data was evicted; forget the key too
+            let _ = fs::remove_file(&key_path);
+            return None;
+        };
+
+        if let Ok(file) = File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(file)
+    }
+
+    /// Store data under a key, evicting old entries if the cache is full
+    pub fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let mut writer = self.writer(key)?;
+        writer.write_all(data)?;
+        self.commit(writer)
+    }
+
+    /// Start writing data for a key, for data that arrives as a stream
+    pub fn writer(&self, key: &str) -> io::Result<CacheWriter> {
+        let objects = self.dir.join("objects");
+        fs::create_dir_all(&objects)?;
+
+        let temp = objects.join(format!(".{}.{}.tmp", std::process::id(), fastrand::u64(..)));
+        let file = BufWriter::new(File::create(&temp)?);
+        Ok(CacheWriter { file, temp, key: self.key_path(key), hasher: Sha256::new(), written: 0 })
+    }
+
+    /// Add a fully written entry to the cache, evicting old entries if the cache is full
+    ///
+    /// Data already in the cache under another key is shared rather than
+    /// stored again.
+    pub fn commit(&self, mut writer: CacheWriter) -> io::Result<()> {
+        writer.flush()?;
+        let hash = format!("{:x}", std::mem::take(&mut writer.hasher).finalize());
+        let path = self.object_path(&hash).unwrap_or_default();
+        fs::create_dir_all(path.parent().unwrap_or(&self.dir))?;
+
+        let _index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+        let before = self.read_size_index()?;
+
+        let added = if path.is_file() {
+            0
+        } else {
+            fs::rename(&writer.temp, &path)?;
+            writer.written
+        };
+        write_atomically(&writer.key, hash.as_bytes())?;
+
+        let total = before + added;
+        if total > self.max_bytes {
+            self.evict()?;
+        } else if added > 0 {
+            self.write_size_index(total)?;
+        }
+        Ok(())
+    }
+
+    /// Drop an entry, such as one that no longer decodes
+    pub fn remove(&self, key: &str) {
+        let key_path = self.key_path(key);
+        let Some(path) = fs::read_to_string(&key_path).ok().and_then(|hash| self.object_path(&hash)) else {
+            return;
+        };
+        let _ = fs::remove_file(&key_path);
+
+        if let Ok(meta) = fs::metadata(&path) {
+            if fs::remove_file(&path).is_ok() {
+                let _index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+                if let Ok(total) = self.read_size_index() {
+                    let _ = self.write_size_index(total.saturating_sub(meta.len()));
+                }
+            }
+        }
+    }
+
+    /// Count the entries and bytes in the cache
+    pub fn stats(&self) -> io::Result<CacheStats> {
+        Ok(self.entries()?.iter().fold(CacheStats::default(), |stats, entry| CacheStats {
+            entries: stats.entries + 1,
+            bytes: stats.bytes + entry.size,
+        }))
+    }
+
+    /// Delete every entry, returning what was removed
+    pub fn clear(&self) -> io::Result<CacheStats> {
+        let mut removed = CacheStats::default();
+        for entry in self.entries()? {
+            fs::remove_file(&entry.path)?;
+            removed.entries += 1;
+            removed.bytes += entry.size;
+        }
+        match fs::remove_dir_all(self.dir.join("keys")) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+
+        let _index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+        self.write_size_index(0)?;
+        Ok(removed)
+    }
+
+    /// Delete the least recently used entries until the cache fits its cap
+    fn evict(&self) -> io::Result<()> {
+        let mut entries = self.entries()?;
+        entries.sort_by_key(|entry| entry.accessed);
+
+        let mut total: u64 = entries.iter().map(|entry| entry.size).sum();
+        for entry in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            // Another process may have evicted it already
+            if fs::remove_file(&entry.path).is_ok() {
+                total -= entry.size;
+            }
+        }
+        self.write_size_index(total)
+    }
+
+    /// Total size of the cached data, from the size index
+    ///
+    /// A cache without an index, such as one written by an older version,
+    /// is walked once to build it.
+    fn read_size_index(&self) -> io::Result<u64> {
+        if let Some(total) = fs::read_to_string(self.dir.join(SIZE_INDEX)).ok()
+            .and_then(|size| size.trim().parse().ok())
+        {
+            return Ok(total);
+        }
+
+        let total = self.stats()?.bytes;
+        self.write_size_index(total)?;
+        Ok(total)
+    }
+
+    fn write_size_index(&self, total: u64) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_atomically(&self.dir.join(SIZE_INDEX), total.to_string().as_bytes())
+    }
+
+    /// Every entry on disk
+    fn entries(&self) -> io::Result<Vec<Entry>> {
+        let root = self.dir.join("objects");
+        let shards = match fs::read_dir(&root) {
+            Ok(shards) => shards,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut entries = Vec::new();
+        for shard in shards {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(shard.path())? {
+                let path = file?.path();
+                if path.extension().and_then(|ext| ext.to_str()) != Some(ENTRY_EXTENSION) {
+                    continue;
+                }
+                // Skip entries removed since the directory was listed
+                let Ok(meta) = fs::metadata(&path) else { continue };
+                entries.push(Entry {
+                    size: meta.len(),
+                    accessed: meta.modified()?,
+                    path,
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    /// `{dir}/keys/{first byte}/{sha256 of the key}`, holding the hash of its data
+    fn key_path(&self, key: &str) -> PathBuf {
+        let hash = format!("{:x}", Sha256::digest(key.as_bytes()));
+        self.dir.join("keys").join(&hash[..2]).join(hash)
+    }
+
+    /// `{dir}/objects/{first byte}/{sha256 of the data}.xdr`, for a well formed hash
+    fn object_path(&self, hash: &str) -> Option<PathBuf> {
+        let hash = hash.trim();
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(self.dir
+            .join("objects")
+            .join(&hash[..2])
+            .join(format!("{}.{}", hash, ENTRY_EXTENSION)))
+    }
+}
+
+/// Replace a small file through a temporary file, so readers never see it half written
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+    let temp = parent.join(format!(".{}.{}.tmp", std::process::id(), fastrand::u64(..)));
+    fs::write(&temp, data)?;
+    fs::rename(&temp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::testing::TempDir;
+
+    fn temp_cache(max_bytes: u64) -> (TempDir, LedgerCache) {
+        let dir = TempDir::new("cache");
+        let cache = LedgerCache::new(dir.path().to_path_buf(), max_bytes);
+        (dir, cache)
+    }
+
+    /// Where the data for a key is stored
+    fn object(cache: &LedgerCache, key: &str) -> PathBuf {
+        cache.object_path(&fs::read_to_string(cache.key_path(key)).unwrap()).unwrap()
+    }
+
+    /// Pretend an entry was last used `secs_ago` seconds ago
+    fn age(cache: &LedgerCache, key: &str, secs_ago: u64) {
+        let file = File::options().write(true).open(object(cache, key)).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(secs_ago)).unwrap();
+    }
+
+    #[test]
+    fn test_put_and_get() {
+        let (_dir, cache) = temp_cache(1024);
+        assert_eq!(cache.get("https://example.com/a.xdr.zst"), None);
+
+        cache.put("https://example.com/a.xdr.zst", b"ledger").unwrap();
+        assert_eq!(cache.get("https://example.com/a.xdr.zst").as_deref(), Some(&b"ledger"[..]));
+        assert_eq!(cache.stats().unwrap(), CacheStats { entries: 1, bytes: 6 });
+        assert!(object(&cache, "https://example.com/a.xdr.zst")
+            .ends_with(format!("{:x}.xdr", Sha256::digest(b"ledger"))));
+    }
+
+    #[test]
+    fn test_streamed_entry() {
+        let (_dir, cache) = temp_cache(1024);
+
+        // Abandoned entries leave nothing behind
+        let mut writer = cache.writer("a").unwrap();
+        writer.write_all(b"partial").unwrap();
+        drop(writer);
+        assert!(cache.open("a").is_none());
+        assert_eq!(fs::read_dir(cache.dir().join("objects")).unwrap().count(), 0);
+
+        let mut writer = cache.writer("a").unwrap();
+        writer.write_all(b"led").unwrap();
+        writer.write_all(b"ger").unwrap();
+        cache.commit(writer).unwrap();
+
+        let mut data = Vec::new();
+        cache.open("a").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"ledger");
+        assert_eq!(cache.stats().unwrap(), CacheStats { entries: 1, bytes: 6 });
+    }
+
+    #[test]
+    fn test_shares_identical_data() {
+        let (_dir, cache) = temp_cache(1024);
+        cache.put("a", b"ledger").unwrap();
+        cache.put("b", b"ledger").unwrap();
+
+        assert_eq!(cache.get("b").as_deref(), Some(&b"ledger"[..]));
+        assert_eq!(cache.stats().unwrap(), CacheStats { entries: 1, bytes: 6 });
+        assert_eq!(fs::read_to_string(cache.dir().join(SIZE_INDEX)).unwrap(), "6");
+    }
+
+    #[test]
+    fn test_size_index_survives_restarts() {
+        let (dir, cache) = temp_cache(25);
+        cache.put("a", &[1; 10]).unwrap();
+        cache.put("b", &[2; 10]).unwrap();
+        age(&cache, "a", 60);
+
+        // A new process picks the size up from the index and still enforces the cap
+        let cache = LedgerCache::new(dir.path().to_path_buf(), 25);
+        cache.put("c", &[3; 10]).unwrap();
+        assert!(cache.get("a").is_none());
+        assert_eq!(fs::read_to_string(dir.join(SIZE_INDEX)).unwrap(), "20");
+
+        // A missing index is rebuilt from the entries on disk
+        fs::remove_file(dir.join(SIZE_INDEX)).unwrap();
+        let cache = LedgerCache::new(dir.path().to_path_buf(), 1024);
+        cache.put("d", &[4; 10]).unwrap();
+        assert_eq!(fs::read_to_string(dir.join(SIZE_INDEX)).unwrap(), "30");
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let (_dir, cache) = temp_cache(25);
+        cache.put("a", &[1; 10]).unwrap();
+        cache.put("b", &[2; 10]).unwrap();
+        age(&cache, "a", 60);
+        age(&cache, "b", 30);
+
+        // Reading "a" makes "b" the least recently used entry
+        assert!(cache.get("a").is_some());
+        cache.put("c", &[3; 10]).unwrap();
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+        assert_eq!(cache.stats().unwrap().bytes, 20);
+    }
+
+    #[test]
+    fn test_clear_and_remove() {
+        let (_dir, cache) = temp_cache(1024);
+        cache.put("a", b"one").unwrap();
+        cache.put("b", b"two").unwrap();
+
+        cache.remove("a");
+        assert!(cache.get("a").is_none());
+        assert_eq!(fs::read_to_string(cache.dir().join(SIZE_INDEX)).unwrap(), "3");
+
+        assert_eq!(cache.clear().unwrap(), CacheStats { entries: 1, bytes: 3 });
+        assert_eq!(cache.stats().unwrap(), CacheStats::default());
+    }
+}
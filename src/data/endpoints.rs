@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::data::error::FetchError;
+use crate::data::rpc::{get_health, rpc_urls, RpcHealth};
+
+/// Last known state of an RPC endpoint
+#[derive(Debug, Clone)]
+struct Health {
+    healthy: bool,
+    since: Instant,
+}
+
+/// Endpoint health by URL, shared by every query in the process
+static HEALTH: LazyLock<Mutex<HashMap<String, Health>>> = LazyLock::new(Default::default);
+
+fn health() -> MutexGuard<'static, HashMap<String, Health>> {
+    HEALTH.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn record(url: &str, healthy: bool) {
+    health().insert(url.to_string(), Health { healthy, since: Instant::now() });
+}
+
+/// Ask an endpoint for its health and record whether it is usable
+pub fn check(config: &Config, url: &str) -> Result<RpcHealth, FetchError> {
+    let result = get_health(config, url);
+    record(url, result.as_ref().is_ok_and(|health| health.status == "healthy"));
+    result
+}
+
+/// Check every configured endpoint, as the server does periodically
+pub fn check_all(config: &Config) {
+    for url in rpc_urls(config) {
+        let _ = check(config, url);
+    }
+}
+
+/// Whether to try an endpoint now
+///
+/// Endpoints never seen failing are used straight away. One that failed is
+/// skipped until `rpc_health_check_secs` have passed, then used again once
+/// `getHealth` reports it healthy.
+fn usable(config: &Config, url: &str) -> bool {
+    let known = health().get(url).cloned();
+    match known {
+        None => true,
+        Some(known) if known.healthy => true,
+        Some(known) if known.since.elapsed() < Duration::from_secs(config.rpc_health_check_secs.into()) => false,
+        Some(_) => check(config, url).is_ok_and(|health| health.status == "healthy"),
+    }
+}
+
+/// Whether an endpoint failing this way should be skipped for a while
+fn is_outage(error: &FetchError) -> bool {
+    error.is_transient() || matches!(error, FetchError::Http { .. })
+}
+
+/// Make an RPC call against the first endpoint that answers
+///
+/// Endpoints are tried in the order given, with those that failed recently
+/// moved to the back as a last resort. Outages (timeouts, throttling, HTTP
+/// errors) move on to the next endpoint and mark the failing one, and a
+/// ledger one endpoint doesn't keep may be kept by another. Any other error,
+/// such as a JSON-RPC error, is returned as is. Returns the result along
+/// with the URL of the endpoint that served it.
+pub fn with_failover<T>(
+    config: &Config,
+    urls: &[&str],
+    mut call: impl FnMut(&str) -> Result<T, FetchError>,
+) -> Result<(T, String), FetchError> {
+    let (ready, resting): (Vec<&str>, Vec<&str>) = urls.iter().partition(|url| usable(config, url));
+
+    let mut not_found = None;
+    let mut outage = None;
+    for url in ready.into_iter().chain(resting) {
+        match call(url) {
+            Ok(value) => {
+                if health().get(url).is_some_and(|known| !known.healthy) {
+                    record(url, true);
+                }
+                return Ok((value, url.to_string()));
+            }
+            Err(e) if e.is_not_found() => not_found = Some(e),
+            Err(e) if is_outage(&e) => {
+                record(url, false);
+                outage = Some(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    // Endpoints that answered know better than those that didn't
+    Err(not_found
+        .or(outage)
+        .unwrap_or_else(|| FetchError::Rpc("No RPC endpoints configured".to_string())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn down(url: &str) -> FetchError {
+        FetchError::Network(format!("{} is down", url))
+    }
+
+    #[test]
+    fn test_fails_over_and_remembers_outages() {
+        let config = Config { rpc_health_check_secs: 3600, ..Config::default() };
+        let urls = ["https://failover-a.example.com/", "https://failover-b.example.com/"];
+
+        let mut tried = Vec::new();
+        let (value, served_by) = with_failover(&config, &urls, |url| {
+            tried.push(url.to_string());
+            if url == urls[0] { Err(down(url)) } else { Ok(42) }
+        }).unwrap();
+        assert_eq!((value, served_by.as_str()), (42, urls[1]));
+        assert_eq!(tried, urls);
+
+        // The failed endpoint is tried last until it is checked again
+        let mut tried = Vec::new();
+        with_failover(&config, &urls, |url| {
+            tried.push(url.to_string());
+            Err::<(), _>(down(url))
+        }).unwrap_err();
+        assert_eq!(tried, vec![urls[1], urls[0]]);
+    }
+
+    #[test]
+    fn test_not_found_wins_over_outages() {
+        let config = Config { rpc_health_check_secs: 3600, ..Config::default() };
+        let urls = ["https://not-found-a.example.com/", "https://not-found-b.example.com/"];
+
+        let err = with_failover(&config, &urls, |url| -> Result<(), _> {
+            if url == urls[0] {
+                Err(FetchError::NotFound("Ledger 5 not available".to_string()))
+            } else {
+                Err(down(url))
+            }
+        }).unwrap_err();
+        assert!(err.is_not_found());
+    }
+
+    #[test]
+    fn test_rpc_errors_do_not_fail_over() {
+        let config = Config::default();
+        let urls = ["https://rpc-error-a.example.com/", "https://rpc-error-b.example.com/"];
+
+        let mut calls = 0;
+        let err = with_failover(&config, &urls, |_| -> Result<(), _> {
+            calls += 1;
+            Err(FetchError::Rpc("RPC error: invalid transaction".to_string()))
+        }).unwrap_err();
+        assert_eq!(err.kind(), "rpc");
+        assert_eq!(calls, 1);
+    }
+}
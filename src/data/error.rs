@@ -0,0 +1,165 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Why fetching a ledger failed
+///
+/// Fallback and retry decisions are made on the variant rather than on the
+/// error text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchError {
+    /// The ledger is not in the data lake (or RPC) yet
+    NotFound(String),
+    /// Connection failures, timeouts and server errors worth retrying
+    Network(String),
+    /// The server asked us to slow down (HTTP 429)
+    Throttled {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    /// Any other HTTP error status, such as 403 for bad credentials
+    Http { status: u16, message: String },
+    /// The data lake URL could not be resolved or parsed
+    InvalidUrl(String),
+    /// The RPC answered with a JSON-RPC error or an unexpected response
+    Rpc(String),
+    /// Reading a local data lake mirror failed
+    Io(String),
+    /// The object was not valid zstd data
+    Decompress(String),
+    /// The data was not valid XDR
+    Decode(String),
+    /// The worker fetching the ledger failed, such as by panicking
+    Internal(String),
+}
+
+impl FetchError {
+    /// Short machine readable name of the failure, as used in range results
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FetchError::NotFound(_) => "not_found",
+            FetchError::Network(_) => "network",
+            FetchError::Throttled { .. } => "throttled",
+            FetchError::Http { .. } => "http",
+            FetchError::InvalidUrl(_) => "invalid_url",
+            FetchError::Rpc(_) => "rpc",
+            FetchError::Io(_) => "io",
+            FetchError::Decompress(_) => "decompress",
+            FetchError::Decode(_) => "decode",
+            FetchError::Internal(_) => "internal",
+        }
+    }
+
+    /// Whether the ledger is missing from the source, so another source may have it
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, FetchError::NotFound(_))
+    }
+
+    /// Whether trying the same request again may succeed
+    pub fn is_transient(&self) -> bool {
+        matches!(self, FetchError::Network(_) | FetchError::Throttled { .. })
+    }
+
+    /// Classify a failed HTTP response
+    pub fn from_status(status: reqwest::StatusCode, retry_after: Option<Duration>, what: &str) -> Self {
+        let message = format!(
+            "{} returned HTTP {}: {}",
+            what,
+            status.as_u16(),
+            status.canonical_reason().unwrap_or("Unknown error")
+        );
+
+        match status.as_u16() {
+            404 => FetchError::NotFound(message),
+            429 => FetchError::Throttled { message, retry_after },
+            408 | 500..=599 => FetchError::Network(message),
+            status => FetchError::Http { status, message },
+        }
+    }
+
+    /// Classify a request that failed before a response arrived
+    pub fn from_reqwest(err: reqwest::Error, what: &str) -> Self {
+        FetchError::Network(format!("{} request failed: {}", what, err))
+    }
+
+    /// Range result entry describing a ledger that could not be fetched
+    pub fn to_json(&self, ledger_seq: u32) -> serde_json::Value {
+        serde_json::json!({
+            "ledger": ledger_seq,
+            "reason": self.kind(),
+            "retryable": self.is_transient(),
+            "error": self.to_string()
+        })
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::NotFound(message)
+            | FetchError::Network(message)
+            | FetchError::Throttled { message, .. }
+            | FetchError::Http { message, .. }
+            | FetchError::InvalidUrl(message)
+            | FetchError::Rpc(message)
+            | FetchError::Io(message)
+            | FetchError::Decompress(message)
+            | FetchError::Decode(message)
+            | FetchError::Internal(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+/// Parse a `Retry-After` header given in seconds
+pub fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers.get(reqwest::header::RETRY_AFTER)?
+        .to_str().ok()?
+        .trim()
+        .parse::<u64>().ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_from_status_classification() {
+        assert!(FetchError::from_status(StatusCode::NOT_FOUND, None, "S3").is_not_found());
+        assert!(FetchError::from_status(StatusCode::SERVICE_UNAVAILABLE, None, "S3").is_transient());
+        assert!(FetchError::from_status(StatusCode::REQUEST_TIMEOUT, None, "S3").is_transient());
+
+        let throttled = FetchError::from_status(StatusCode::TOO_MANY_REQUESTS, Some(Duration::from_secs(3)), "RPC");
+        assert!(throttled.is_transient());
+        assert!(matches!(throttled, FetchError::Throttled { retry_after: Some(d), .. } if d.as_secs() == 3));
+
+        let forbidden = FetchError::from_status(StatusCode::FORBIDDEN, None, "S3");
+        assert!(!forbidden.is_transient());
+        assert!(!forbidden.is_not_found());
+        assert_eq!(forbidden.kind(), "http");
+        assert_eq!(forbidden.to_string(), "S3 returned HTTP 403: Forbidden");
+    }
+
+    #[test]
+    fn test_to_json() {
+        let json = FetchError::Decode("Failed to parse XDR data".to_string()).to_json(63864);
+        assert_eq!(json["ledger"], 63864);
+        assert_eq!(json["reason"], "decode");
+        assert_eq!(json["retryable"], false);
+        assert_eq!(json["error"], "Failed to parse XDR data");
+    }
+
+    #[test]
+    fn test_retry_after_seconds() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(reqwest::header::RETRY_AFTER, "120".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+
+        headers.insert(reqwest::header::RETRY_AFTER, "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use stellar_xdr::curr::{LedgerCloseMeta, LedgerCloseMetaBatch};
+
+use crate::data::error::FetchError;
+use crate::data::source::{missing_from_batch, LedgerReader, LedgerSource};
+use crate::data::xdr::single_batch;
+
+/// A ledger as a batch holding only that ledger
+#[derive(Debug, Clone)]
+pub struct FetchedLedger {
+    pub batch: LedgerCloseMetaBatch,
+    /// RPC endpoint that served the ledger, if it didn't come from the data lake
+    pub served_by: Option<String>,
+}
+
+/// Fetches consecutive ledgers from a source
+///
+/// Batch files holding several ledgers are downloaded once and read as they
+/// download; the following ledgers of the batch are read from the open file.
+pub struct LedgerFetcher {
+    source: Arc<dyn LedgerSource>,
+    /// Last ledger that will be asked for
+    end: u32,
+    reader: Option<LedgerReader>,
+}
+
+impl LedgerFetcher {
+    /// Fetcher for ledgers up to `end`
+    pub fn new(source: Arc<dyn LedgerSource>, end: u32) -> Self {
+        LedgerFetcher { source, end, reader: None }
+    }
+
+    /// Fetch a ledger
+    ///
+    /// A ledger the open batch has already passed, or failed to read, is
+    /// fetched again from the start of its batch.
+    pub fn fetch(&mut self, ledger_seq: u32) -> Result<FetchedLedger, FetchError> {
+        if let Some(reader) = self.reader.as_mut() {
+            match reader.seek(ledger_seq) {
+                Some(Ok(meta)) => return fetched(ledger_seq, meta, reader),
+                _ => self.reader = None,
+            }
+        }
+
+        let mut reader = self.source.read_batch(ledger_seq, self.end.max(ledger_seq))?;
+        let meta = match reader.seek(ledger_seq) {
+            Some(meta) => meta?,
+            None => return Err(missing_from_batch(ledger_seq, &reader)),
+        };
+
+        let ledger = fetched(ledger_seq, meta, &reader);
+        self.reader = Some(reader);
+        ledger
+    }
+}
+
+fn fetched(ledger_seq: u32, meta: LedgerCloseMeta, reader: &LedgerReader) -> Result<FetchedLedger, FetchError> {
+    Ok(FetchedLedger { batch: single_batch(ledger_seq, meta)?, served_by: reader.served_by.clone() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::local::LocalSource;
+    use crate::data::xdr::ledger_sequence;
+    use crate::testing::{lake_config, write_lake, TempDir};
+
+    #[test]
+    fn test_fetch_from_multi_ledger_batch() {
+        let root = TempDir::new("fetcher");
+        let config = lake_config(root.path(), 4, 2);
+        write_lake(&config, 8, 11);
+
+        let mut fetcher = LedgerFetcher::new(Arc::new(LocalSource::new(Arc::new(config), true)), 11);
+        let first = fetcher.fetch(9).unwrap().batch;
+
+        // Later ledgers are read from the batch file already open, even once it is deleted
+        std::fs::remove_dir_all(root.path()).unwrap();
+        let second = fetcher.fetch(11).unwrap().batch;
+
+        assert_eq!(ledger_sequence(&first.ledger_close_metas[0]), 9);
+        assert_eq!(ledger_sequence(&second.ledger_close_metas[0]), 11);
+        assert_eq!(second.start_sequence, 11);
+        assert_eq!(second.end_sequence, 11);
+    }
+}
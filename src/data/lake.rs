@@ -0,0 +1,160 @@
+use serde_json::json;
+
+use crate::data::error::FetchError;
+use crate::config::{Config, RpcRole};
+use crate::data::endpoints;
+use crate::data::rpc::rpc_urls;
+use crate::data::source::LedgerSource;
+
+/// First ledger exported to data lakes (ledger 1 is the genesis ledger)
+pub const FIRST_LEDGER: u32 = 2;
+
+/// Last value in `lo..=hi` for which `exists` holds
+///
+/// Assumes everything up to the answer exists and nothing after it does,
+/// as in a data lake exported in ledger order. Probes down from `hi` in
+/// doubling steps and then bisects, so an answer close to `hi` only takes
+/// a few probes.
+pub fn find_last(
+    lo: u32,
+    hi: u32,
+    mut exists: impl FnMut(u32) -> Result<bool, FetchError>,
+) -> Result<Option<u32>, FetchError> {
+    if lo > hi {
+        return Ok(None);
+    }
+    if exists(hi)? {
+        return Ok(Some(hi));
+    }
+
+    // Step down until something exists
+    let mut missing = hi;
+    let mut step = 1u32;
+    let mut found = loop {
+        let probe = missing.saturating_sub(step).max(lo);
+        if exists(probe)? {
+            break probe;
+        }
+        if probe == lo {
+            return Ok(None);
+        }
+        missing = probe;
+        step = step.saturating_mul(2);
+    };
+
+    // Bisect between the last value found and the first one missing
+    while missing - found > 1 {
+        let mid = found + (missing - found) / 2;
+        if exists(mid)? {
+            found = mid;
+        } else {
+            missing = mid;
+        }
+    }
+    Ok(Some(found))
+}
+
+/// Last ledger of `start..=end` in the data lake, probing for batch files
+pub fn last_in_lake(
+    config: &Config,
+    start: u32,
+    end: u32,
+    mut exists: impl FnMut(&str) -> Result<bool, FetchError>,
+) -> Result<Option<u32>, FetchError> {
+    let per_batch = config.ledgers_per_batch.max(1);
+    let last_batch = find_last(start / per_batch, end / per_batch, |batch| {
+        exists(&config.generate_url(batch * per_batch))
+    })?;
+
+    Ok(last_batch
+        .map(|batch| (batch * per_batch).saturating_add(per_batch - 1).min(end))
+        .filter(|last| *last >= start))
+}
+
+/// Latest ledger of the network and of the data lake, and the ledgers each
+/// RPC keeps, as shown by `status`
+pub fn status(config: &Config, source: &dyn LedgerSource, latest_ledger: u32) -> Result<serde_json::Value, FetchError> {
+    let lake_latest = source.last_available(FIRST_LEDGER, latest_ledger)?;
+
+    let archive = config.archive_rpc_urls();
+    let soroban = config.soroban_rpc_urls();
+    let rpc: Vec<serde_json::Value> = rpc_urls(config).into_iter()
+        .map(|url| {
+            let role = match (archive.contains(&url), soroban.contains(&url)) {
+                (true, true) => RpcRole::Both,
+                (true, false) => RpcRole::Archive,
+                _ => RpcRole::Soroban,
+            };
+            (url, role, endpoints::check(config, url))
+        })
+        .map(|(url, role, health)| match health {
+            Ok(health) => json!({
+                "url": url,
+                "role": role.as_str(),
+                "status": health.status,
+                "latest_ledger": health.latest_ledger,
+                "oldest_ledger": health.oldest_ledger,
+                "retention_window_ledgers": health.ledger_retention_window
+            }),
+            Err(e) => json!({ "url": url, "role": role.as_str(), "error": e.to_string() }),
+        })
+        .collect();
+
+    Ok(json!({
+        "network": config.network.as_str(),
+        "latest_ledger": latest_ledger,
+        "data_lake": {
+            "base_url": config.base_url,
+            "ledgers_path": config.ledgers_path,
+            "latest_ledger": lake_latest,
+            "lag_ledgers": lake_latest.map(|last| latest_ledger.saturating_sub(last))
+        },
+        "rpc": rpc,
+        "sources": source.name()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_find_last() {
+        let probes = &Cell::new(0);
+        let exists = |last: u32| {
+            move |i: u32| {
+                probes.set(probes.get() + 1);
+                Ok(i <= last)
+            }
+        };
+
+        assert_eq!(find_last(2, 60_000_000, exists(60_000_000)).unwrap(), Some(60_000_000));
+        assert_eq!(find_last(2, 60_000_000, exists(59_999_990)).unwrap(), Some(59_999_990));
+        assert_eq!(find_last(2, 60_000_000, exists(123_456)).unwrap(), Some(123_456));
+        assert_eq!(find_last(2, 60_000_000, exists(1)).unwrap(), None);
+        assert_eq!(find_last(5, 4, exists(10)).unwrap(), None);
+
+        // A lake a few minutes behind is found with a handful of probes
+        probes.set(0);
+        find_last(2, 60_000_000, exists(59_999_950)).unwrap();
+        assert!(probes.get() <= 14, "{} probes", probes.get());
+    }
+
+    #[test]
+    fn test_last_in_lake_rounds_to_batches() {
+        let config = Config { ledgers_per_batch: 64, ..Config::default() };
+
+        // Batches up to the one starting at 640 are exported
+        let exported = |url: &str| {
+            let start: u32 = url.rsplit("--").next().unwrap()
+                .split(['-', '.']).next().unwrap()
+                .parse().unwrap();
+            Ok(start <= 640)
+        };
+
+        assert_eq!(last_in_lake(&config, 100, 1000, exported).unwrap(), Some(703));
+        assert_eq!(last_in_lake(&config, 100, 650, exported).unwrap(), Some(650));
+        assert_eq!(last_in_lake(&config, 800, 1000, exported).unwrap(), None);
+    }
+}
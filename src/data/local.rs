@@ -0,0 +1,128 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use stellar_xdr::curr::LedgerCloseMetaBatch;
+
+use crate::config::Config;
+use crate::data::error::FetchError;
+use crate::data::lake::last_in_lake;
+use crate::data::s3::{object_exists, open_decompressed};
+use crate::data::source::{LedgerReader, LedgerSource};
+use crate::data::xdr::BatchReader;
+
+/// Resolve a data lake URL to a filesystem path
+///
+/// Accepts `file://` URLs and plain paths (anything without a URL scheme).
+/// Returns `None` for remote URLs such as `https://`.
+pub fn local_path(url: &str) -> Option<PathBuf> {
+    if let Some(path) = url.strip_prefix("file://") {
+        Some(PathBuf::from(path))
+    } else if url.contains("://") {
+        None
+    } else {
+        Some(PathBuf::from(url))
+    }
+}
+
+/// Open an object of a local data lake mirror
+pub fn open_local_object(path: &Path, silent: bool) -> Result<File, FetchError> {
+    if !silent {
+        println!("Reading data from: {}", path.display());
+    }
+
+    match File::open(path) {
+        Ok(file) => Ok(file),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(FetchError::NotFound(format!(
+            "Ledger data not found ({}). The ledger may not be in the local data lake yet.",
+            path.display()
+        ))),
+        Err(e) => Err(FetchError::Io(format!("Failed to read {}: {}", path.display(), e))),
+    }
+}
+
+/// Ledgers from a local data lake mirror (`file://` URL or plain path)
+pub struct LocalSource {
+    config: Arc<Config>,
+    silent: bool,
+}
+
+impl LocalSource {
+    pub fn new(config: Arc<Config>, silent: bool) -> Self {
+        LocalSource { config, silent }
+    }
+}
+
+impl LedgerSource for LocalSource {
+    fn name(&self) -> String {
+        "local".to_string()
+    }
+
+    fn fetch_batch(&self, ledger_seq: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+        self.read_batch(ledger_seq, ledger_seq)?.into_batch()
+    }
+
+    fn read_batch(&self, ledger_seq: u32, _end: u32) -> Result<LedgerReader, FetchError> {
+        let url = self.config.generate_url(ledger_seq);
+        if local_path(&url).is_none() {
+            return Err(FetchError::InvalidUrl(format!("{} is not a local data lake path", url)));
+        }
+
+        Ok(BatchReader::new(open_decompressed(&url, &self.config, self.silent)?)?.into())
+    }
+
+    fn ledgers_per_batch(&self) -> u32 {
+        self.config.ledgers_per_batch
+    }
+
+    fn last_available(&self, start: u32, end: u32) -> Result<Option<u32>, FetchError> {
+        last_in_lake(&self.config, start, end, |url| object_exists(url, &self.config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use crate::testing::{write_object, TempDir};
+
+    #[test]
+    fn test_local_path() {
+        assert_eq!(local_path("file:///data/lake"), Some(PathBuf::from("/data/lake")));
+        assert_eq!(local_path("/data/lake"), Some(PathBuf::from("/data/lake")));
+        assert_eq!(local_path("./lake"), Some(PathBuf::from("./lake")));
+        assert_eq!(local_path("https://aws-public-blockchain.s3.us-east-2.amazonaws.com"), None);
+    }
+
+    #[test]
+    fn test_fetch_from_local_data_lake() {
+        let root = TempDir::new("local");
+        let config = Config { base_url: root.path().display().to_string(), ..Config::default() };
+        write_object(&config, 63864, b"ledger bytes");
+
+        for base_url in [root.path().display().to_string(), format!("file://{}", root.path().display())] {
+            let config = Config { base_url, ..config.clone() };
+            let mut data = Vec::new();
+            open_decompressed(&config.generate_url(63864), &config, true).unwrap()
+                .read_to_end(&mut data).unwrap();
+            assert_eq!(data, b"ledger bytes");
+
+            let err = open_decompressed(&config.generate_url(63865), &config, true).err().unwrap();
+            assert!(err.is_not_found());
+        }
+    }
+
+    #[test]
+    fn test_last_available_in_local_data_lake() {
+        let root = TempDir::new("local");
+        let config = Config { base_url: root.path().display().to_string(), ..Config::default() };
+        for seq in 100..=110 {
+            write_object(&config, seq, b"ledger bytes");
+        }
+
+        let source = LocalSource::new(Arc::new(config), true);
+        assert_eq!(source.last_available(100, 200).unwrap(), Some(110));
+        assert_eq!(source.last_available(100, 105).unwrap(), Some(105));
+        assert_eq!(source.last_available(111, 200).unwrap(), None);
+    }
+}
@@ -0,0 +1,164 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::data::s3::fetch_object;
+
+/// Datastore manifest written by Galexie as `.config.json`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatastoreManifest {
+    pub network_passphrase: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub compression: String,
+    pub ledgers_per_batch: u32,
+    pub batches_per_partition: u32,
+}
+
+impl DatastoreManifest {
+    /// Parse a manifest from its JSON text
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        serde_json::from_slice(data).context("Failed to parse datastore manifest")
+    }
+}
+
+/// Location of the datastore manifest for the configured data lake
+pub fn manifest_url(config: &Config) -> String {
+    format!("{}/{}/.config.json", config.base_url, config.ledgers_path)
+}
+
+/// Fetch the datastore manifest, if the data lake has one
+pub fn fetch_manifest(config: &Config) -> Result<Option<DatastoreManifest>> {
+    match fetch_object(&manifest_url(config), config, true) {
+        Ok(data) => DatastoreManifest::parse(&data).map(Some),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e).context("Failed to fetch datastore manifest"),
+    }
+}
+
+/// Apply a manifest's layout to the configuration
+///
+/// Fails when the manifest belongs to another network or uses a layout this
+/// tool cannot read.
+pub fn apply_manifest(config: &mut Config, manifest: &DatastoreManifest) -> Result<()> {
+    if manifest.network_passphrase != config.network_passphrase {
+        anyhow::bail!(
+            "Data lake at {}/{} holds ledgers for '{}', but network {} uses '{}'",
+            config.base_url,
+            config.ledgers_path,
+            manifest.network_passphrase,
+            config.network.as_str(),
+            config.network_passphrase
+        );
+    }
+
+    if !manifest.compression.is_empty() && manifest.compression != "zstd" {
+        anyhow::bail!("Unsupported data lake compression: {}", manifest.compression);
+    }
+
+    if manifest.ledgers_per_batch == 0 || manifest.batches_per_partition == 0 {
+        anyhow::bail!("Invalid datastore manifest: ledgersPerBatch and batchesPerPartition must be at least 1");
+    }
+
+    config.ledgers_per_batch = manifest.ledgers_per_batch;
+    config.batches_per_partition = manifest.batches_per_partition;
+    Ok(())
+}
+
+/// Discover the data lake layout from its manifest
+///
+/// Data lakes without a manifest keep the configured layout, as do those
+/// whose manifest can't be fetched or parsed, with a warning. A manifest
+/// that doesn't fit the configuration is an error.
+pub fn discover_layout(config: &mut Config) -> Result<()> {
+    match fetch_manifest(config) {
+        Ok(Some(manifest)) => apply_manifest(config, &manifest),
+        Ok(None) => Ok(()),
+        Err(e) => {
+            eprintln!("Warning: could not read data lake manifest, using the configured layout: {:#}", e);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBNET_MANIFEST: &str = r#"{
+        "networkPassphrase": "Public Global Stellar Network ; September 2015",
+        "version": "1.0",
+        "compression": "zstd",
+        "ledgersPerBatch": 1,
+        "batchesPerPartition": 64000
+    }"#;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = DatastoreManifest::parse(PUBNET_MANIFEST.as_bytes()).unwrap();
+        assert_eq!(manifest.network_passphrase, "Public Global Stellar Network ; September 2015");
+        assert_eq!(manifest.compression, "zstd");
+        assert_eq!(manifest.ledgers_per_batch, 1);
+        assert_eq!(manifest.batches_per_partition, 64000);
+    }
+
+    #[test]
+    fn test_manifest_url() {
+        assert_eq!(
+            manifest_url(&Config::default()),
+            "https://aws-public-blockchain.s3.us-east-2.amazonaws.com/v1.1/stellar/ledgers/pubnet/.config.json"
+        );
+    }
+
+    #[test]
+    fn test_apply_manifest_layout() {
+        let mut config = Config::default();
+        let manifest = DatastoreManifest {
+            ledgers_per_batch: 64,
+            batches_per_partition: 10,
+            ..DatastoreManifest::parse(PUBNET_MANIFEST.as_bytes()).unwrap()
+        };
+
+        apply_manifest(&mut config, &manifest).unwrap();
+        assert_eq!(config.ledgers_per_batch, 64);
+        assert_eq!(config.batches_per_partition, 10);
+    }
+
+    #[test]
+    fn test_apply_manifest_rejects_other_network() {
+        let mut config = Config::for_network(crate::config::Network::Testnet);
+        let manifest = DatastoreManifest::parse(PUBNET_MANIFEST.as_bytes()).unwrap();
+
+        let err = apply_manifest(&mut config, &manifest).unwrap_err();
+        assert!(err.to_string().contains("network testnet"));
+    }
+
+    #[test]
+    fn test_apply_manifest_rejects_unknown_compression() {
+        let mut config = Config::default();
+        let manifest = DatastoreManifest {
+            compression: "gzip".to_string(),
+            ..DatastoreManifest::parse(PUBNET_MANIFEST.as_bytes()).unwrap()
+        };
+
+        assert!(apply_manifest(&mut config, &manifest).is_err());
+    }
+
+    #[test]
+    fn test_discover_layout_keeps_configured_layout_when_unreadable() {
+        let dir = crate::testing::TempDir::new("manifest");
+        let mut config = Config { base_url: dir.path().display().to_string(), ..Config::default() };
+        let manifest = std::path::PathBuf::from(manifest_url(&config));
+        std::fs::create_dir_all(manifest.parent().unwrap()).unwrap();
+
+        std::fs::write(&manifest, "not json").unwrap();
+        discover_layout(&mut config).unwrap();
+        assert_eq!((config.ledgers_per_batch, config.batches_per_partition), (1, 64000));
+
+        std::fs::write(&manifest, PUBNET_MANIFEST.replace("\"ledgersPerBatch\": 1", "\"ledgersPerBatch\": 64")).unwrap();
+        discover_layout(&mut config).unwrap();
+        assert_eq!(config.ledgers_per_batch, 64);
+    }
+}
@@ -0,0 +1,39 @@
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Bytes received from data lakes and RPC endpoints by this process
+static DOWNLOADED: AtomicU64 = AtomicU64::new(0);
+
+/// Count bytes received over the network
+pub fn record_download(bytes: usize) {
+    DOWNLOADED.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+/// Bytes received over the network so far, as shown in range progress
+pub fn downloaded_bytes() -> u64 {
+    DOWNLOADED.load(Ordering::Relaxed)
+}
+
+/// A response body whose bytes are counted as they are read
+pub struct Counted<R>(pub R);
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        record_download(n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_bytes_read() {
+        let before = downloaded_bytes();
+        let mut data = Vec::new();
+        Counted(&b"ledger bytes"[..]).read_to_end(&mut data).unwrap();
+        assert!(downloaded_bytes() - before >= 12);
+    }
+}
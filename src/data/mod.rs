@@ -1,6 +1,18 @@
+pub mod cache;
+pub mod error;
 pub mod s3;
+pub mod sigv4;
+pub mod retry;
+pub mod local;
+pub mod lake;
+pub mod manifest;
+pub mod source;
+pub mod fetcher;
+pub mod pipeline;
 pub mod rpc;
+pub mod endpoints;
+pub mod metrics;
 pub mod xdr;
+pub mod tokens;
 
-pub use xdr::parse_xdr;
 pub use rpc::{query_balance, query_price};
@@ -0,0 +1,285 @@
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+
+use crate::data::error::FetchError;
+use crate::data::fetcher::{FetchedLedger, LedgerFetcher};
+use crate::data::source::LedgerSource;
+
+/// A fetched ledger, or why it could not be fetched
+pub type LedgerResult = (u32, Result<FetchedLedger, FetchError>);
+
+/// A run of ledgers from one batch file, fetched by a single worker
+struct Job {
+    start: u32,
+    end: u32,
+    /// Where the worker hands over each ledger as soon as it is decoded
+    results: SyncSender<LedgerResult>,
+}
+
+/// Fetches a ledger range from a source on a pool of worker threads
+///
+/// Work is split along batch file boundaries so every batch is downloaded
+/// once. Results are yielded in ledger order. Each worker hands its ledgers
+/// over one at a time, waiting until the consumer takes them, and workers
+/// only run a bounded window of batches ahead of the consumer, so at most
+/// one decoded ledger per worker is held however long the range or large
+/// the batch files.
+pub struct LedgerStream {
+    jobs: Option<Sender<Job>>,
+    ledgers_per_batch: u32,
+    next_start: Option<u32>,
+    end: u32,
+    window: usize,
+    /// Results of the dispatched jobs, in ledger order
+    pending: VecDeque<Pending>,
+}
+
+/// A dispatched job, with the next ledger it is due to hand over
+struct Pending {
+    next: Option<u32>,
+    end: u32,
+    results: Receiver<LedgerResult>,
+}
+
+impl LedgerStream {
+    /// Start fetching the ledgers `start..=end` with up to `concurrency` workers
+    pub fn new(source: Arc<dyn LedgerSource>, start: u32, end: u32, concurrency: usize) -> Self {
+        let concurrency = concurrency.max(1);
+        let (job_tx, job_rx) = channel::<Job>();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+
+        for _ in 0..concurrency {
+            let source = Arc::clone(&source);
+            let jobs = Arc::clone(&job_rx);
+
+            std::thread::spawn(move || {
+                let mut fetcher = LedgerFetcher::new(Arc::clone(&source), end);
+                loop {
+                    let job = match jobs.lock().map(|jobs| jobs.recv()) {
+                        Ok(Ok(job)) => job,
+                        _ => return,
+                    };
+
+                    // A panic fails the rest of the job rather than the whole range
+                    let mut next = job.start;
+                    let handed_over = catch_unwind(AssertUnwindSafe(|| {
+                        (job.start..=job.end).all(|seq| {
+                            next = seq;
+                            job.results.send((seq, fetcher.fetch(seq))).is_ok()
+                        })
+                    }));
+                    let error = match handed_over {
+                        Ok(true) => continue,
+                        // The stream was dropped
+                        Ok(false) => return,
+                        Err(panic) => FetchError::Internal(format!(
+                            "Worker panicked fetching ledger {}: {}",
+                            next,
+                            panic_message(&*panic)
+                        )),
+                    };
+
+                    fetcher = LedgerFetcher::new(Arc::clone(&source), end);
+                    for seq in next..=job.end {
+                        if job.results.send((seq, Err(error.clone()))).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+
+        let mut stream = LedgerStream {
+            jobs: Some(job_tx),
+            ledgers_per_batch: source.ledgers_per_batch().max(1),
+            next_start: (start <= end).then_some(start),
+            end,
+            window: concurrency * 2,
+            pending: VecDeque::new(),
+        };
+        stream.dispatch();
+        stream
+    }
+
+    /// Hand out jobs until the read-ahead window is full
+    fn dispatch(&mut self) {
+        let Some(jobs) = &self.jobs else { return };
+
+        while self.pending.len() < self.window {
+            let Some(start) = self.next_start else { break };
+            let batch_start = (start / self.ledgers_per_batch) * self.ledgers_per_batch;
+            let end = batch_start.saturating_add(self.ledgers_per_batch - 1).min(self.end);
+
+            let (results, receiver) = sync_channel(0);
+            if jobs.send(Job { start, end, results }).is_err() {
+                break;
+            }
+            self.pending.push_back(Pending { next: Some(start), end, results: receiver });
+            self.next_start = end.checked_add(1).filter(|next| *next <= self.end);
+        }
+    }
+}
+
+impl Iterator for LedgerStream {
+    type Item = LedgerResult;
+
+    fn next(&mut self) -> Option<LedgerResult> {
+        loop {
+            let Some(job) = self.pending.front_mut() else {
+                self.jobs = None;
+                return None;
+            };
+
+            // Wait for the next ledger of the job first in ledger order
+            if let Ok((seq, result)) = job.results.recv() {
+                job.next = seq.checked_add(1).filter(|next| *next <= job.end);
+                return Some((seq, result));
+            }
+
+            // Every worker stopped before finishing the job
+            if let Some(seq) = job.next {
+                job.next = seq.checked_add(1).filter(|next| *next <= job.end);
+                let error = FetchError::Internal(format!("No worker was left to fetch ledger {}", seq));
+                return Some((seq, Err(error)));
+            }
+
+            // The job is done; start another in its place
+            self.pending.pop_front();
+            self.dispatch();
+        }
+    }
+}
+
+/// Text of a caught panic
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> &str {
+    panic.downcast_ref::<&str>().copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("worker panicked")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use stellar_xdr::curr::LedgerCloseMetaBatch;
+    use std::time::Duration;
+    use crate::data::source::{from_config, LedgerReader};
+    use crate::data::xdr::ledger_sequence;
+    use crate::data::xdr::tests::test_ledger;
+    use crate::testing::{lake_config, write_lake, TempDir};
+
+    #[test]
+    fn test_stream_yields_in_ledger_order() {
+        let root = TempDir::new("pipeline");
+        let config = lake_config(root.path(), 1, 4);
+        write_lake(&config, 100, 140);
+
+        let stream = LedgerStream::new(from_config(Arc::new(config), true), 100, 140, 8);
+        let sequences: Vec<u32> = stream
+            .map(|(seq, result)| {
+                let batch = result.unwrap().batch;
+                assert_eq!(ledger_sequence(&batch.ledger_close_metas[0]), seq);
+                seq
+            })
+            .collect();
+
+        assert_eq!(sequences, (100..=140).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_stream_splits_on_batch_boundaries() {
+        let root = TempDir::new("pipeline");
+        let config = lake_config(root.path(), 8, 4);
+        write_lake(&config, 5, 30);
+
+        let stream = LedgerStream::new(from_config(Arc::new(config), true), 5, 30, 3);
+        let sequences: Vec<u32> = stream.map(|(seq, result)| {
+            result.unwrap();
+            seq
+        }).collect();
+
+        assert_eq!(sequences, (5..=30).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_stream_reports_missing_ledgers_in_place() {
+        let root = TempDir::new("pipeline");
+        let mut config = lake_config(root.path(), 1, 4);
+        write_lake(&config, 10, 12);
+        std::fs::remove_file(config.generate_url(11)).unwrap();
+        // Keep the RPC fallback local so the test never touches the network
+        config.rpc_url = "http://127.0.0.1:9/".to_string();
+        config.retry.retries = 0;
+
+        let results: Vec<LedgerResult> = LedgerStream::new(from_config(Arc::new(config), true), 10, 12, 2).collect();
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].1.is_ok());
+        assert_eq!(results[1].0, 11);
+        assert!(results[1].1.is_err());
+        assert!(results[2].1.is_ok());
+    }
+
+    /// Batches of 64 ledgers, counting every ledger decoded and panicking on one
+    struct CountingSource(Arc<AtomicUsize>, Option<u32>);
+
+    impl LedgerSource for CountingSource {
+        fn name(&self) -> String {
+            "counting".to_string()
+        }
+
+        fn fetch_batch(&self, ledger_seq: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+            self.read_batch(ledger_seq, ledger_seq)?.into_batch()
+        }
+
+        fn read_batch(&self, ledger_seq: u32, _end: u32) -> Result<LedgerReader, FetchError> {
+            let start = ledger_seq / 64 * 64;
+            let (decoded, panic_at) = (Arc::clone(&self.0), self.1);
+            Ok(LedgerReader::new(start, start + 63, (start..=start + 63).map(move |seq| {
+                if Some(seq) == panic_at {
+                    panic!("bad ledger");
+                }
+                decoded.fetch_add(1, Ordering::SeqCst);
+                Ok(test_ledger(seq))
+            })))
+        }
+
+        fn ledgers_per_batch(&self) -> u32 {
+            64
+        }
+    }
+
+    #[test]
+    fn test_stream_holds_one_ledger_per_worker() {
+        let decoded = Arc::new(AtomicUsize::new(0));
+        let mut stream = LedgerStream::new(Arc::new(CountingSource(Arc::clone(&decoded), None)), 0, 64 * 8 - 1, 2);
+
+        assert_eq!(stream.next().unwrap().0, 0);
+        std::thread::sleep(Duration::from_millis(100));
+        // Each worker waits with the ledger it decoded next, rather than reading its batch ahead
+        assert!(decoded.load(Ordering::SeqCst) <= 3);
+
+        assert_eq!(stream.map(|(seq, _)| seq).collect::<Vec<_>>(), (1..64 * 8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_stream_survives_worker_panics() {
+        let source = CountingSource(Arc::new(AtomicUsize::new(0)), Some(100));
+        let results: Vec<LedgerResult> = LedgerStream::new(Arc::new(source), 0, 64 * 3 - 1, 1).collect();
+
+        assert_eq!(results.len(), 64 * 3);
+        for (seq, result) in results {
+            match result {
+                Err(e) => {
+                    assert!((100..128).contains(&seq));
+                    assert_eq!(e.kind(), "internal");
+                    assert!(e.to_string().contains("ledger 100: bad ledger"));
+                }
+                // The worker carries on with the next batch
+                Ok(_) => assert!(!(100..128).contains(&seq)),
+            }
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::RetrySettings;
+use crate::data::error::FetchError;
+
+/// Stops calling an endpoint after repeated transient failures
+///
+/// Once `threshold` consecutive transient failures are seen the breaker
+/// opens and requests fail immediately for `cooldown`. The first request
+/// after the cooldown is let through; a success closes the breaker, another
+/// failure opens it again.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: &'static str,
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, settings: &RetrySettings) -> Self {
+        CircuitBreaker {
+            name,
+            threshold: settings.circuit_breaker_threshold,
+            cooldown: Duration::from_secs(settings.circuit_breaker_cooldown_secs.into()),
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Fail fast while the breaker is open
+    pub fn check(&self) -> Result<(), FetchError> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.open_until {
+            Some(until) if Instant::now() < until => Err(FetchError::Network(format!(
+                "{} circuit breaker is open after {} consecutive failures",
+                self.name, state.failures
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record the outcome of a request
+    pub fn record(&self, result: Result<(), &FetchError>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match result {
+            Err(e) if e.is_transient() => {
+                state.failures += 1;
+                if self.threshold > 0 && state.failures >= self.threshold {
+                    state.open_until = Some(Instant::now() + self.cooldown);
+                }
+            }
+            // Any answer from the server, even a 404, means it is reachable
+            _ => *state = BreakerState::default(),
+        }
+    }
+}
+
+/// Delay before retry number `attempt` (starting at 0)
+///
+/// Throttled responses wait for their `Retry-After`, up to the maximum
+/// delay; everything else uses exponential backoff with jitter, between
+/// half and all of the backoff.
+pub fn retry_delay(settings: &RetrySettings, attempt: u32, err: &FetchError) -> Duration {
+    if let FetchError::Throttled { retry_after: Some(delay), .. } = err {
+        return (*delay).min(Duration::from_millis(settings.max_delay_ms.into()));
+    }
+
+    let backoff = u64::from(settings.base_delay_ms)
+        .saturating_mul(1u64 << attempt.min(32))
+        .min(settings.max_delay_ms.into());
+    let half = backoff / 2;
+    Duration::from_millis(half + fastrand::u64(0..=backoff - half))
+}
+
+/// Run a request, retrying transient failures with backoff
+pub fn with_retry<T>(
+    settings: &RetrySettings,
+    breaker: &CircuitBreaker,
+    mut request: impl FnMut() -> Result<T, FetchError>,
+) -> Result<T, FetchError> {
+    let mut attempt = 0;
+
+    loop {
+        breaker.check()?;
+
+        let result = request();
+        breaker.record(result.as_ref().map(|_| ()));
+
+        match result {
+            Err(e) if e.is_transient() && attempt < settings.retries => {
+                std::thread::sleep(retry_delay(settings, attempt, &e));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn settings() -> RetrySettings {
+        RetrySettings {
+            retries: 3,
+            base_delay_ms: 1,
+            max_delay_ms: 4,
+            circuit_breaker_threshold: 0,
+            ..RetrySettings::default()
+        }
+    }
+
+    #[test]
+    fn test_retries_transient_failures() {
+        let settings = settings();
+        let breaker = CircuitBreaker::new("S3", &settings);
+        let calls = Cell::new(0);
+
+        let result = with_retry(&settings, &breaker, || {
+            calls.set(calls.get() + 1);
+            if calls.get() < 3 {
+                Err(FetchError::Network("timeout".to_string()))
+            } else {
+                Ok(calls.get())
+            }
+        });
+
+        assert_eq!(result, Ok(3));
+    }
+
+    #[test]
+    fn test_gives_up_after_retries() {
+        let settings = settings();
+        let breaker = CircuitBreaker::new("S3", &settings);
+        let calls = Cell::new(0);
+
+        let result: Result<(), _> = with_retry(&settings, &breaker, || {
+            calls.set(calls.get() + 1);
+            Err(FetchError::Network("timeout".to_string()))
+        });
+
+        assert!(result.unwrap_err().is_transient());
+        assert_eq!(calls.get(), 4);
+    }
+
+    #[test]
+    fn test_does_not_retry_permanent_failures() {
+        let settings = settings();
+        let breaker = CircuitBreaker::new("S3", &settings);
+        let calls = Cell::new(0);
+
+        let result: Result<(), _> = with_retry(&settings, &breaker, || {
+            calls.set(calls.get() + 1);
+            Err(FetchError::NotFound("missing".to_string()))
+        });
+
+        assert!(result.unwrap_err().is_not_found());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_backoff_is_bounded_with_jitter() {
+        let settings = RetrySettings { base_delay_ms: 100, max_delay_ms: 1000, ..RetrySettings::default() };
+        let err = FetchError::Network("timeout".to_string());
+
+        for _ in 0..20 {
+            let first = retry_delay(&settings, 0, &err).as_millis();
+            assert!((50..=100).contains(&first));
+
+            let third = retry_delay(&settings, 2, &err).as_millis();
+            assert!((200..=400).contains(&third));
+
+            let capped = retry_delay(&settings, 40, &err).as_millis();
+            assert!((500..=1000).contains(&capped));
+        }
+    }
+
+    #[test]
+    fn test_throttled_waits_for_retry_after() {
+        let settings = RetrySettings { max_delay_ms: 10_000, ..RetrySettings::default() };
+        let err = FetchError::Throttled { message: "slow down".to_string(), retry_after: Some(Duration::from_secs(7)) };
+        assert_eq!(retry_delay(&settings, 0, &err), Duration::from_secs(7));
+
+        // A server asking for an hour doesn't park the worker that long
+        let err = FetchError::Throttled { message: "slow down".to_string(), retry_after: Some(Duration::from_secs(3600)) };
+        assert_eq!(retry_delay(&settings, 0, &err), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_circuit_breaker_opens_and_fails_fast() {
+        let settings = RetrySettings {
+            retries: 0,
+            circuit_breaker_threshold: 2,
+            circuit_breaker_cooldown_secs: 60,
+            ..RetrySettings::default()
+        };
+        let breaker = CircuitBreaker::new("RPC", &settings);
+        let calls = Cell::new(0);
+        let mut failing = || -> Result<(), FetchError> {
+            calls.set(calls.get() + 1);
+            Err(FetchError::Network("connection refused".to_string()))
+        };
+
+        assert!(with_retry(&settings, &breaker, &mut failing).is_err());
+        assert!(with_retry(&settings, &breaker, &mut failing).is_err());
+        let err = with_retry(&settings, &breaker, &mut failing).unwrap_err();
+
+        assert_eq!(calls.get(), 2);
+        assert!(err.to_string().contains("RPC circuit breaker is open"));
+    }
+
+    #[test]
+    fn test_circuit_breaker_resets_on_success() {
+        let settings = RetrySettings { circuit_breaker_threshold: 2, ..RetrySettings::default() };
+        let breaker = CircuitBreaker::new("S3", &settings);
+        let transient = FetchError::Network("timeout".to_string());
+
+        breaker.record(Err(&transient));
+        breaker.record(Ok(()));
+        breaker.record(Err(&transient));
+        assert!(breaker.check().is_ok());
+    }
+}
@@ -1,13 +1,18 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, OnceLock};
+
 use anyhow::{Context, Result};
 use stellar_xdr::curr::{LedgerCloseMeta, LedgerCloseMetaBatch, Limits, ReadXdr, WriteXdr};
 use stellar_strkey::Strkey;
 use crate::config::Config;
-
-/// Reflector oracle contract addresses
-const REFLECTOR_STELLAR_CONTRACT: &str = "CALI2BYU2JE6WVRUFYTS6MSBNEHGJ35P4AVCZYF3B6QOE3QKOB2PLE6M";
-const REFLECTOR_CRYPTO_CONTRACT: &str = "CAFJZQWSED6YAWZU3GWRTOCNPPCGBN32L7QV43XX5LZLFTK6JLN34DLN";
-const REFLECTOR_FIAT_CONTRACT: &str = "CBKGPWGKSKZF52CFHMTRR23TBWTPMRDIYZ4O2P5VS65BMHYH4DXMCJZC";
-const REFLECTOR_DECIMALS: u32 = 14;
+use crate::config::tokens::DEFAULT_DECIMALS;
+use crate::data::cache::LedgerCache;
+use crate::data::error::{retry_after, FetchError};
+use crate::data::endpoints::{self, with_failover};
+use crate::data::metrics::record_download;
+use crate::data::retry::{with_retry, CircuitBreaker};
+use crate::data::source::{LedgerReader, LedgerSource};
+use crate::data::xdr::{ledger_sequence, parse_xdr, single_batch};
 
 /// Crypto assets that use the crypto oracle
 const CRYPTO_ASSETS: &[&str] = &[
@@ -22,166 +27,414 @@ const FIAT_ASSETS: &[&str] = &[
     "RUB", "ZAR", "XAU"
 ];
 
-/// Determine which Reflector oracle to use based on the asset
-fn get_oracle_for_asset(asset: &str) -> &'static str {
+/// Determine which Reflector oracle to use based on the asset, failing if
+/// the network doesn't have it
+fn get_oracle_for_asset<'a>(asset: &str, config: &'a Config) -> Result<&'a str> {
     let asset_upper = asset.to_uppercase();
 
     if CRYPTO_ASSETS.contains(&asset_upper.as_str()) {
-        REFLECTOR_CRYPTO_CONTRACT
+        config.reflector("crypto")
     } else if FIAT_ASSETS.contains(&asset_upper.as_str()) {
-        REFLECTOR_FIAT_CONTRACT
+        config.reflector("fiat")
     } else {
         // Default to Stellar contract for contract addresses and other assets
-        REFLECTOR_STELLAR_CONTRACT
+        config.reflector("stellar")
     }
 }
 
-/// RPC response structures (currently unused, kept for reference)
-#[allow(dead_code)]
-#[derive(serde::Deserialize)]
+/// Pages of ledgers kept by an `RpcSource` for the workers of a range query
+const MAX_PAGES: usize = 4;
+
+/// `getLedgers` result
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct RpcLedgerResponse {
     ledgers: Vec<RpcLedger>,
+    latest_ledger: u32,
+    /// Where the next page starts
+    #[serde(default)]
+    cursor: String,
 }
 
-#[allow(dead_code)]
-#[derive(serde::Deserialize)]
+/// A ledger in a `getLedgers` result
+#[derive(Debug, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct RpcLedger {
     sequence: u32,
     metadata_xdr: String,
 }
 
-/// Fetch ledger data from RPC when S3 doesn't have it yet
-pub fn fetch_from_rpc(ledger_seq: u32, silent: bool) -> Result<Vec<u8>> {
-    if !silent {
-        println!("Ledger not in S3, fetching from RPC archive...");
+impl RpcLedger {
+    /// Decode the base64 `LedgerCloseMeta`
+    fn decode(&self) -> Result<LedgerCloseMeta, FetchError> {
+        LedgerCloseMeta::from_xdr_base64(&self.metadata_xdr, Limits::none()).map_err(|e| {
+            FetchError::Decode(format!("Failed to decode metadataXdr of ledger {} from RPC: {}", self.sequence, e))
+        })
     }
+}
+
+/// `getLatestLedger` result
+#[derive(Debug, serde::Deserialize)]
+struct RpcLatestLedger {
+    sequence: u32,
+}
 
-    let client = reqwest::blocking::Client::new();
-    let rpc_request = serde_json::json!({
+/// `getHealth` result: the state of an RPC and the ledgers it keeps
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcHealth {
+    pub status: String,
+    pub latest_ledger: u32,
+    pub oldest_ledger: u32,
+    pub ledger_retention_window: u32,
+}
+
+/// Call a JSON-RPC method and decode its `result`
+///
+/// Invalid params errors, which the RPC also uses for ledgers outside its
+/// retention window, are returned as `NotFound`.
+fn rpc_call<T: serde::de::DeserializeOwned>(
+    config: &Config,
+    url: &str,
+    method: &str,
+    params: Option<serde_json::Value>,
+) -> Result<T, FetchError> {
+    let mut rpc_request = serde_json::json!({
         "jsonrpc": "2.0",
         "id": 1,
-        "method": "getLedgers",
-        "params": {
-            "startLedger": ledger_seq,
-            "pagination": {
-                "limit": 1
-            }
-        }
+        "method": method
     });
+    if let Some(params) = params {
+        rpc_request["params"] = params;
+    }
 
-    let response = client
-        .post(Config::rpc_url())
+    let response = reqwest::blocking::Client::new()
+        .post(url)
         .json(&rpc_request)
+        .timeout(std::time::Duration::from_secs(config.retry.timeout_secs.into()))
         .send()
-        .context("Failed to call RPC")?;
+        .map_err(|e| FetchError::from_reqwest(e, "RPC"))?;
 
-    if !response.status().is_success() {
-        anyhow::bail!("RPC returned error status: {}", response.status());
+    let status = response.status();
+    if !status.is_success() {
+        return Err(FetchError::from_status(status, retry_after(response.headers()), "RPC"));
     }
 
-    let json: serde_json::Value = response.json()
-        .context("Failed to parse RPC response")?;
+    let body = response.bytes()
+        .map_err(|e| FetchError::from_reqwest(e, "RPC"))?;
+    record_download(body.len());
+    let mut json: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| FetchError::Rpc(format!("Failed to parse RPC response: {}", e)))?;
 
     if let Some(error) = json.get("error") {
-        anyhow::bail!("RPC error: {}", error);
+        if error.get("code").and_then(|c| c.as_i64()) == Some(-32602) {
+            return Err(FetchError::NotFound(error.to_string()));
+        }
+        return Err(FetchError::Rpc(format!("RPC error: {}", error)));
     }
 
-    let result = json.get("result")
-        .ok_or_else(|| anyhow::anyhow!("No result in RPC response"))?;
+    let result = json.get_mut("result")
+        .map(serde_json::Value::take)
+        .ok_or_else(|| FetchError::Rpc("No result in RPC response".to_string()))?;
 
-    let ledgers = result.get("ledgers")
-        .and_then(|l| l.as_array())
-        .ok_or_else(|| anyhow::anyhow!("No ledgers in RPC response"))?;
+    serde_json::from_value(result)
+        .map_err(|e| FetchError::Rpc(format!("Unexpected {} response: {}", method, e)))
+}
 
-    if ledgers.is_empty() {
-        anyhow::bail!("Ledger {} not found in RPC", ledger_seq);
-    }
+/// Request one page of `getLedgers`, starting at a ledger or following a cursor
+fn get_ledgers(config: &Config, url: &str, start: u32, cursor: Option<&str>, limit: u32) -> Result<RpcLedgerResponse, FetchError> {
+    // The RPC rejects startLedger together with a cursor
+    let params = match cursor {
+        Some(cursor) => serde_json::json!({ "pagination": { "cursor": cursor, "limit": limit } }),
+        None => serde_json::json!({ "startLedger": start, "pagination": { "limit": limit } }),
+    };
+
+    rpc_call(config, url, "getLedgers", Some(params)).map_err(|e| match e {
+        FetchError::NotFound(error) => {
+            FetchError::NotFound(format!("Ledger {} not available from RPC: {}", start, error))
+        }
+        e => e,
+    })
+}
+
+/// Latest ledger known to an RPC
+pub fn get_latest_ledger(config: &Config, url: &str) -> Result<u32, FetchError> {
+    rpc_call::<RpcLatestLedger>(config, url, "getLatestLedger", None).map(|latest| latest.sequence)
+}
 
-    let ledger = &ledgers[0];
-    let metadata_xdr = ledger.get("metadataXdr")
-        .and_then(|m| m.as_str())
-        .ok_or_else(|| anyhow::anyhow!("No metadataXdr in RPC response"))?;
+/// Health and retention window of an RPC
+pub fn get_health(config: &Config, url: &str) -> Result<RpcHealth, FetchError> {
+    rpc_call(config, url, "getHealth", None)
+}
 
-    if !silent {
-        println!("Decoding base64 XDR from RPC...");
+/// Every configured RPC endpoint, archive endpoints first
+pub fn rpc_urls(config: &Config) -> Vec<&str> {
+    let mut urls = config.archive_rpc_urls();
+    for url in config.soroban_rpc_urls() {
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
     }
+    urls
+}
 
-    // Decode base64 to get the XDR bytes for LedgerCloseMeta
-    let ledger_close_meta = LedgerCloseMeta::from_xdr_base64(metadata_xdr, Limits::none())
-        .context("Failed to decode metadataXdr from RPC")?;
+/// Why ledgers `start..=end` can't be read from an RPC, if they are older than it keeps
+pub fn retention_error(url: &str, health: &RpcHealth, start: u32, end: u32) -> Option<String> {
+    if start >= health.oldest_ledger {
+        return None;
+    }
 
-    // Wrap it in a LedgerCloseMetaBatch (single ledger batch)
-    let batch = LedgerCloseMetaBatch {
-        start_sequence: ledger_seq,
-        end_sequence: ledger_seq,
-        ledger_close_metas: vec![ledger_close_meta].try_into()
-            .map_err(|_| anyhow::anyhow!("Failed to create VecM"))?,
-    };
+    Some(format!(
+        "Ledgers {}-{} are not in the data lake and are outside the retention window of RPC {} \
+         (it keeps the last {} ledgers, {}-{})",
+        start,
+        end.min(health.oldest_ledger - 1),
+        url,
+        health.ledger_retention_window,
+        health.oldest_ledger,
+        health.latest_ledger
+    ))
+}
 
-    // Serialize the batch to XDR bytes so it matches the S3 format
-    let xdr_bytes = batch.to_xdr(Limits::none())
-        .context("Failed to serialize batch to XDR")?;
+/// Fail when ledgers that must come from the archive RPC are older than
+/// every archive endpoint keeps
+///
+/// Nothing is checked when an endpoint's health can't be read; fetching will
+/// report the problem for each ledger instead.
+pub fn check_retention(config: &Config, start: u32, end: u32) -> Result<()> {
+    let mut errors = Vec::new();
+    for url in config.archive_rpc_urls() {
+        match endpoints::check(config, url) {
+            Ok(health) => match retention_error(url, &health, start, end) {
+                Some(error) => errors.push(error),
+                None => return Ok(()),
+            },
+            Err(_) => return Ok(()),
+        }
+    }
+
+    match errors.into_iter().next() {
+        Some(error) => Err(anyhow::anyhow!(error)),
+        None => Ok(()),
+    }
+}
+
+/// Collect up to `limit` consecutive ledgers from `start`, following cursors
+///
+/// The RPC may return fewer ledgers per call than asked for; paging stops
+/// once `limit` ledgers are collected or the latest ledger is reached. It
+/// also stops at a page that adds no ledger following on from the last
+/// one, and fails on a cursor that doesn't move, so an RPC that returns
+/// the same page over and over can't keep it paging forever.
+fn collect_ledgers(
+    start: u32,
+    limit: u32,
+    mut page: impl FnMut(Option<&str>, u32) -> Result<RpcLedgerResponse, FetchError>,
+) -> Result<Vec<RpcLedger>, FetchError> {
+    let mut ledgers: Vec<RpcLedger> = Vec::new();
+    let mut cursor: Option<String> = None;
+
+    while (ledgers.len() as u32) < limit {
+        let response = page(cursor.as_deref(), limit - ledgers.len() as u32)?;
+        let collected = ledgers.len();
+        let done = response.ledgers.is_empty()
+            || response.cursor.is_empty()
+            || response.ledgers.last().is_some_and(|ledger| ledger.sequence >= response.latest_ledger);
+
+        // Only keep the run of ledgers that continues from `start`
+        for ledger in response.ledgers {
+            let expected = ledgers.last().map_or(start, |last| last.sequence + 1);
+            if ledger.sequence == expected && (ledgers.len() as u32) < limit {
+                ledgers.push(ledger);
+            }
+        }
 
-    if !silent {
-        println!("Fetched {} bytes from RPC", xdr_bytes.len());
+        if done || ledgers.len() == collected {
+            break;
+        }
+        if cursor.as_deref() == Some(response.cursor.as_str()) {
+            return Err(FetchError::Rpc(format!("RPC returned cursor {} again while paging getLedgers", response.cursor)));
+        }
+        cursor = Some(response.cursor);
     }
 
-    Ok(xdr_bytes)
+    Ok(ledgers)
 }
 
-/// Query token balance for an address using Soroban RPC
+/// A run of ledgers fetched with one series of `getLedgers` calls
+struct Page {
+    start: u32,
+    /// Last ledger asked for; the RPC may stop earlier at its latest ledger
+    end: u32,
+    /// The ledgers, and the endpoint that served them
+    ledgers: OnceLock<Result<(Vec<LedgerCloseMeta>, String), FetchError>>,
+}
+
+/// Ledgers from the archive RPC, for those not in the data lake yet
 ///
-/// This uses the simulateTransaction RPC method with a minimal transaction envelope
-/// that calls the "balance" function on the token contract.
-pub fn query_balance(address: &str, token_contract: &str) -> Result<serde_json::Value> {
-    use stellar_xdr::curr::*;
+/// Ledgers are requested in pages of `rpc_page_size`, which the workers of a
+/// range query share: the first worker to ask for a ledger fetches its page
+/// and the others wait for it. Closed ledgers never change, so they are
+/// cached by network and sequence.
+pub struct RpcSource {
+    config: Arc<Config>,
+    cache: Option<Arc<LedgerCache>>,
+    breaker: CircuitBreaker,
+    silent: bool,
+    pages: Mutex<VecDeque<Arc<Page>>>,
+}
 
-    let client = reqwest::blocking::Client::new();
+impl RpcSource {
+    pub fn new(config: Arc<Config>, cache: Option<Arc<LedgerCache>>, silent: bool) -> Self {
+        let breaker = CircuitBreaker::new("RPC", &config.retry);
+        RpcSource { config, cache, breaker, silent, pages: Mutex::new(VecDeque::new()) }
+    }
 
-    // Decode the Stellar address to get the account ID bytes
-    let address_bytes = match Strkey::from_string(address) {
-        Ok(Strkey::PublicKeyEd25519(pk)) => pk.0,
-        _ => anyhow::bail!("Invalid Stellar address format"),
-    };
+    fn cache_key(&self, ledger_seq: u32) -> String {
+        format!("rpc:{}:{}", self.config.network_passphrase, ledger_seq)
+    }
+
+    /// The page holding a ledger, starting a new one up to `end` if none does
+    fn page_for(&self, ledger_seq: u32, end: u32) -> Arc<Page> {
+        let mut pages = self.pages.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(page) = pages.iter().find(|page| (page.start..=page.end).contains(&ledger_seq)) {
+            return Arc::clone(page);
+        }
+
+        let page = Arc::new(Page {
+            start: ledger_seq,
+            end: ledger_seq.saturating_add(self.config.rpc_page_size.max(1) - 1).min(end.max(ledger_seq)),
+            ledgers: OnceLock::new(),
+        });
+        pages.push_back(Arc::clone(&page));
+        if pages.len() > MAX_PAGES {
+            pages.pop_front();
+        }
+        page
+    }
+
+    /// Forget a page, so its ledgers are requested again next time
+    fn drop_page(&self, page: &Arc<Page>) {
+        self.pages.lock().unwrap_or_else(|e| e.into_inner()).retain(|p| !Arc::ptr_eq(p, page));
+    }
+
+    /// Fetch and decode a page, adding its ledgers to the cache
+    ///
+    /// Returns the endpoint that served the page along with its ledgers.
+    fn fetch_page(&self, start: u32, end: u32) -> Result<(Vec<LedgerCloseMeta>, String), FetchError> {
+        if !self.silent {
+            println!("Ledger not in S3, fetching ledgers {}-{} from RPC archive...", start, end);
+        }
+
+        let urls = self.config.archive_rpc_urls();
+        let mut served_by = String::new();
+        let ledgers = collect_ledgers(start, end - start + 1, |cursor, limit| {
+            let (response, url) = with_retry(&self.config.retry, &self.breaker, || {
+                with_failover(&self.config, &urls, |url| get_ledgers(&self.config, url, start, cursor, limit))
+            })?;
+            served_by = url;
+            Ok(response)
+        })?;
+
+        let metas = ledgers.iter().map(RpcLedger::decode).collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(cache) = &self.cache {
+            for meta in &metas {
+                let seq = ledger_sequence(meta);
+                if let Ok(xdr) = single_batch(seq, meta.clone()).and_then(|batch| {
+                    batch.to_xdr(Limits::none())
+                        .map_err(|e| FetchError::Decode(format!("Failed to serialize batch to XDR: {}", e)))
+                }) {
+                    let _ = cache.put(&self.cache_key(seq), &xdr);
+                }
+            }
+        }
+
+        if !self.silent {
+            println!("Fetched {} ledgers from RPC {}", metas.len(), served_by);
+        }
+        Ok((metas, served_by))
+    }
+
+    /// Fetch a ledger as a batch of its own, with the endpoint that served it
+    ///
+    /// Ledgers read from the cache have no endpoint.
+    fn fetch_served(&self, ledger_seq: u32, end: u32) -> Result<(LedgerCloseMetaBatch, Option<String>), FetchError> {
+        if let Some(cache) = &self.cache {
+            if let Some(batch) = cache.get(&self.cache_key(ledger_seq)).and_then(|data| parse_xdr(&data).ok()) {
+                return Ok((batch, None));
+            }
+        }
+
+        let page = self.page_for(ledger_seq, end);
+        let (ledgers, served_by) = match page.ledgers.get_or_init(|| self.fetch_page(page.start, page.end)) {
+            Ok(page) => page,
+            Err(e) => {
+                self.drop_page(&page);
+                return Err(e.clone());
+            }
+        };
+
+        match ledgers.get((ledger_seq - page.start) as usize) {
+            Some(meta) => Ok((single_batch(ledger_seq, meta.clone())?, Some(served_by.clone()))),
+            None => {
+                // Past the latest ledger when the page was fetched, it may exist by now
+                self.drop_page(&page);
+                Err(FetchError::NotFound(format!("Ledger {} not found in RPC", ledger_seq)))
+            }
+        }
+    }
+}
+
+impl LedgerSource for RpcSource {
+    fn name(&self) -> String {
+        "RPC".to_string()
+    }
+
+    fn fetch_batch(&self, ledger_seq: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+        self.fetch_batch_ahead(ledger_seq, ledger_seq)
+    }
+
+    fn fetch_batch_ahead(&self, ledger_seq: u32, end: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+        self.fetch_served(ledger_seq, end).map(|(batch, _)| batch)
+    }
+
+    fn read_batch(&self, ledger_seq: u32, end: u32) -> Result<LedgerReader, FetchError> {
+        let (batch, served_by) = self.fetch_served(ledger_seq, end)?;
+        let mut reader = LedgerReader::from(batch);
+        reader.served_by = served_by;
+        Ok(reader)
+    }
+}
+
+/// Build a minimal transaction invoking a contract function, as base64 XDR
+///
+/// The transaction is only ever simulated, so it uses a dummy source account
+/// and carries no signatures.
+fn build_invoke_transaction(contract: &str, function: &str, args: Vec<stellar_xdr::curr::ScVal>) -> Result<String> {
+    use stellar_xdr::curr::*;
 
     // Decode the contract address
-    let contract_bytes = match Strkey::from_string(token_contract) {
+    let contract_bytes = match Strkey::from_string(contract) {
         Ok(Strkey::Contract(contract)) => contract.0,
-        _ => anyhow::bail!("Invalid contract address format"),
+        _ => anyhow::bail!("Invalid contract address format: {}", contract),
     };
 
-    // Create the ScVal for the address parameter (Address type with Account variant)
-    let address_scval = ScVal::Address(ScAddress::Account(
-        AccountId(PublicKey::PublicKeyTypeEd25519(
-            Uint256(address_bytes),
-        )),
-    ));
-
     // Create function name as ScSymbol
-    let function_name = ScSymbol("balance".as_bytes().to_vec().try_into()
+    let function_name = ScSymbol(function.as_bytes().to_vec().try_into()
         .map_err(|_| anyhow::anyhow!("Function name too long"))?);
 
-    // Create contract address
-    use stellar_xdr::curr::ContractId;
-    let contract_address = ScAddress::Contract(
-        ContractId(Hash(contract_bytes)),
-    );
-
     // Build InvokeContractArgs
     let invoke_args = InvokeContractArgs {
-        contract_address,
+        contract_address: ScAddress::Contract(ContractId(Hash(contract_bytes))),
         function_name,
-        args: vec![address_scval].try_into()
+        args: args.try_into()
             .map_err(|_| anyhow::anyhow!("Failed to create args vec"))?,
     };
 
-    // Build HostFunction::InvokeContract
-    let host_function = HostFunction::InvokeContract(invoke_args);
-
     // Build InvokeHostFunctionOp
     let invoke_op = InvokeHostFunctionOp {
-        host_function,
+        host_function: HostFunction::InvokeContract(invoke_args),
         auth: vec![].try_into()
             .map_err(|_| anyhow::anyhow!("Failed to create auth vec"))?,
     };
@@ -214,76 +467,98 @@ pub fn query_balance(address: &str, token_contract: &str) -> Result<serde_json::
     });
 
     // Encode transaction to base64 XDR
-    let tx_xdr = tx_envelope.to_xdr_base64(Limits::none())
-        .context("Failed to encode transaction to XDR")?;
+    tx_envelope.to_xdr_base64(Limits::none())
+        .context("Failed to encode transaction to XDR")
+}
 
-    // Make the simulateTransaction RPC call
-    let rpc_request = serde_json::json!({
-        "jsonrpc": "2.0",
-        "id": 1,
-        "method": "simulateTransaction",
-        "params": {
-            "transaction": tx_xdr
-        }
-    });
+/// Call simulateTransaction on the Soroban RPC and return its `result` object
+/// along with the endpoint that answered
+fn simulate_transaction(config: &Config, tx_xdr: &str) -> Result<(serde_json::Value, String)> {
+    let params = serde_json::json!({ "transaction": tx_xdr });
 
-    let response = client
-        .post(Config::soroban_rpc_url())
-        .json(&rpc_request)
-        .send()
-        .context("Failed to call RPC for balance query")?;
+    with_failover(config, &config.soroban_rpc_urls(), |url| {
+        rpc_call(config, url, "simulateTransaction", Some(params.clone()))
+    })
+    .context("Failed to call RPC for simulation")
+}
 
-    if !response.status().is_success() {
-        anyhow::bail!("RPC returned error status: {}", response.status());
-    }
+/// Extract the return value XDR from a simulation result
+///
+/// The result is in result.results[0].xdr
+fn simulation_result_xdr(result: &serde_json::Value) -> Option<&str> {
+    result.get("results")
+        .and_then(|r| r.get(0))
+        .and_then(|r| r.get("xdr"))
+        .and_then(|x| x.as_str())
+}
 
-    let json: serde_json::Value = response.json()
-        .context("Failed to parse RPC response")?;
+/// Invoke a read-only contract function through simulation and decode its return value
+pub fn call_contract(
+    config: &Config,
+    contract: &str,
+    function: &str,
+    args: Vec<stellar_xdr::curr::ScVal>,
+) -> Result<stellar_xdr::curr::ScVal> {
+    use stellar_xdr::curr::ScVal;
 
-    if let Some(error) = json.get("error") {
-        anyhow::bail!("RPC error: {}", error);
+    let tx_xdr = build_invoke_transaction(contract, function, args)?;
+    let (result, _) = simulate_transaction(config, &tx_xdr)?;
+
+    if let Some(error) = result.get("error") {
+        anyhow::bail!("Contract call {}() failed: {}", function, error);
     }
 
-    let result = json.get("result")
-        .ok_or_else(|| anyhow::anyhow!("No result in RPC response"))?;
+    let result_xdr = simulation_result_xdr(&result)
+        .ok_or_else(|| anyhow::anyhow!("No result XDR found in response"))?;
+
+    ScVal::from_xdr_base64(result_xdr, Limits::none())
+        .context("Failed to decode contract result")
+}
+
+/// Query token balance for an address using Soroban RPC
+///
+/// This uses the simulateTransaction RPC method with a minimal transaction envelope
+/// that calls the "balance" function on the token contract. The token may be a
+/// registry symbol or a contract address; registry decimals scale the balance.
+pub fn query_balance(config: &Config, address: &str, token_input: &str) -> Result<serde_json::Value> {
+    use stellar_xdr::curr::*;
+
+    let token = config.resolve_token(token_input);
+    let token_contract = token.map(|t| t.contract.as_str()).unwrap_or(token_input);
+    let symbol = token.map(|t| t.symbol.as_str());
+    let decimals = token.map(|t| t.decimals).unwrap_or(DEFAULT_DECIMALS);
+
+    // Decode the Stellar address to get the account ID bytes
+    let address_bytes = match Strkey::from_string(address) {
+        Ok(Strkey::PublicKeyEd25519(pk)) => pk.0,
+        _ => anyhow::bail!("Invalid Stellar address format"),
+    };
+
+    // Create the ScVal for the address parameter (Address type with Account variant)
+    let address_scval = ScVal::Address(ScAddress::Account(
+        AccountId(PublicKey::PublicKeyTypeEd25519(
+            Uint256(address_bytes),
+        )),
+    ));
+
+    let tx_xdr = build_invoke_transaction(token_contract, "balance", vec![address_scval])?;
+    let (result, endpoint) = simulate_transaction(config, &tx_xdr)
+        .context("Failed to call RPC for balance query")?;
 
     // Try to extract and decode the balance from the result XDR
-    // The result is in result.results[0].xdr
-    if let Some(result_xdr) = result.get("results")
-        .and_then(|r| r.get(0))
-        .and_then(|r| r.get("xdr"))
-        .and_then(|x| x.as_str())
-    {
-        match ScVal::from_xdr_base64(result_xdr, Limits::none()) {
-            Ok(ScVal::I128(parts)) => {
-                // Convert i128 parts to get raw balance in stroops
-                let raw_balance = i128::from(parts.hi) << 64 | i128::from(parts.lo as u64);
-                // Convert to human-readable format (7 decimals for Stellar tokens)
-                let balance = raw_balance as f64 / 10_f64.powi(7);
-                return Ok(serde_json::json!({
-                    "address": address,
-                    "token": token_contract,
-                    "balance": balance,
-                    "raw_balance": raw_balance.to_string()
-                }));
-            }
-            Ok(ScVal::U128(parts)) => {
-                // Convert u128 parts to get raw balance in stroops
-                let raw_balance = u128::from(parts.hi) << 64 | u128::from(parts.lo);
-                // Convert to human-readable format (7 decimals for Stellar tokens)
-                let balance = raw_balance as f64 / 10_f64.powi(7);
-                return Ok(serde_json::json!({
-                    "address": address,
-                    "token": token_contract,
-                    "balance": balance,
-                    "raw_balance": raw_balance.to_string()
-                }));
-            }
+    if let Some(result_xdr) = simulation_result_xdr(&result) {
+        let raw_balance = match ScVal::from_xdr_base64(result_xdr, Limits::none()) {
+            // Convert i128 parts to get raw balance in stroops
+            Ok(ScVal::I128(parts)) => i128::from(parts.hi) << 64 | i128::from(parts.lo),
+            // Convert u128 parts to get raw balance in stroops
+            Ok(ScVal::U128(parts)) => (u128::from(parts.hi) << 64 | u128::from(parts.lo)) as i128,
             Ok(val) => {
                 // Return the ScVal as-is if it's not a number
                 return Ok(serde_json::json!({
                     "address": address,
                     "token": token_contract,
+                    "symbol": symbol,
+                    "rpc_endpoint": endpoint,
                     "result": format!("{:?}", val)
                 }));
             }
@@ -291,17 +566,33 @@ pub fn query_balance(address: &str, token_contract: &str) -> Result<serde_json::
                 return Ok(serde_json::json!({
                     "address": address,
                     "token": token_contract,
+                    "symbol": symbol,
+                    "rpc_endpoint": endpoint,
                     "error": format!("Failed to decode result: {}", e),
                     "raw_xdr": result_xdr
                 }));
             }
-        }
+        };
+
+        // Convert to human-readable format using the token's decimals
+        let balance = raw_balance as f64 / 10_f64.powi(decimals as i32);
+        return Ok(serde_json::json!({
+            "address": address,
+            "token": token_contract,
+            "symbol": symbol,
+            "rpc_endpoint": endpoint,
+            "decimals": decimals,
+            "balance": balance,
+            "raw_balance": raw_balance.to_string()
+        }));
     }
 
     // If we can't extract the XDR, return the full result
     Ok(serde_json::json!({
         "address": address,
         "token": token_contract,
+        "symbol": symbol,
+        "rpc_endpoint": endpoint,
         "result": result
     }))
 }
@@ -312,20 +603,25 @@ pub fn query_balance(address: &str, token_contract: &str) -> Result<serde_json::
 /// on the Reflector oracle contract. The asset can be:
 /// - External asset symbol (e.g., "btc", "eth", "xlm") -> uses ReflectorAsset::Other variant with appropriate oracle
 /// - Stellar contract address (starts with 'C') -> uses ReflectorAsset::Stellar variant with Stellar DEX oracle
-pub fn query_price(asset_input: &str) -> Result<serde_json::Value> {
+/// - Token registry symbol (e.g., "kale") -> resolved to its contract, as above
+pub fn query_price(config: &Config, asset_input: &str) -> Result<serde_json::Value> {
     use stellar_xdr::curr::*;
 
-    let client = reqwest::blocking::Client::new();
+    let asset_upper = asset_input.to_uppercase();
+    let is_external = CRYPTO_ASSETS.contains(&asset_upper.as_str()) || FIAT_ASSETS.contains(&asset_upper.as_str());
 
     // Determine if this is a Stellar asset (contract address) or external asset (symbol)
     // And which oracle to use
     let (asset_type, asset_value, reflector_contract) = if asset_input.starts_with('C') && asset_input.len() == 56 {
         // It's a Stellar contract address - use Stellar DEX oracle
-        ("Stellar", asset_input, REFLECTOR_STELLAR_CONTRACT)
+        ("Stellar", asset_input, config.reflector("stellar")?)
+    } else if let Some(token) = config.resolve_token(asset_input).filter(|_| !is_external) {
+        // A registry token the external oracles don't quote - price it on the Stellar DEX
+        ("Stellar", token.contract.as_str(), config.reflector("stellar")?)
     } else {
-        // It's an asset symbol (including token shortcuts like xlm, usdc, btc, eth, etc.)
+        // It's an asset symbol (btc, eth, xlm, usdc, eur, etc.)
         // Treat all symbols as external assets and determine oracle based on asset type
-        let oracle = get_oracle_for_asset(asset_input);
+        let oracle = get_oracle_for_asset(asset_input, config)?;
         ("Other", asset_input, oracle)
     };
 
@@ -370,105 +666,17 @@ pub fn query_price(asset_input: &str) -> Result<serde_json::Value> {
         ))
     };
 
-    // Decode the Reflector contract address
-    let reflector_contract_bytes = match Strkey::from_string(reflector_contract) {
-        Ok(Strkey::Contract(contract)) => contract.0,
-        _ => anyhow::bail!("Invalid Reflector contract address"),
-    };
-
-    // Create function name as ScSymbol
-    let function_name = ScSymbol("lastprice".as_bytes().to_vec().try_into()
-        .map_err(|_| anyhow::anyhow!("Function name too long"))?);
-
-    // Create contract address
-    let contract_address = ScAddress::Contract(
-        ContractId(Hash(reflector_contract_bytes)),
-    );
-
-    // Build InvokeContractArgs
-    let invoke_args = InvokeContractArgs {
-        contract_address,
-        function_name,
-        args: vec![asset_scval].try_into()
-            .map_err(|_| anyhow::anyhow!("Failed to create args vec"))?,
-    };
-
-    // Build HostFunction::InvokeContract
-    let host_function = HostFunction::InvokeContract(invoke_args);
-
-    // Build InvokeHostFunctionOp
-    let invoke_op = InvokeHostFunctionOp {
-        host_function,
-        auth: vec![].try_into()
-            .map_err(|_| anyhow::anyhow!("Failed to create auth vec"))?,
-    };
-
-    // Build Operation
-    let operation = Operation {
-        source_account: None,
-        body: OperationBody::InvokeHostFunction(invoke_op),
-    };
-
-    // Build a minimal transaction envelope
-    // Use a dummy source account
-    let source_account = MuxedAccount::Ed25519(Uint256([0u8; 32]));
-
-    let tx = Transaction {
-        source_account,
-        fee: 100,
-        seq_num: SequenceNumber(0),
-        cond: Preconditions::None,
-        memo: Memo::None,
-        operations: vec![operation].try_into()
-            .map_err(|_| anyhow::anyhow!("Failed to create operations vec"))?,
-        ext: TransactionExt::V0,
-    };
-
-    let tx_envelope = TransactionEnvelope::Tx(TransactionV1Envelope {
-        tx,
-        signatures: vec![].try_into()
-            .map_err(|_| anyhow::anyhow!("Failed to create signatures vec"))?,
-    });
-
-    // Encode transaction to base64 XDR
-    let tx_xdr = tx_envelope.to_xdr_base64(Limits::none())
-        .context("Failed to encode transaction to XDR")?;
-
-    // Make the simulateTransaction RPC call
-    let rpc_request = serde_json::json!({
-        "jsonrpc": "2.0",
-        "id": 1,
-        "method": "simulateTransaction",
-        "params": {
-            "transaction": tx_xdr
-        }
-    });
-
-    let response = client
-        .post(Config::soroban_rpc_url())
-        .json(&rpc_request)
-        .send()
+    let tx_xdr = build_invoke_transaction(reflector_contract, "lastprice", vec![asset_scval])
+        .context("Invalid Reflector contract address")?;
+    let (result, endpoint) = simulate_transaction(config, &tx_xdr)
         .context("Failed to call RPC for price query")?;
 
-    if !response.status().is_success() {
-        anyhow::bail!("RPC returned error status: {}", response.status());
-    }
-
-    let json: serde_json::Value = response.json()
-        .context("Failed to parse RPC response")?;
-
-    if let Some(error) = json.get("error") {
-        anyhow::bail!("RPC error: {}", error);
-    }
-
-    let result = json.get("result")
-        .ok_or_else(|| anyhow::anyhow!("No result in RPC response"))?;
-
     // Check for RPC error in result
     if let Some(error) = result.get("error") {
         return Ok(serde_json::json!({
             "asset": asset_input,
             "asset_type": asset_type,
+            "rpc_endpoint": endpoint,
             "error": "Contract execution failed",
             "contract_error": error,
             "result": result
@@ -476,13 +684,8 @@ pub fn query_price(asset_input: &str) -> Result<serde_json::Value> {
     }
 
     // Try to extract and decode the price data from the result XDR
-    // The result should be in result.results[0].xdr
     // Expected structure: { price: i128, timestamp: u64 }
-    if let Some(result_xdr) = result.get("results")
-        .and_then(|r| r.get(0))
-        .and_then(|r| r.get("xdr"))
-        .and_then(|x| x.as_str())
-    {
+    if let Some(result_xdr) = simulation_result_xdr(&result) {
         match ScVal::from_xdr_base64(result_xdr, Limits::none()) {
             Ok(ScVal::Map(Some(map))) => {
                 // Parse the map to extract price and timestamp
@@ -496,7 +699,7 @@ pub fn query_price(asset_input: &str) -> Result<serde_json::Value> {
                         match key_str.as_ref() {
                             "price" => {
                                 if let ScVal::I128(parts) = &entry.val {
-                                    price_i128 = Some(i128::from(parts.hi) << 64 | i128::from(parts.lo as u64));
+                                    price_i128 = Some(i128::from(parts.hi) << 64 | i128::from(parts.lo));
                                 }
                             }
                             "timestamp" => {
@@ -511,15 +714,16 @@ pub fn query_price(asset_input: &str) -> Result<serde_json::Value> {
 
                 if let (Some(price), Some(timestamp)) = (price_i128, timestamp_u64) {
                     // Format price with decimals
-                    let price_float = price as f64 / 10_f64.powi(REFLECTOR_DECIMALS as i32);
+                    let price_float = price as f64 / 10_f64.powi(config.reflector_decimals as i32);
 
                     return Ok(serde_json::json!({
                         "asset": asset_input,
                         "asset_type": asset_type,
+                        "rpc_endpoint": endpoint,
                         "price": price_float,
                         "price_raw": price.to_string(),
                         "timestamp": timestamp,
-                        "decimals": REFLECTOR_DECIMALS,
+                        "decimals": config.reflector_decimals,
                         "source": "reflector"
                     }));
                 }
@@ -529,6 +733,7 @@ pub fn query_price(asset_input: &str) -> Result<serde_json::Value> {
                 return Ok(serde_json::json!({
                     "asset": asset_input,
                     "asset_type": asset_type,
+                    "rpc_endpoint": endpoint,
                     "error": "Unexpected result format",
                     "result": format!("{:?}", val)
                 }));
@@ -537,6 +742,7 @@ pub fn query_price(asset_input: &str) -> Result<serde_json::Value> {
                 return Ok(serde_json::json!({
                     "asset": asset_input,
                     "asset_type": asset_type,
+                    "rpc_endpoint": endpoint,
                     "error": format!("Failed to decode result: {}", e),
                     "raw_xdr": result_xdr
                 }));
@@ -548,7 +754,137 @@ pub fn query_price(asset_input: &str) -> Result<serde_json::Value> {
     Ok(serde_json::json!({
         "asset": asset_input,
         "asset_type": asset_type,
+        "rpc_endpoint": endpoint,
         "error": "No result XDR found in response",
         "result": result
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// A `getLedgers` page of empty ledgers
+    fn response(sequences: std::ops::RangeInclusive<u32>, latest_ledger: u32) -> RpcLedgerResponse {
+        RpcLedgerResponse {
+            cursor: sequences.end().to_string(),
+            ledgers: sequences
+                .map(|sequence| RpcLedger { sequence, metadata_xdr: String::new() })
+                .collect(),
+            latest_ledger,
+        }
+    }
+
+    #[test]
+    fn test_parse_get_ledgers_response() {
+        let result: RpcLedgerResponse = serde_json::from_value(serde_json::json!({
+            "ledgers": [{
+                "hash": "00",
+                "sequence": 1000,
+                "ledgerCloseTime": "1700000000",
+                "headerXdr": "AAAA",
+                "metadataXdr": "AAAA"
+            }],
+            "latestLedger": 1010,
+            "latestLedgerCloseTime": 1700000050,
+            "oldestLedger": 10,
+            "oldestLedgerCloseTime": 1690000000,
+            "cursor": "1000"
+        })).unwrap();
+
+        assert_eq!(result.ledgers[0].sequence, 1000);
+        assert_eq!(result.latest_ledger, 1010);
+        assert_eq!(result.cursor, "1000");
+    }
+
+    #[test]
+    fn test_parse_get_health_response() {
+        let health: RpcHealth = serde_json::from_value(serde_json::json!({
+            "status": "healthy",
+            "latestLedger": 60000100,
+            "oldestLedger": 59879141,
+            "ledgerRetentionWindow": 120960
+        })).unwrap();
+
+        assert_eq!(health.oldest_ledger, 59879141);
+        assert_eq!(health.ledger_retention_window, 120960);
+    }
+
+    #[test]
+    fn test_retention_error() {
+        let health = RpcHealth {
+            status: "healthy".to_string(),
+            latest_ledger: 2000,
+            oldest_ledger: 1000,
+            ledger_retention_window: 1000,
+        };
+
+        assert_eq!(retention_error("https://rpc.example.com", &health, 1000, 2000), None);
+        let error = retention_error("https://rpc.example.com", &health, 900, 2000).unwrap();
+        assert!(error.starts_with("Ledgers 900-999 are not in the data lake"), "{}", error);
+        assert!(error.contains("1000-2000"), "{}", error);
+    }
+
+    #[test]
+    fn test_rpc_urls() {
+        let config = Config::for_network(crate::config::Network::Pubnet);
+        assert_eq!(rpc_urls(&config), vec![config.rpc_url.as_str(), config.soroban_rpc_url.as_str()]);
+
+        let config = Config { soroban_rpc_url: config.rpc_url.clone(), ..config };
+        assert_eq!(rpc_urls(&config).len(), 1);
+    }
+
+    #[test]
+    fn test_collect_ledgers_follows_cursors() {
+        let calls = RefCell::new(Vec::new());
+
+        // The RPC caps every page at 3 ledgers
+        let ledgers = collect_ledgers(100, 8, |cursor, limit| {
+            calls.borrow_mut().push((cursor.map(str::to_string), limit));
+            let first = cursor.map_or(100, |c| c.parse::<u32>().unwrap() + 1);
+            Ok(response(first..=first + limit.min(3) - 1, 500))
+        }).unwrap();
+
+        let sequences: Vec<u32> = ledgers.iter().map(|l| l.sequence).collect();
+        assert_eq!(sequences, (100..=107).collect::<Vec<_>>());
+        assert_eq!(*calls.borrow(), vec![
+            (None, 8),
+            (Some("102".to_string()), 5),
+            (Some("105".to_string()), 2),
+        ]);
+    }
+
+    #[test]
+    fn test_collect_ledgers_stops_at_latest_ledger() {
+        let mut calls = 0;
+        let ledgers = collect_ledgers(100, 50, |_, _| {
+            calls += 1;
+            Ok(response(100..=104, 104))
+        }).unwrap();
+
+        assert_eq!(ledgers.len(), 5);
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_collect_ledgers_stops_when_paging_stalls() {
+        // Pages that don't continue from the last ledger add nothing
+        let mut calls = 0;
+        let ledgers = collect_ledgers(100, 50, |_, _| {
+            calls += 1;
+            Ok(response(100..=102, 500))
+        }).unwrap();
+        assert_eq!(ledgers.len(), 3);
+        assert_eq!(calls, 2);
+
+        // A cursor that doesn't move
+        let mut first = 100;
+        let err = collect_ledgers(100, 50, |_, _| {
+            let page = RpcLedgerResponse { cursor: "same".to_string(), ..response(first..=first + 1, 500) };
+            first += 2;
+            Ok(page)
+        }).unwrap_err();
+        assert_eq!(err.kind(), "rpc");
+    }
+}
@@ -1,38 +1,496 @@
-use anyhow::{Context, Result};
+use std::io::{self, BufReader, Read};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use reqwest::Url;
+use stellar_xdr::curr::{LedgerCloseMeta, LedgerCloseMetaBatch};
+
+use crate::config::{Config, S3Settings};
+use crate::data::cache::{CacheWriter, LedgerCache};
+use crate::data::error::{retry_after, FetchError};
+use crate::data::lake::last_in_lake;
+use crate::data::metrics::Counted;
+use crate::data::local::{local_path, open_local_object};
+use crate::data::retry::{with_retry, CircuitBreaker};
+use crate::data::sigv4::{amz_date_now, sign_request, Credentials};
+use crate::data::source::{LedgerReader, LedgerSource};
+use crate::data::xdr::{io_error, BatchReader};
+
+/// Resolve an `s3://bucket/key` URL to the HTTP URL of the object
+///
+/// Other URLs are returned unchanged.
+pub fn resolve_s3_url(url: &str, s3: &S3Settings) -> Result<String> {
+    let Some(rest) = url.strip_prefix("s3://") else {
+        return Ok(url.to_string());
+    };
+
+    let (bucket, key) = rest.split_once('/').unwrap_or((rest, ""));
+    let endpoint = s3.endpoint.clone()
+        .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", s3.region));
+    let endpoint = endpoint.trim_end_matches('/');
+
+    if s3.path_style {
+        return Ok(format!("{}/{}/{}", endpoint, bucket, key));
+    }
+
+    let (scheme, host) = endpoint.split_once("://")
+        .ok_or_else(|| anyhow::anyhow!("Invalid S3 endpoint: {}", endpoint))?;
+    Ok(format!("{}://{}.{}/{}", scheme, bucket, host, key))
+}
+
+/// Build a request for a data lake object, signed when S3 credentials are configured
+fn object_request(method: reqwest::Method, url: &str, config: &Config) -> Result<reqwest::blocking::RequestBuilder, FetchError> {
+    let s3 = &config.s3;
+    let mut request = reqwest::blocking::Client::new()
+        .request(method.clone(), url)
+        .timeout(Duration::from_secs(config.retry.timeout_secs.into()));
+
+    if let (Some(access_key_id), Some(secret_access_key)) = (&s3.access_key_id, &s3.secret_access_key) {
+        let credentials = Credentials {
+            access_key_id,
+            secret_access_key,
+            session_token: s3.session_token.as_deref(),
+        };
+        let parsed = Url::parse(url)
+            .map_err(|e| FetchError::InvalidUrl(format!("Invalid data lake URL {}: {}", url, e)))?;
+        for (name, value) in sign_request(method.as_str(), &parsed, &[], &credentials, &s3.region, &amz_date_now()) {
+            request = request.header(name, value);
+        }
+    }
+
+    Ok(request)
+}
+
+/// A data lake object being read, with read failures carried as `FetchError`s
+///
+/// Wrapped in the `io::Error`s the readers pass on, so a failed download can
+/// be told apart from data that doesn't decompress or decode.
+pub type ObjectReader = Box<dyn Read + Send>;
+
+struct Download<R> {
+    inner: R,
+    error: fn(io::Error) -> FetchError,
+}
+
+impl<R: Read> Read for Download<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).map_err(|e| io::Error::other((self.error)(e)))
+    }
+}
+
+/// Open an object of the data lake to read it as it downloads
+///
+/// `file://` URLs and plain paths are read from a local data lake mirror instead.
+/// `s3://` URLs are resolved against the configured endpoint, and requests
+/// are signed when S3 credentials are configured.
+pub fn open_object(url: &str, config: &Config, silent: bool) -> Result<ObjectReader, FetchError> {
+    if let Some(path) = local_path(url) {
+        let file = open_local_object(&path, silent)?;
+        return Ok(Box::new(Download {
+            inner: file,
+            error: |e| FetchError::Io(format!("Failed to read local data lake object: {}", e)),
+        }));
+    }
+
+    let url = resolve_s3_url(url, &config.s3).map_err(|e| FetchError::InvalidUrl(e.to_string()))?;
 
-/// Download and decompress XDR data from S3
-pub fn fetch_and_decompress(url: &str, silent: bool) -> Result<Vec<u8>> {
     if !silent {
         println!("Fetching data from: {}", url);
     }
 
-    let response = reqwest::blocking::get(url)
-        .context("Failed to download data from S3")?;
+    let response = object_request(reqwest::Method::GET, &url, config)?
+        .send()
+        .map_err(|e| FetchError::from_reqwest(e, "S3"))?;
 
     // Check HTTP status code before processing
     let status = response.status();
     if !status.is_success() {
-        if status.as_u16() == 404 {
-            anyhow::bail!("Ledger data not found (HTTP 404). The ledger may not be available in the S3 bucket yet.");
-        } else {
-            anyhow::bail!("HTTP error {}: {}", status.as_u16(), status.canonical_reason().unwrap_or("Unknown error"));
+        return Err(FetchError::from_status(status, retry_after(response.headers()), "S3"));
+    }
+
+    Ok(Box::new(Download {
+        inner: Counted(response),
+        error: |e| FetchError::Network(format!("S3 download failed: {}", e)),
+    }))
+}
+
+/// Download an object from the data lake
+pub fn fetch_object(url: &str, config: &Config, silent: bool) -> Result<Vec<u8>, FetchError> {
+    let mut bytes = Vec::new();
+    open_object(url, config, silent)?
+        .read_to_end(&mut bytes)
+        .map_err(io_error)?;
+    Ok(bytes)
+}
+
+/// Check whether an object is in the data lake without downloading it
+pub fn object_exists(url: &str, config: &Config) -> Result<bool, FetchError> {
+    if let Some(path) = local_path(url) {
+        return Ok(path.is_file());
+    }
+
+    let url = resolve_s3_url(url, &config.s3).map_err(|e| FetchError::InvalidUrl(e.to_string()))?;
+    let response = object_request(reqwest::Method::HEAD, &url, config)?
+        .send()
+        .map_err(|e| FetchError::from_reqwest(e, "S3"))?;
+
+    let status = response.status();
+    match FetchError::from_status(status, retry_after(response.headers()), "S3") {
+        _ if status.is_success() => Ok(true),
+        e if e.is_not_found() => Ok(false),
+        e => Err(e),
+    }
+}
+
+/// Open a zstd compressed object of the data lake, decompressing it as it downloads
+pub fn open_decompressed(url: &str, config: &Config, silent: bool) -> Result<ObjectReader, FetchError> {
+    let decoder = zstd::stream::read::Decoder::new(open_object(url, config, silent)?)
+        .map_err(|e| FetchError::Decompress(format!("Failed to decompress zstd data: {}", e)))?;
+    Ok(Box::new(decoder))
+}
+
+/// Copies what is read into a cache entry, committed once the end is reached
+///
+/// An entry the reader stops short of, or fails to write, is dropped.
+struct CachingReader {
+    inner: ObjectReader,
+    cache: Arc<LedgerCache>,
+    entry: Option<CacheWriter>,
+    silent: bool,
+}
+
+impl CachingReader {
+    fn warn(&self, e: io::Error) {
+        if !self.silent {
+            eprintln!("Warning: could not write to cache {}: {}", self.cache.dir().display(), e);
         }
     }
+}
 
-    let bytes = response.bytes()
-        .context("Failed to read response bytes")?;
+impl Read for CachingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
 
-    if !silent {
-        println!("Downloaded {} bytes (compressed)", bytes.len());
+        if let Some(mut entry) = self.entry.take() {
+            let written = if n == 0 {
+                self.cache.commit(entry)
+            } else {
+                let written = io::Write::write_all(&mut entry, &buf[..n]);
+                self.entry = written.is_ok().then_some(entry);
+                written
+            };
+            if let Err(e) = written {
+                self.warn(e);
+            }
+        }
+        Ok(n)
     }
+}
 
-    // Decompress using zstd
-    let decompressed = zstd::decode_all(&bytes[..])
-        .context("Failed to decompress zstd data")?;
+/// Open a batch file to decode as it downloads, writing it to the cache on the way
+fn open_batch(
+    url: &str,
+    config: &Config,
+    cache: Option<&Arc<LedgerCache>>,
+    silent: bool,
+) -> Result<BatchReader<ObjectReader>, FetchError> {
+    let mut reader = open_decompressed(url, config, silent)?;
+    if let Some(cache) = cache {
+        match cache.writer(url) {
+            Ok(entry) => {
+                reader = Box::new(CachingReader {
+                    inner: reader,
+                    cache: Arc::clone(cache),
+                    entry: Some(entry),
+                    silent,
+                })
+            }
+            Err(e) if !silent => {
+                eprintln!("Warning: could not write to cache {}: {}", cache.dir().display(), e);
+            }
+            Err(_) => {}
+        }
+    }
+    BatchReader::new(reader)
+}
 
-    if !silent {
-        println!("Decompressed to {} bytes", decompressed.len());
+/// Ledgers of a batch file being downloaded, resuming after transient failures
+///
+/// When the download breaks part way through, the file is opened again
+/// with retries and read forward to the ledger that failed, so a dropped
+/// connection costs a second download rather than the rest of the batch.
+struct ResumingBatch<F> {
+    open: F,
+    config: Arc<Config>,
+    breaker: Arc<CircuitBreaker>,
+    reader: Option<BatchReader<ObjectReader>>,
+    /// Ledgers handed out so far
+    read: u32,
+}
+
+impl<F> ResumingBatch<F>
+where
+    F: FnMut() -> Result<BatchReader<ObjectReader>, FetchError> + Send + 'static,
+{
+    fn open(config: Arc<Config>, breaker: Arc<CircuitBreaker>, mut open: F) -> Result<LedgerReader, FetchError> {
+        let reader = with_retry(&config.retry, &breaker, &mut open)?;
+        let (start, end) = (reader.start_sequence, reader.end_sequence);
+        let ledgers = ResumingBatch { open, config, breaker, reader: Some(reader), read: 0 };
+        Ok(LedgerReader::new(start, end, ledgers))
+    }
+
+    /// Open the file again and read the ledger after the ones handed out
+    fn resume(&mut self) -> Result<(BatchReader<ObjectReader>, LedgerCloseMeta), FetchError> {
+        let (open, read) = (&mut self.open, self.read);
+        with_retry(&self.config.retry, &self.breaker, || {
+            let mut reader = open()?;
+            for _ in 0..read {
+                reader.next().transpose()?;
+            }
+            match reader.next() {
+                Some(meta) => Ok((reader, meta?)),
+                None => Err(FetchError::Decode("Batch file ended early when downloaded again".to_string())),
+            }
+        })
     }
+}
 
-    Ok(decompressed)
+impl<F> Iterator for ResumingBatch<F>
+where
+    F: FnMut() -> Result<BatchReader<ObjectReader>, FetchError> + Send + 'static,
+{
+    type Item = Result<LedgerCloseMeta, FetchError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = match self.reader.as_mut()?.next()? {
+            Err(e) if e.is_transient() => self.resume().map(|(reader, meta)| {
+                self.reader = Some(reader);
+                meta
+            }),
+            result => result,
+        };
+
+        match &result {
+            Ok(_) => self.read += 1,
+            Err(_) => self.reader = None,
+        }
+        Some(result)
+    }
+}
+
+/// How long ledgers past the end of the data lake are assumed missing
+const TAIL_TTL: Duration = Duration::from_secs(30);
+
+/// Ledgers from a remote data lake over HTTPS or an S3-compatible API
+///
+/// Transient failures are retried behind a circuit breaker, and decompressed
+/// batches are kept in the ledger cache keyed by their URL. Galexie exports
+/// ledgers in order, so once a ledger is missing the following ones are
+/// reported missing without a request for a short while, letting the tail
+/// of a recent range go straight to RPC.
+pub struct S3Source {
+    config: Arc<Config>,
+    cache: Option<Arc<LedgerCache>>,
+    breaker: Arc<CircuitBreaker>,
+    silent: bool,
+    /// First ledger found missing, and when
+    tail: Mutex<Option<(u32, Instant)>>,
+}
+
+impl S3Source {
+    pub fn new(config: Arc<Config>, cache: Option<Arc<LedgerCache>>, silent: bool) -> Self {
+        let breaker = Arc::new(CircuitBreaker::new("S3", &config.retry));
+        S3Source { config, cache, breaker, silent, tail: Mutex::new(None) }
+    }
+
+    /// Read a batch file from the cache
+    ///
+    /// Entries that no longer decode are dropped, so they are downloaded again.
+    fn read_cached(&self, cache: &Arc<LedgerCache>, url: &str) -> Option<LedgerReader> {
+        let file = cache.open(url)?;
+        let size = file.metadata().map_or(0, |meta| meta.len());
+
+        match BatchReader::new(BufReader::new(file)) {
+            Ok(reader) => {
+                if !self.silent {
+                    println!("Reading {} bytes from cache", size);
+                }
+                let (start, end) = (reader.start_sequence, reader.end_sequence);
+                let (cache, key) = (Arc::clone(cache), url.to_string());
+                let ledgers = reader.inspect(move |meta| {
+                    if meta.is_err() {
+                        cache.remove(&key);
+                    }
+                });
+                Some(LedgerReader::new(start, end, ledgers))
+            }
+            Err(_) => {
+                cache.remove(url);
+                None
+            }
+        }
+    }
+
+    /// Download a batch file, skipping ledgers known to be past the end of the data lake
+    ///
+    /// Ledgers are decoded as the file downloads, and the decompressed file
+    /// is written to the cache on the way.
+    fn download(&self, ledger_seq: u32, url: &str) -> Result<LedgerReader, FetchError> {
+        let mut tail = self.tail.lock().unwrap_or_else(|e| e.into_inner());
+        match *tail {
+            Some((first_missing, since)) if since.elapsed() < TAIL_TTL => {
+                if ledger_seq >= first_missing {
+                    return Err(FetchError::NotFound(format!(
+                        "Ledger {} is past the end of the data lake (ledger {} is missing)",
+                        ledger_seq, first_missing
+                    )));
+                }
+            }
+            _ => *tail = None,
+        }
+        drop(tail);
+
+        let (config, cache, silent) = (Arc::clone(&self.config), self.cache.clone(), self.silent);
+        let url = url.to_string();
+        let open = move || open_batch(&url, &config, cache.as_ref(), silent);
+        let result = ResumingBatch::open(Arc::clone(&self.config), Arc::clone(&self.breaker), open);
+
+        if matches!(&result, Err(e) if e.is_not_found()) {
+            let mut tail = self.tail.lock().unwrap_or_else(|e| e.into_inner());
+            let first_missing = tail.map_or(ledger_seq, |(first, _)| first.min(ledger_seq));
+            *tail = Some((first_missing, Instant::now()));
+        }
+        result
+    }
+}
+
+impl LedgerSource for S3Source {
+    fn name(&self) -> String {
+        "S3".to_string()
+    }
+
+    fn fetch_batch(&self, ledger_seq: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+        self.read_batch(ledger_seq, ledger_seq)?.into_batch()
+    }
+
+    fn read_batch(&self, ledger_seq: u32, _end: u32) -> Result<LedgerReader, FetchError> {
+        let url = self.config.generate_url(ledger_seq);
+
+        if let Some(reader) = self.cache.as_ref().and_then(|cache| self.read_cached(cache, &url)) {
+            return Ok(reader);
+        }
+        self.download(ledger_seq, &url)
+    }
+
+    fn ledgers_per_batch(&self) -> u32 {
+        self.config.ledgers_per_batch
+    }
+
+    /// Probe for batch files, remembering where the data lake ends
+    fn last_available(&self, start: u32, end: u32) -> Result<Option<u32>, FetchError> {
+        let last = last_in_lake(&self.config, start, end, |url| {
+            with_retry(&self.config.retry, &self.breaker, || object_exists(url, &self.config))
+        })?;
+
+        if last.is_none_or(|last| last < end) {
+            let first_missing = last.map_or(start, |last| last + 1);
+            *self.tail.lock().unwrap_or_else(|e| e.into_inner()) = Some((first_missing, Instant::now()));
+        }
+        Ok(last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::xdr::ledger_sequence;
+    use crate::data::xdr::tests::test_batch_xdr;
+    use crate::testing::{lake_config, write_lake, TempDir};
+
+    #[test]
+    fn test_resolve_s3_url_virtual_hosted() {
+        let s3 = S3Settings { region: "us-east-2".to_string(), ..S3Settings::default() };
+        assert_eq!(
+            resolve_s3_url("s3://aws-public-blockchain/v1.1/stellar/ledgers/pubnet/.config.json", &s3).unwrap(),
+            "https://aws-public-blockchain.s3.us-east-2.amazonaws.com/v1.1/stellar/ledgers/pubnet/.config.json"
+        );
+    }
+
+    #[test]
+    fn test_resolve_s3_url_path_style_endpoint() {
+        let s3 = S3Settings {
+            endpoint: Some("http://localhost:9000/".to_string()),
+            path_style: true,
+            ..S3Settings::default()
+        };
+        assert_eq!(
+            resolve_s3_url("s3://ledgers/pubnet/FFFFFFFF--0.xdr.zst", &s3).unwrap(),
+            "http://localhost:9000/ledgers/pubnet/FFFFFFFF--0.xdr.zst"
+        );
+    }
+
+    #[test]
+    fn test_caches_batches_read_to_the_end() {
+        let root = TempDir::new("s3-cache");
+        let config = lake_config(&root.join("lake"), 4, 2);
+        write_lake(&config, 8, 11);
+
+        let cache = Arc::new(LedgerCache::new(root.join("cache"), 1 << 20));
+        let source = S3Source::new(Arc::new(config), Some(Arc::clone(&cache)), true);
+
+        // A batch left half read is not cached
+        source.read_batch(8, 11).unwrap().seek(9).unwrap().unwrap();
+        assert_eq!(cache.stats().unwrap().entries, 0);
+
+        source.fetch_batch(8).unwrap();
+        assert_eq!(cache.stats().unwrap().entries, 1);
+
+        std::fs::remove_dir_all(root.join("lake")).unwrap();
+        let batch = source.fetch_batch(10).unwrap();
+        assert_eq!(batch.ledger_close_metas.iter().map(ledger_sequence).collect::<Vec<_>>(), vec![8, 9, 10, 11]);
+    }
+
+    /// Fails the first download after `fail_after` bytes, as a dropped connection would
+    struct Flaky {
+        data: io::Cursor<Vec<u8>>,
+        fail_after: Option<u64>,
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.fail_after.is_some_and(|after| self.data.position() >= after) {
+                return Err(io::Error::other(FetchError::Network("S3 download failed: connection reset".to_string())));
+            }
+            let len = self.fail_after.map_or(buf.len(), |after| buf.len().min((after - self.data.position()) as usize));
+            self.data.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_resumes_interrupted_downloads() {
+        let xdr = test_batch_xdr(64, 127);
+        let config = Arc::new(Config {
+            retry: crate::config::RetrySettings { base_delay_ms: 1, max_delay_ms: 1, ..Default::default() },
+            ..Config::default()
+        });
+        let breaker = Arc::new(CircuitBreaker::new("S3", &config.retry));
+
+        let mut opened = 0;
+        let open = move || {
+            opened += 1;
+            let fail_after = (opened == 1).then_some(xdr.len() as u64 / 2);
+            BatchReader::new(Box::new(Flaky { data: io::Cursor::new(xdr.clone()), fail_after }) as ObjectReader)
+        };
+        let reader = ResumingBatch::open(Arc::clone(&config), breaker, open).unwrap();
+
+        let batch = reader.into_batch().unwrap();
+        assert_eq!(batch.ledger_close_metas.iter().map(ledger_sequence).collect::<Vec<_>>(), (64..=127).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_resolve_s3_url_leaves_http_urls() {
+        let url = "https://aws-public-blockchain.s3.us-east-2.amazonaws.com/v1.1/stellar/ledgers/pubnet";
+        assert_eq!(resolve_s3_url(url, &S3Settings::default()).unwrap(), url);
+    }
 }
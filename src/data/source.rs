@@ -0,0 +1,325 @@
+use std::io::Read;
+use std::sync::Arc;
+
+use stellar_xdr::curr::{LedgerCloseMeta, LedgerCloseMetaBatch};
+
+use crate::config::Config;
+use crate::data::cache::LedgerCache;
+use crate::data::error::FetchError;
+use crate::data::local::{local_path, LocalSource};
+use crate::data::rpc::RpcSource;
+use crate::data::s3::S3Source;
+use crate::data::xdr::{ledger_sequence, single_batch, BatchReader};
+
+/// Ledgers of a batch, read in order as they are decoded
+///
+/// Data lake batch files are decoded straight from the download, so only
+/// the ledger being read is held in memory.
+pub struct LedgerReader {
+    pub start_sequence: u32,
+    pub end_sequence: u32,
+    /// RPC endpoint the ledgers came from, for ledgers not from the data lake
+    pub served_by: Option<String>,
+    ledgers: Box<dyn Iterator<Item = Result<LedgerCloseMeta, FetchError>> + Send>,
+    /// Ledger read past by `seek`, returned by the next call
+    peeked: Option<LedgerCloseMeta>,
+}
+
+impl LedgerReader {
+    pub fn new(
+        start_sequence: u32,
+        end_sequence: u32,
+        ledgers: impl Iterator<Item = Result<LedgerCloseMeta, FetchError>> + Send + 'static,
+    ) -> Self {
+        LedgerReader { start_sequence, end_sequence, served_by: None, ledgers: Box::new(ledgers), peeked: None }
+    }
+
+    /// Read up to a ledger, skipping the ones before it
+    ///
+    /// Returns `None` once the batch has no such ledger, leaving any later
+    /// ledger to be found by the next call.
+    pub fn seek(&mut self, ledger_seq: u32) -> Option<Result<LedgerCloseMeta, FetchError>> {
+        loop {
+            let meta = match self.peeked.take() {
+                Some(meta) => meta,
+                None => match self.ledgers.next()? {
+                    Ok(meta) => meta,
+                    Err(e) => return Some(Err(e)),
+                },
+            };
+
+            match ledger_sequence(&meta).cmp(&ledger_seq) {
+                std::cmp::Ordering::Less => continue,
+                std::cmp::Ordering::Equal => return Some(Ok(meta)),
+                std::cmp::Ordering::Greater => {
+                    self.peeked = Some(meta);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Read the remaining ledgers into a batch
+    pub fn into_batch(self) -> Result<LedgerCloseMetaBatch, FetchError> {
+        let metas = self.peeked.into_iter().map(Ok)
+            .chain(self.ledgers)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(LedgerCloseMetaBatch {
+            start_sequence: self.start_sequence,
+            end_sequence: self.end_sequence,
+            ledger_close_metas: metas.try_into()
+                .map_err(|_| FetchError::Decode("Too many ledgers for one batch".to_string()))?,
+        })
+    }
+}
+
+impl From<LedgerCloseMetaBatch> for LedgerReader {
+    fn from(batch: LedgerCloseMetaBatch) -> Self {
+        let metas: Vec<LedgerCloseMeta> = batch.ledger_close_metas.into();
+        LedgerReader::new(batch.start_sequence, batch.end_sequence, metas.into_iter().map(Ok))
+    }
+}
+
+impl<R: Read + Send + 'static> From<BatchReader<R>> for LedgerReader {
+    fn from(reader: BatchReader<R>) -> Self {
+        LedgerReader::new(reader.start_sequence, reader.end_sequence, reader)
+    }
+}
+
+/// Somewhere ledgers can be read from
+///
+/// Sources are shared between the worker threads of a range query, so they
+/// take `&self` and keep any state (circuit breakers, caches) internally.
+pub trait LedgerSource: Send + Sync {
+    /// Short name used in messages, such as "S3" or "RPC"
+    fn name(&self) -> String;
+
+    /// Fetch the batch holding a ledger
+    ///
+    /// Data lake batch files may hold ledgers either side of the one asked for.
+    fn fetch_batch(&self, ledger_seq: u32) -> Result<LedgerCloseMetaBatch, FetchError>;
+
+    /// Fetch the batch holding a ledger when the ledgers up to `end` are wanted next
+    ///
+    /// Sources that can fetch several ledgers in one request, such as RPC,
+    /// use `end` to size it.
+    fn fetch_batch_ahead(&self, ledger_seq: u32, end: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+        let _ = end;
+        self.fetch_batch(ledger_seq)
+    }
+
+    /// Read the batch holding a ledger, decoding its ledgers as they are read
+    ///
+    /// Sources that download batch files stream them; the others read the
+    /// batch from `fetch_batch_ahead`.
+    fn read_batch(&self, ledger_seq: u32, end: u32) -> Result<LedgerReader, FetchError> {
+        self.fetch_batch_ahead(ledger_seq, end).map(LedgerReader::from)
+    }
+
+    /// Ledgers per batch, so range work can be split on batch boundaries
+    fn ledgers_per_batch(&self) -> u32 {
+        1
+    }
+
+    /// Last ledger of `start..=end` this source holds, or `None` if it holds none of them
+    ///
+    /// Sources that can't tell assume they hold the whole range.
+    fn last_available(&self, start: u32, end: u32) -> Result<Option<u32>, FetchError> {
+        Ok(Some(end).filter(|end| *end >= start))
+    }
+
+    /// Fetch a ledger as a batch holding only that ledger
+    fn fetch(&self, ledger_seq: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+        let mut reader = self.read_batch(ledger_seq, ledger_seq)?;
+        match reader.seek(ledger_seq) {
+            Some(meta) => single_batch(ledger_seq, meta?),
+            None => Err(missing_from_batch(ledger_seq, &reader)),
+        }
+    }
+}
+
+/// Error for a batch that doesn't hold the ledger it was fetched for
+pub fn missing_from_batch(ledger_seq: u32, batch: &LedgerReader) -> FetchError {
+    FetchError::Decode(format!(
+        "Ledger {} is missing from batch {}-{}",
+        ledger_seq, batch.start_sequence, batch.end_sequence
+    ))
+}
+
+/// Tries each source in turn, moving on when a ledger is not found
+///
+/// Any other failure is returned as is, so an outage of the first source
+/// isn't hidden by the next one.
+pub struct FallbackSource {
+    sources: Vec<Arc<dyn LedgerSource>>,
+}
+
+impl FallbackSource {
+    pub fn new(sources: Vec<Arc<dyn LedgerSource>>) -> Self {
+        FallbackSource { sources }
+    }
+}
+
+impl LedgerSource for FallbackSource {
+    fn name(&self) -> String {
+        self.sources.iter()
+            .map(|source| source.name())
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+
+    fn fetch_batch(&self, ledger_seq: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+        self.fetch_batch_ahead(ledger_seq, ledger_seq)
+    }
+
+    fn fetch_batch_ahead(&self, ledger_seq: u32, end: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+        let mut last = FetchError::NotFound(format!("Ledger {} not found: no ledger sources", ledger_seq));
+
+        for source in &self.sources {
+            match source.fetch_batch_ahead(ledger_seq, end) {
+                Err(e) if e.is_not_found() => last = e,
+                result => return result,
+            }
+        }
+        Err(last)
+    }
+
+    fn read_batch(&self, ledger_seq: u32, end: u32) -> Result<LedgerReader, FetchError> {
+        let mut last = FetchError::NotFound(format!("Ledger {} not found: no ledger sources", ledger_seq));
+
+        for source in &self.sources {
+            match source.read_batch(ledger_seq, end) {
+                Err(e) if e.is_not_found() => last = e,
+                result => return result,
+            }
+        }
+        Err(last)
+    }
+
+    fn ledgers_per_batch(&self) -> u32 {
+        self.sources.first().map_or(1, |source| source.ledgers_per_batch())
+    }
+
+    /// What the first source holds, since that is where ranges are read from first
+    fn last_available(&self, start: u32, end: u32) -> Result<Option<u32>, FetchError> {
+        match self.sources.first() {
+            Some(source) => source.last_available(start, end),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The configured sources: the data lake (remote or a local mirror), then RPC
+pub fn from_config(config: Arc<Config>, silent: bool) -> Arc<dyn LedgerSource> {
+    let rpc_cache;
+    let lake: Arc<dyn LedgerSource> = if local_path(&config.base_url).is_some() {
+        // A local mirror is already on disk, caching it would only copy it
+        rpc_cache = None;
+        Arc::new(LocalSource::new(Arc::clone(&config), silent))
+    } else {
+        let cache = LedgerCache::from_settings(&config.cache).map(Arc::new);
+        rpc_cache = cache.clone();
+        Arc::new(S3Source::new(Arc::clone(&config), cache, silent))
+    };
+
+    Arc::new(FallbackSource::new(vec![
+        lake,
+        Arc::new(RpcSource::new(config, rpc_cache, silent)),
+    ]))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::data::lake::find_last;
+    use crate::data::xdr::tests::test_ledger;
+
+    /// Ledgers held in memory, for tests
+    #[derive(Debug, Default, Clone)]
+    pub struct MemorySource {
+        ledgers: BTreeMap<u32, LedgerCloseMeta>,
+    }
+
+    impl FromIterator<LedgerCloseMeta> for MemorySource {
+        fn from_iter<I: IntoIterator<Item = LedgerCloseMeta>>(metas: I) -> Self {
+            MemorySource {
+                ledgers: metas.into_iter().map(|meta| (ledger_sequence(&meta), meta)).collect(),
+            }
+        }
+    }
+
+    impl LedgerSource for MemorySource {
+        fn name(&self) -> String {
+            "memory".to_string()
+        }
+
+        fn fetch_batch(&self, ledger_seq: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+            let meta = self.ledgers.get(&ledger_seq)
+                .ok_or_else(|| FetchError::NotFound(format!("Ledger {} is not in memory", ledger_seq)))?;
+
+            single_batch(ledger_seq, meta.clone())
+        }
+
+        fn last_available(&self, start: u32, end: u32) -> Result<Option<u32>, FetchError> {
+            find_last(start, end, |seq| Ok(self.ledgers.contains_key(&seq)))
+        }
+    }
+
+    #[test]
+    fn test_fallback_moves_on_when_not_found() {
+        let first: MemorySource = (10..=12).map(test_ledger).collect();
+        let second: MemorySource = (10..=20).map(test_ledger).collect();
+        let chain = FallbackSource::new(vec![Arc::new(first), Arc::new(second)]);
+
+        assert_eq!(ledger_sequence(&chain.fetch(11).unwrap().ledger_close_metas[0]), 11);
+        assert_eq!(ledger_sequence(&chain.fetch(15).unwrap().ledger_close_metas[0]), 15);
+        assert!(chain.fetch(21).unwrap_err().is_not_found());
+        assert_eq!(chain.name(), "memory -> memory");
+    }
+
+    #[test]
+    fn test_fallback_stops_on_other_errors() {
+        struct Failing;
+        impl LedgerSource for Failing {
+            fn name(&self) -> String {
+                "failing".to_string()
+            }
+            fn fetch_batch(&self, _: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+                Err(FetchError::Http { status: 403, message: "Forbidden".to_string() })
+            }
+        }
+
+        let backup: MemorySource = (1..=5).map(test_ledger).collect();
+        let chain = FallbackSource::new(vec![Arc::new(Failing), Arc::new(backup)]);
+        assert_eq!(chain.fetch(3).unwrap_err().kind(), "http");
+    }
+
+    #[test]
+    fn test_ledger_reader_seek() {
+        let batch = crate::data::xdr::parse_xdr(&crate::data::xdr::tests::test_batch_xdr(64, 127)).unwrap();
+        let mut reader = LedgerReader::from(batch);
+
+        assert_eq!(ledger_sequence(&reader.seek(70).unwrap().unwrap()), 70);
+        assert_eq!(ledger_sequence(&reader.seek(71).unwrap().unwrap()), 71);
+        // Ledgers already read past are gone
+        assert!(reader.seek(65).is_none());
+        assert_eq!(ledger_sequence(&reader.seek(100).unwrap().unwrap()), 100);
+
+        let rest = reader.into_batch().unwrap();
+        assert_eq!(rest.ledger_close_metas.len(), 27);
+        assert_eq!(ledger_sequence(&rest.ledger_close_metas[0]), 101);
+    }
+
+    #[test]
+    fn test_last_available() {
+        let lake: MemorySource = (100..=110).map(test_ledger).collect();
+        let rpc: MemorySource = (100..=200).map(test_ledger).collect();
+        let chain = FallbackSource::new(vec![Arc::new(lake), Arc::new(rpc)]);
+
+        assert_eq!(chain.last_available(100, 150).unwrap(), Some(110));
+        assert_eq!(chain.last_available(100, 105).unwrap(), Some(105));
+        assert_eq!(chain.last_available(120, 150).unwrap(), None);
+    }
+}
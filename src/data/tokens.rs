@@ -0,0 +1,67 @@
+use serde_json::{json, Value};
+use stellar_xdr::curr::ScVal;
+use crate::config::{Config, TokenInfo};
+use crate::config::tokens::stellar_asset_contract;
+use crate::data::rpc::call_contract;
+
+/// Check a registry entry against the chain
+///
+/// Verifies that the contract address is well formed, that classic assets map
+/// to their Stellar Asset Contract on this network, and that the contract's
+/// `decimals()` and `name()` agree with the registry.
+pub fn validate_token(config: &Config, token: &TokenInfo) -> Value {
+    let mut checks = Vec::new();
+
+    let mut check = |name: &str, expected: Value, actual: Result<Value, String>| {
+        let ok = actual.as_ref().map(|a| *a == expected).unwrap_or(false);
+        let mut entry = json!({ "check": name, "ok": ok, "expected": expected });
+        match actual {
+            Ok(actual) => entry["actual"] = actual,
+            Err(error) => entry["error"] = Value::String(error),
+        }
+        checks.push(entry);
+    };
+
+    if stellar_strkey::Contract::from_string(&token.contract).is_err() {
+        check("contract", json!(token.contract), Err("Invalid contract address".to_string()));
+    } else {
+        if let Some(ref classic) = token.classic {
+            let derived = stellar_asset_contract(classic, &config.network_passphrase)
+                .map(Value::String)
+                .map_err(|e| e.to_string());
+            check("classic_contract", json!(token.contract), derived);
+
+            // Stellar Asset Contracts report the classic asset as their name
+            let name = call_contract(config, &token.contract, "name", vec![])
+                .map_err(|e| format!("{:#}", e))
+                .and_then(|val| scval_string(&val));
+            check("name", json!(classic), name);
+        }
+
+        let decimals = call_contract(config, &token.contract, "decimals", vec![])
+            .map_err(|e| format!("{:#}", e))
+            .and_then(|val| match val {
+                ScVal::U32(decimals) => Ok(json!(decimals)),
+                other => Err(format!("Unexpected decimals() result: {:?}", other)),
+            });
+        check("decimals", json!(token.decimals), decimals);
+    }
+
+    let valid = checks.iter().all(|c| c["ok"] == json!(true));
+
+    json!({
+        "symbol": token.symbol,
+        "contract": token.contract,
+        "valid": valid,
+        "checks": checks
+    })
+}
+
+/// Convert a String or Symbol ScVal into JSON text
+fn scval_string(val: &ScVal) -> Result<Value, String> {
+    match val {
+        ScVal::String(s) => Ok(Value::String(String::from_utf8_lossy(s.as_vec()).into_owned())),
+        ScVal::Symbol(s) => Ok(Value::String(String::from_utf8_lossy(s.as_vec()).into_owned())),
+        other => Err(format!("Unexpected string result: {:?}", other)),
+    }
+}
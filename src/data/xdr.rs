@@ -1,8 +1,176 @@
-use anyhow::{Context, Result};
-use stellar_xdr::curr::{LedgerCloseMetaBatch, ReadXdr, Limits};
+use std::io::{self, Read};
+
+use stellar_xdr::curr::{LedgerCloseMeta, LedgerCloseMetaBatch, Limited, Limits, ReadXdr};
+
+use crate::data::error::FetchError;
 
 /// Parse XDR data into LedgerCloseMetaBatch
-pub fn parse_xdr(data: &[u8]) -> Result<LedgerCloseMetaBatch> {
+pub fn parse_xdr(data: &[u8]) -> Result<LedgerCloseMetaBatch, FetchError> {
     LedgerCloseMetaBatch::from_xdr(data, Limits::none())
-        .context("Failed to parse XDR data")
+        .map_err(|e| FetchError::Decode(format!("Failed to parse XDR data: {}", e)))
+}
+
+/// Ledgers of an XDR `LedgerCloseMetaBatch`, decoded one at a time from a reader
+///
+/// Only the ledger being decoded is held in memory, so batch files of any
+/// size decode in flat memory. Once the last ledger is decoded the rest of
+/// the reader is drained, so readers that act on end of input see it.
+pub struct BatchReader<R: Read> {
+    reader: Limited<R>,
+    pub start_sequence: u32,
+    pub end_sequence: u32,
+    remaining: u32,
+}
+
+impl<R: Read> BatchReader<R> {
+    /// Read the batch header
+    pub fn new(reader: R) -> Result<Self, FetchError> {
+        let mut reader = Limited::new(reader, Limits::none());
+        let mut header = || u32::read_xdr(&mut reader).map_err(read_error);
+        let (start_sequence, end_sequence, remaining) = (header()?, header()?, header()?);
+
+        Ok(BatchReader { reader, start_sequence, end_sequence, remaining })
+    }
+}
+
+impl<R: Read> Iterator for BatchReader<R> {
+    type Item = Result<LedgerCloseMeta, FetchError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let result = LedgerCloseMeta::read_xdr(&mut self.reader).map_err(read_error);
+        self.remaining = match result {
+            Ok(_) => self.remaining - 1,
+            Err(_) => 0,
+        };
+        if self.remaining == 0 && result.is_ok() {
+            if let Err(e) = io::copy(&mut self.reader.inner, &mut io::sink()) {
+                return Some(Err(io_error(e)));
+            }
+        }
+        Some(result)
+    }
+}
+
+/// Classify a failure to decode XDR from a reader
+fn read_error(error: stellar_xdr::curr::Error) -> FetchError {
+    match error {
+        stellar_xdr::curr::Error::Io(e) => io_error(e),
+        e => FetchError::Decode(format!("Failed to parse XDR data: {}", e)),
+    }
+}
+
+/// Classify a failed read from a decompressing download
+///
+/// Failures of the download itself are passed through as `FetchError`s;
+/// anything else comes from the zstd decoder.
+pub fn io_error(error: io::Error) -> FetchError {
+    if let Some(fetch_error) = error.get_ref().and_then(|inner| inner.downcast_ref::<FetchError>()) {
+        return fetch_error.clone();
+    }
+    match error.kind() {
+        io::ErrorKind::UnexpectedEof => FetchError::Decode("Failed to parse XDR data: unexpected end of data".to_string()),
+        _ => FetchError::Decompress(format!("Failed to decompress zstd data: {}", error)),
+    }
+}
+
+/// Wrap a ledger in a batch of its own
+pub fn single_batch(ledger_seq: u32, meta: LedgerCloseMeta) -> Result<LedgerCloseMetaBatch, FetchError> {
+    Ok(LedgerCloseMetaBatch {
+        start_sequence: ledger_seq,
+        end_sequence: ledger_seq,
+        ledger_close_metas: vec![meta].try_into()
+            .map_err(|_| FetchError::Decode("Failed to create VecM".to_string()))?,
+    })
+}
+
+/// Sequence number of a ledger
+pub fn ledger_sequence(meta: &LedgerCloseMeta) -> u32 {
+    match meta {
+        LedgerCloseMeta::V0(v0) => v0.ledger_header.header.ledger_seq,
+        LedgerCloseMeta::V1(v1) => v1.ledger_header.header.ledger_seq,
+        LedgerCloseMeta::V2(v2) => v2.ledger_header.header.ledger_seq,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use stellar_xdr::curr::{
+        Hash, LedgerCloseMetaV0, LedgerHeader, LedgerHeaderExt, LedgerHeaderHistoryEntry,
+        LedgerHeaderHistoryEntryExt, StellarValue, StellarValueExt, TimePoint, TransactionSet,
+        WriteXdr,
+    };
+
+    /// An empty ledger with the given sequence number
+    pub fn test_ledger(ledger_seq: u32) -> LedgerCloseMeta {
+        LedgerCloseMeta::V0(LedgerCloseMetaV0 {
+            ledger_header: LedgerHeaderHistoryEntry {
+                hash: Hash([0; 32]),
+                header: LedgerHeader {
+                    ledger_version: 23,
+                    previous_ledger_hash: Hash([0; 32]),
+                    scp_value: StellarValue {
+                        tx_set_hash: Hash([0; 32]),
+                        close_time: TimePoint(0),
+                        upgrades: Default::default(),
+                        ext: StellarValueExt::Basic,
+                    },
+                    tx_set_result_hash: Hash([0; 32]),
+                    bucket_list_hash: Hash([0; 32]),
+                    ledger_seq,
+                    total_coins: 0,
+                    fee_pool: 0,
+                    inflation_seq: 0,
+                    id_pool: 0,
+                    base_fee: 100,
+                    base_reserve: 5_000_000,
+                    max_tx_set_size: 100,
+                    skip_list: [Hash([0; 32]), Hash([0; 32]), Hash([0; 32]), Hash([0; 32])],
+                    ext: LedgerHeaderExt::V0,
+                },
+                ext: LedgerHeaderHistoryEntryExt::V0,
+            },
+            tx_set: TransactionSet {
+                previous_ledger_hash: Hash([0; 32]),
+                txs: Default::default(),
+            },
+            tx_processing: Default::default(),
+            upgrades_processing: Default::default(),
+            scp_info: Default::default(),
+        })
+    }
+
+    /// XDR for a batch holding the ledgers `start..=end`
+    pub fn test_batch_xdr(start: u32, end: u32) -> Vec<u8> {
+        LedgerCloseMetaBatch {
+            start_sequence: start,
+            end_sequence: end,
+            ledger_close_metas: (start..=end).map(test_ledger).collect::<Vec<_>>().try_into().unwrap(),
+        }
+        .to_xdr(Limits::none())
+        .unwrap()
+    }
+
+    #[test]
+    fn test_batch_reader() {
+        let xdr = test_batch_xdr(64, 127);
+        let compressed = zstd::encode_all(&xdr[..], 0).unwrap();
+
+        let reader = BatchReader::new(zstd::stream::read::Decoder::new(&compressed[..]).unwrap()).unwrap();
+        assert_eq!((reader.start_sequence, reader.end_sequence), (64, 127));
+        let sequences: Vec<u32> = reader.map(|meta| ledger_sequence(&meta.unwrap())).collect();
+        assert_eq!(sequences, (64..=127).collect::<Vec<_>>());
+
+        // A truncated file fails on the ledger that is cut off, then stops
+        let mut reader = BatchReader::new(&xdr[..xdr.len() - 10]).unwrap();
+        assert_eq!(reader.by_ref().take_while(Result::is_ok).count(), 63);
+        assert!(reader.next().is_none());
+
+        let not_zstd = zstd::stream::read::Decoder::new(&xdr[..]).unwrap();
+        assert_eq!(BatchReader::new(not_zstd).err().unwrap().kind(), "decompress");
+    }
 }
@@ -1,18 +1,26 @@
-use anyhow::{Context, Result};
-
-#[allow(dead_code)]
-#[derive(serde::Deserialize)]
-struct HorizonLedger {
-    sequence: u32,
-}
-
-/// Fetch the latest ledger number from Stellar Horizon API
-pub fn get_latest_ledger() -> Result<u32> {
-    let horizon_url = "https://horizon.stellar.org/ledgers?order=desc&limit=1";
+use std::time::Duration;
 
-    println!("Fetching latest ledger from Horizon...");
-
-    let response = reqwest::blocking::get(horizon_url)
+use anyhow::{Context, Result};
+use crate::config::Config;
+
+/// Fetch the latest ledger number from the Stellar Horizon API
+///
+/// Horizon is being deprecated, so this is only a fallback for when no RPC
+/// answers; see `ledger::latest`.
+pub fn latest_from_horizon(config: &Config) -> Result<u32> {
+    if config.horizon_url.is_empty() {
+        anyhow::bail!("No Horizon URL configured for network {}", config.network.as_str());
+    }
+
+    let horizon_url = format!(
+        "{}/ledgers?order=desc&limit=1",
+        config.horizon_url.trim_end_matches('/')
+    );
+
+    let response = reqwest::blocking::Client::new()
+        .get(&horizon_url)
+        .timeout(Duration::from_secs(config.retry.timeout_secs.into()))
+        .send()
         .context("Failed to fetch latest ledger from Horizon")?;
 
     let json: serde_json::Value = response.json()
@@ -27,7 +35,5 @@ pub fn get_latest_ledger() -> Result<u32> {
     let sequence = latest_ledger["sequence"].as_u64()
         .ok_or_else(|| anyhow::anyhow!("Could not parse ledger sequence"))?;
 
-    println!("Latest ledger: {}\n", sequence);
-
     Ok(sequence as u32)
 }
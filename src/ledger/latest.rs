@@ -0,0 +1,39 @@
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::data::endpoints::with_failover;
+use crate::data::rpc::{self, rpc_urls};
+use crate::ledger::horizon::latest_from_horizon;
+
+/// Fetch the latest ledger number of the network
+///
+/// Asks the configured RPC endpoints with `getLatestLedger`, failing over
+/// between them, and falls back to Horizon only when none of them answers
+/// and a Horizon URL is configured. Unless `silent`, progress goes to
+/// stderr, so stdout only carries query results.
+pub fn get_latest_ledger(config: &Config, silent: bool) -> Result<u32> {
+    if !silent {
+        eprintln!("Fetching latest ledger from RPC...");
+    }
+
+    let rpc_error = match with_failover(config, &rpc_urls(config), |url| rpc::get_latest_ledger(config, url)) {
+        Ok((sequence, url)) => {
+            if !silent {
+                eprintln!("Latest ledger: {} (from {})", sequence, url);
+            }
+            return Ok(sequence);
+        }
+        Err(e) => e,
+    };
+
+    if config.horizon_url.is_empty() {
+        anyhow::bail!("Could not fetch the latest ledger from RPC: {}", rpc_error);
+    }
+
+    eprintln!("Warning: no RPC returned the latest ledger ({}), trying Horizon", rpc_error);
+    let sequence = latest_from_horizon(config)?;
+    if !silent {
+        eprintln!("Latest ledger: {} (from {})", sequence, config.horizon_url);
+    }
+    Ok(sequence)
+}
@@ -1,5 +1,6 @@
 pub mod range;
 pub mod horizon;
+pub mod latest;
 
 pub use range::LedgerRange;
-pub use horizon::get_latest_ledger;
+pub use latest::get_latest_ledger;
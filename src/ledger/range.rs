@@ -56,11 +56,6 @@ impl LedgerRange {
         }
     }
 
-    /// Create an iterator over ledger sequence numbers in the range
-    pub fn iter(&self) -> impl Iterator<Item = u32> {
-        self.start..=self.end
-    }
-
     /// Check if this represents a range (multiple ledgers) or a single ledger
     pub fn is_range(&self) -> bool {
         self.start != self.end
@@ -158,27 +153,6 @@ mod tests {
         assert!(!range.is_range()); // Same start and end = not a range
     }
 
-    #[test]
-    fn test_iter_single_ledger() {
-        let range = LedgerRange::parse("100", None).unwrap();
-        let values: Vec<u32> = range.iter().collect();
-        assert_eq!(values, vec![100]);
-    }
-
-    #[test]
-    fn test_iter_range() {
-        let range = LedgerRange::parse("100-105", None).unwrap();
-        let values: Vec<u32> = range.iter().collect();
-        assert_eq!(values, vec![100, 101, 102, 103, 104, 105]);
-    }
-
-    #[test]
-    fn test_iter_negative() {
-        let range = LedgerRange::parse("-3", Some(1000)).unwrap();
-        let values: Vec<u32> = range.iter().collect();
-        assert_eq!(values, vec![998, 999, 1000]);
-    }
-
     #[test]
     fn test_is_range_single() {
         let range = LedgerRange { start: 100, end: 100 };
@@ -1,6 +1,9 @@
+use std::io;
+use std::sync::Arc;
+
 use anyhow::Result;
 use clap::Parser;
-use stellar_xdr::curr::LedgerCloseMeta;
+use serde_json::json;
 
 mod cli;
 mod ledger;
@@ -9,18 +12,28 @@ mod stellar;
 mod output;
 mod server;
 mod config;
+mod checkpoint;
+mod progress;
+#[cfg(test)]
+mod testing;
 
-use cli::{Args, LONG_ABOUT};
+use checkpoint::{Checkpoint, LedgerRecord, ScanHeader};
+use cli::{Args, CacheAction, Command, ConfigAction, TokensAction, LONG_ABOUT};
 use ledger::{LedgerRange, get_latest_ledger};
-use config::Config;
-use data::{parse_xdr, query_balance, query_price};
-use data::s3::fetch_and_decompress;
-use data::rpc::fetch_from_rpc;
-use stellar::filters::{filter_by_address, filter_by_contract, filter_by_function};
-use output::to_json;
-
-#[tokio::main]
-async fn main() -> Result<()> {
+use data::{query_balance, query_price};
+use data::rpc::check_retention;
+use data::cache::LedgerCache;
+use data::lake;
+use data::pipeline::LedgerStream;
+use data::source as sources;
+use data::manifest::discover_layout;
+use data::tokens::validate_token;
+use output::{range_items, to_json, MissingLedgers, RangeWriter, RpcEndpoints, TransactionOptions};
+use progress::Progress;
+
+// Not `#[tokio::main]`: the CLI uses blocking HTTP clients, which must not
+// run inside an async runtime. Only server mode starts one.
+fn main() -> Result<()> {
     // If no arguments provided, show just the long_about and exit
     if std::env::args().len() == 1 {
         println!("{}", LONG_ABOUT);
@@ -32,9 +45,88 @@ async fn main() -> Result<()> {
     // Validate arguments
     args.validate()?;
 
+    // Merge defaults, config file, environment and flags
+    let mut config = args.config()?;
+
+    match args.command {
+        Some(Command::Config { action: ConfigAction::Show }) => {
+            print!("{}", toml::to_string_pretty(&config)?);
+            return Ok(());
+        }
+        Some(Command::Tokens { action: TokensAction::List }) => {
+            let tokens: Vec<_> = config.tokens.iter().collect();
+            println!("{}", serde_json::to_string_pretty(&tokens)?);
+            return Ok(());
+        }
+        Some(Command::Tokens { action: TokensAction::Validate { ref token } }) => {
+            let tokens: Vec<_> = match token {
+                Some(token) => vec![config.resolve_token(token)
+                    .ok_or_else(|| anyhow::anyhow!("Token {} is not in the registry", token))?],
+                None => config.tokens.iter().collect(),
+            };
+
+            let results: Vec<_> = tokens.iter()
+                .map(|token| validate_token(&config, token))
+                .collect();
+            let invalid = results.iter().filter(|r| r["valid"] != true).count();
+
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "network": config.network.as_str(),
+                "tokens": results,
+                "count": results.len(),
+                "invalid": invalid
+            }))?);
+
+            if invalid > 0 {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Cache { ref action }) => {
+            let dir = config.cache.dir()
+                .ok_or_else(|| anyhow::anyhow!("No cache directory (set HOME or --cache-dir)"))?;
+            let cache = LedgerCache::new(dir, config.cache.max_bytes());
+
+            let result = match action {
+                CacheAction::Stats => {
+                    let stats = cache.stats()?;
+                    serde_json::json!({
+                        "dir": cache.dir(),
+                        "enabled": config.cache.enabled,
+                        "entries": stats.entries,
+                        "size_bytes": stats.bytes,
+                        "max_size_bytes": cache.max_bytes()
+                    })
+                }
+                CacheAction::Clear => {
+                    let removed = cache.clear()?;
+                    serde_json::json!({
+                        "dir": cache.dir(),
+                        "removed": removed.entries,
+                        "freed_bytes": removed.bytes
+                    })
+                }
+            };
+
+            println!("{}", serde_json::to_string_pretty(&result)?);
+            return Ok(());
+        }
+        Some(Command::Status) => {
+            discover_layout(&mut config)?;
+            let latest_ledger = get_latest_ledger(&config, false)?;
+            let config = Arc::new(config);
+            let source = sources::from_config(Arc::clone(&config), true);
+
+            let status = lake::status(&config, source.as_ref(), latest_ledger)?;
+            println!("{}", serde_json::to_string_pretty(&status)?);
+            return Ok(());
+        }
+        None => {}
+    }
+
     // If server mode is enabled, start the API server
     if args.server {
-        return server::start_server(args.port).await;
+        return tokio::runtime::Runtime::new()?.block_on(server::start_server(args.port, config));
     }
 
     // Handle balance query separately (doesn't need ledger data)
@@ -42,14 +134,15 @@ async fn main() -> Result<()> {
         let address = args.address.as_ref().unwrap();
         let token_input = args.token.as_ref().unwrap();
 
-        // Resolve token shortcut to contract address
-        let token_contract = Config::resolve_token(token_input)
+        // Resolve registry symbol to contract address
+        let token_contract = config.resolve_token(token_input)
+            .map(|token| token.contract.as_str())
             .unwrap_or(token_input.as_str());
 
         println!("Querying balance for address: {}", address);
         println!("Token: {} ({})", token_input, token_contract);
 
-        let result = query_balance(address, token_contract)?;
+        let result = query_balance(&config, address, token_input)?;
         println!("\n{}", serde_json::to_string_pretty(&result)?);
         return Ok(());
     }
@@ -60,210 +153,238 @@ async fn main() -> Result<()> {
 
         println!("Querying price for asset: {}", asset_input);
 
-        let result = query_price(asset_input)?;
+        let result = query_price(&config, asset_input)?;
         println!("\n{}", serde_json::to_string_pretty(&result)?);
         return Ok(());
     }
 
-    let config = Config::default();
+    // Read the data lake layout from its manifest
+    discover_layout(&mut config)?;
+    let config = Arc::new(config);
 
     // Get ledger string, required for non-balance queries
     let ledger_str = args.ledger.as_ref()
         .ok_or_else(|| anyhow::anyhow!("--ledger is required for this query type"))?;
 
-    // Fetch latest ledger if we need it (for negative ledger values)
-    let latest_ledger = if ledger_str.trim().starts_with('-') {
-        Some(get_latest_ledger()?)
-    } else {
-        None
+    // A resumed scan reads back what its checkpoint recorded
+    let mut replay = match args.checkpoint {
+        Some(ref path) if args.resume => Some(Checkpoint::resume(path)?),
+        _ => None,
     };
 
-    // Parse ledger range
-    let ledger_range = LedgerRange::parse(ledger_str, latest_ledger)?;
+    let relative = ledger_str.trim().starts_with('-');
+    let ledger_range = match replay {
+        // Recent ledgers have moved on since, so resume the range recorded
+        Some(ref replay) if relative => LedgerRange {
+            start: replay.header.start_sequence,
+            end: replay.header.end_sequence,
+        },
+        _ => {
+            // Fetch latest ledger if we need it (for negative ledger values)
+            let latest_ledger = if relative {
+                Some(get_latest_ledger(&config, false)?)
+            } else {
+                None
+            };
+
+            // Parse ledger range
+            LedgerRange::parse(ledger_str, latest_ledger)?
+        }
+    };
 
     let is_range = ledger_range.is_range();
     let silent = is_range; // Be silent during range queries to reduce output
+    let source = sources::from_config(Arc::clone(&config), silent);
+
+    let scan = ScanHeader {
+        network: config.network.as_str().to_string(),
+        query: args.query.clone(),
+        address: args.address.clone(),
+        name: args.name.clone(),
+        raw: args.raw,
+        success: args.only_success(),
+        start_sequence: ledger_range.start,
+        end_sequence: ledger_range.end,
+    };
+    if args.checkpoint.is_some() && !is_range {
+        anyhow::bail!("--checkpoint only applies to ledger ranges");
+    }
+    if let Some(ref replay) = replay {
+        if replay.header != scan {
+            anyhow::bail!(
+                "The checkpoint is for a different scan: {}",
+                serde_json::to_string(&replay.header)?
+            );
+        }
+    }
+
+    let mut checkpoint = match (&replay, &args.checkpoint) {
+        (None, Some(path)) => Some(Checkpoint::create(path, &scan)?),
+        _ => None,
+    };
+
+    // Ledgers recorded in the checkpoint are not fetched again
+    let scan_start = match replay.as_ref().and_then(|replay| replay.last_ledger()) {
+        Some(last) => last.saturating_add(1),
+        None => ledger_range.start,
+    };
 
     if is_range {
-        println!("Querying ledger range: {} to {}", ledger_range.start, ledger_range.end);
-        println!("Query type: {}", args.query);
+        eprintln!("Querying ledger range: {} to {}", ledger_range.start, ledger_range.end);
+        if scan_start != ledger_range.start {
+            eprintln!("Resuming after ledger {}", scan_start - 1);
+        }
+
+        // Find where the data lake ends so the rest goes straight to RPC
+        let first_from_rpc = match source.last_available(scan_start, ledger_range.end) {
+            Ok(Some(last)) if last >= ledger_range.end => {
+                eprintln!("Ledger source: data lake");
+                None
+            }
+            Ok(Some(last)) => {
+                eprintln!(
+                    "Ledger sources: data lake for {}-{}, RPC for {}-{}",
+                    scan_start, last, last + 1, ledger_range.end
+                );
+                Some(last + 1)
+            }
+            Ok(None) if scan_start > ledger_range.end => None,
+            Ok(None) => {
+                eprintln!("Ledger source: RPC (the range is past the end of the data lake)");
+                Some(scan_start)
+            }
+            Err(e) => {
+                eprintln!("Warning: could not find the end of the data lake: {}", e);
+                None
+            }
+        };
+
+        // Fail up front rather than once per ledger when RPC no longer keeps them
+        if let Some(first) = first_from_rpc {
+            check_retention(&config, first, ledger_range.end)?;
+        }
+        eprintln!("Query type: {}", args.query);
         if let Some(ref addr) = args.address {
-            if args.query == "address" {
-                println!("Filtering by address: {}\n", addr);
+            if args.query == "address" || args.query == "operations" {
+                eprintln!("Filtering by address: {}\n", addr);
             } else if args.query == "contract" {
-                println!("Filtering by contract: {}\n", addr);
+                eprintln!("Filtering by contract: {}\n", addr);
             }
         }
         if let Some(ref name) = args.name {
-            println!("Filtering by function: {}\n", name);
+            eprintln!("Filtering by function: {}\n", name);
         }
     }
 
-    // Collect all matching transactions across the range
-    let mut all_transactions = Vec::new();
-    let mut total_processed = 0;
+    let options = TransactionOptions {
+        raw: args.raw,
+        success: args.only_success(),
+        ..TransactionOptions::new(&config)
+    };
 
-    for ledger_seq in ledger_range.iter() {
-        // Generate URL for the ledger
-        let url = config.generate_url(ledger_seq);
+    if !is_range {
+        // Single ledger - use original output format
+        let batch = source.fetch(ledger_range.start)?;
 
-        // Fetch and decompress the data (with RPC fallback on 404)
-        let decompressed_data = match fetch_and_decompress(&url, silent) {
-            Ok(data) => data,
-            Err(e) => {
-                // Check if it's a 404 error, and if so, try RPC fallback
-                let error_msg = e.to_string();
-                if error_msg.contains("HTTP 404") {
-                    match fetch_from_rpc(ledger_seq, silent) {
-                        Ok(data) => data,
-                        Err(rpc_err) => {
-                            eprintln!("Error fetching ledger {} from RPC: {}", ledger_seq, rpc_err);
-                            continue;
-                        }
-                    }
-                } else {
-                    eprintln!("Error fetching ledger {}: {}", ledger_seq, e);
-                    continue;
-                }
-            }
-        };
+        println!("\nLedger batch: {} to {}", batch.start_sequence, batch.end_sequence);
+        println!("Number of ledgers in batch: {}\n", batch.ledger_close_metas.len());
 
-        // Parse XDR
-        let batch = match parse_xdr(&decompressed_data) {
-            Ok(batch) => batch,
-            Err(e) => {
-                eprintln!("Error parsing ledger {}: {}", ledger_seq, e);
-                continue;
-            }
-        };
+        let json = to_json(&batch, &args.query, args.address.as_deref(), args.name.as_deref(), options)?;
+        println!("{}", json);
+        return Ok(());
+    }
 
-        total_processed += 1;
+    // Write results as each ledger is processed, with the summary at the end
+    let mut fields = vec![
+        ("start_sequence", json!(ledger_range.start)),
+        ("end_sequence", json!(ledger_range.end)),
+    ];
+    let items = match args.query.as_str() {
+        "all" => "ledgers",
+        query => {
+            fields.push(("address", json!(args.address)));
+            if query == "operations" { "operations" } else { "transactions" }
+        }
+    };
+    let mut output = RangeWriter::new(io::BufWriter::new(io::stdout().lock()), &fields, items)?;
 
-        // Filter or collect transactions based on query type
-        match args.query.as_str() {
-            "address" => {
-                if let Some(ref address) = args.address {
-                    let matching = filter_by_address(&batch, address);
-                    if !matching.is_empty() && !silent {
-                        println!("Found {} transaction(s) in ledger {}", matching.len(), ledger_seq);
-                    }
-                    all_transactions.extend(matching);
-                }
-            }
-            "contract" => {
-                if let Some(ref contract) = args.address {
-                    let matching = filter_by_contract(&batch, contract);
-                    if !matching.is_empty() && !silent {
-                        println!("Found {} transaction(s) in ledger {}", matching.len(), ledger_seq);
-                    }
-                    all_transactions.extend(matching);
-                }
-            }
-            "function" => {
-                if let Some(ref function_name) = args.name {
-                    let matching = filter_by_function(&batch, function_name);
-                    if !matching.is_empty() && !silent {
-                        println!("Found {} transaction(s) in ledger {}", matching.len(), ledger_seq);
-                    }
-                    all_transactions.extend(matching);
-                }
-            }
-            "transactions" => {
-                // Collect all transactions
-                for meta in batch.ledger_close_metas.as_vec() {
-                    match meta {
-                        LedgerCloseMeta::V0(v0) => {
-                            for tx in v0.tx_set.txs.as_vec() {
-                                if let Ok(tx_json) = serde_json::to_value(tx) {
-                                    all_transactions.push(tx_json);
-                                }
-                            }
-                        }
-                        LedgerCloseMeta::V1(v1) => {
-                            for tx_processing in v1.tx_processing.as_vec() {
-                                if let Ok(tx_json) = serde_json::to_value(tx_processing) {
-                                    all_transactions.push(tx_json);
-                                }
-                            }
-                        }
-                        LedgerCloseMeta::V2(v2) => {
-                            for tx_processing in v2.tx_processing.as_vec() {
-                                if let Ok(tx_json) = serde_json::to_value(tx_processing) {
-                                    all_transactions.push(tx_json);
-                                }
-                            }
-                        }
-                    }
-                }
+    let mut total_processed = 0;
+    let mut missing_ledgers = MissingLedgers::default();
+    let mut rpc_endpoints = RpcEndpoints::default();
+
+    // Write out what the checkpoint recorded, then carry on recording after it
+    if let Some(mut replay) = replay.take() {
+        for record in replay.by_ref() {
+            let record = record?;
+            match record.failed {
+                Some(failure) => missing_ledgers.push(&failure),
+                None => total_processed += 1,
             }
-            "all" => {
-                // For "all" mode with ranges, collect all ledger metadata
-                if !is_range {
-                    println!("\nLedger batch: {} to {}", batch.start_sequence, batch.end_sequence);
-                    println!("Number of ledgers in batch: {}\n", batch.ledger_close_metas.len());
-                    let json = to_json(&batch, &args.query, args.address.as_deref(), args.name.as_deref())?;
-                    println!("{}", json);
-                    return Ok(());
-                } else {
-                    // For range queries with "all", collect the full ledger metadata
-                    for meta in batch.ledger_close_metas.as_vec() {
-                        if let Ok(meta_json) = serde_json::to_value(meta) {
-                            all_transactions.push(meta_json);
-                        }
-                    }
-                }
+            rpc_endpoints.push(record.served_by.as_deref());
+            for item in &record.results {
+                output.push(item)?;
             }
-            _ => {}
         }
+        output.flush()?;
+        checkpoint = Some(replay.into_checkpoint()?);
     }
 
-    // Output results for range queries
-    if is_range {
-        println!("\nProcessed {} ledgers", total_processed);
-
-        let result = if args.query == "all" {
-            serde_json::json!({
-                "start_sequence": ledger_range.start,
-                "end_sequence": ledger_range.end,
-                "ledgers_processed": total_processed,
-                "ledgers": all_transactions,
-                "count": all_transactions.len()
-            })
-        } else {
-            serde_json::json!({
-                "start_sequence": ledger_range.start,
-                "end_sequence": ledger_range.end,
-                "ledgers_processed": total_processed,
-                "address": args.address,
-                "transactions": all_transactions,
-                "count": all_transactions.len()
-            })
-        };
+    let mut progress = Progress::new(
+        (ledger_range.end + 1).saturating_sub(scan_start).into(),
+        output.count(),
+        missing_ledgers.count() as usize,
+        !args.no_progress,
+    );
+    let ledgers = LedgerStream::new(
+        Arc::clone(&source),
+        scan_start,
+        ledger_range.end,
+        config.concurrency as usize,
+    );
 
-        println!("\n{}", serde_json::to_string_pretty(&result)?);
-    } else {
-        // Single ledger - use original output format
-        let url = config.generate_url(ledger_range.start);
-        let decompressed_data = match fetch_and_decompress(&url, false) {
-            Ok(data) => data,
+    for (ledger_seq, result) in ledgers {
+        // Fetched in parallel (with RPC fallback when not found), in ledger order
+        let ledger = match result {
+            Ok(ledger) => ledger,
             Err(e) => {
-                // Check if it's a 404 error, and if so, try RPC fallback
-                let error_msg = e.to_string();
-                if error_msg.contains("HTTP 404") {
-                    fetch_from_rpc(ledger_range.start, false)?
-                } else {
-                    return Err(e);
+                progress.failed(&format!("Error fetching ledger {}: {}", ledger_seq, e));
+                let failure = e.to_json(ledger_seq);
+                if let Some(ref mut checkpoint) = checkpoint {
+                    checkpoint.record(&LedgerRecord {
+                        ledger: ledger_seq,
+                        results: Vec::new(),
+                        failed: Some(failure.clone()),
+                        served_by: None,
+                    })?;
                 }
+                missing_ledgers.push(&failure);
+                continue;
             }
         };
-        let batch = parse_xdr(&decompressed_data)?;
 
-        println!("\nLedger batch: {} to {}", batch.start_sequence, batch.end_sequence);
-        println!("Number of ledgers in batch: {}\n", batch.ledger_close_metas.len());
+        total_processed += 1;
+        rpc_endpoints.push(ledger.served_by.as_deref());
+        let results = range_items(&ledger.batch, &args.query, args.address.as_deref(), args.name.as_deref(), options);
+        for item in &results {
+            output.push(item)?;
+        }
+        output.flush()?;
+        progress.ledger(results.len());
 
-        let json = to_json(&batch, &args.query, args.address.as_deref(), args.name.as_deref())?;
-        println!("{}", json);
+        if let Some(ref mut checkpoint) = checkpoint {
+            checkpoint.record(&LedgerRecord { ledger: ledger_seq, results, failed: None, served_by: ledger.served_by })?;
+        }
     }
+    progress.finish();
+
+    let count = output.count();
+    drop(output.finish(&[
+        ("ledgers_processed", json!(total_processed)),
+        ("count", json!(count)),
+        ("rpc_endpoints", rpc_endpoints.to_json()),
+        ("missing_ledgers", missing_ledgers.to_json()),
+    ])?);
+    eprintln!("\nProcessed {} ledgers ({} missing)", total_processed, missing_ledgers.count());
 
     Ok(())
 }
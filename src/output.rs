@@ -1,13 +1,89 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
 use anyhow::{Context, Result};
-use stellar_xdr::curr::{LedgerCloseMetaBatch, LedgerCloseMeta};
-use crate::stellar::filters::{filter_by_address, filter_by_contract, filter_by_function};
+use serde::Serialize;
+use serde_json::{json, Value};
+use stellar_xdr::curr::LedgerCloseMetaBatch;
+use crate::config::Config;
+use crate::config::tokens::network_id;
+use crate::data::xdr::ledger_sequence;
+use crate::stellar::filters::{filter_by_address, filter_by_contract, filter_by_function, filter_operations};
+use crate::stellar::transaction::{ledger_transactions, LedgerTransaction};
+
+/// How transaction queries write out transactions
+#[derive(Debug, Clone, Copy)]
+pub struct TransactionOptions {
+    /// Network transaction hashes are computed for
+    pub network_id: [u8; 32],
+    /// Include the envelope, result, fee changes and meta XDR as JSON with
+    /// each transaction
+    pub raw: bool,
+    /// Only transactions that succeeded (`Some(true)`) or failed (`Some(false)`)
+    pub success: Option<bool>,
+}
+
+impl TransactionOptions {
+    /// Options for the network of `config`, writing every transaction
+    /// without raw XDR
+    pub fn new(config: &Config) -> Self {
+        TransactionOptions { network_id: network_id(&config.network_passphrase), raw: false, success: None }
+    }
+}
+
+/// Transactions as `NormalizedTransaction` JSON, keeping those that
+/// succeeded or failed if asked to
+fn normalize<'a>(
+    transactions: impl IntoIterator<Item = LedgerTransaction<'a>>,
+    options: TransactionOptions,
+) -> Vec<Value> {
+    transactions.into_iter()
+        .filter(|tx| options.success.is_none_or(|success| tx.success() == Some(success)))
+        .map(|tx| to_item(&tx.normalize(options.network_id, options.raw), || json!({ "ledger": tx.ledger, "index": tx.index })))
+        .collect()
+}
+
+/// An item of the results as JSON
+///
+/// An item that can't be serialized is replaced by the fields from
+/// `describe` and the error, so it still shows in the results and their
+/// count rather than silently going missing.
+fn to_item(item: &impl Serialize, describe: impl FnOnce() -> Value) -> Value {
+    serde_json::to_value(item).unwrap_or_else(|e| {
+        let mut failed = describe();
+        failed["error"] = Value::from(format!("Failed to serialize: {}", e));
+        failed
+    })
+}
+
+/// Operations in a batch as `OperationRecord` JSON, those involving
+/// `address` if given
+///
+/// Keeping only successful or failed transactions keeps their operations.
+fn operation_items(batch: &LedgerCloseMetaBatch, address: Option<&str>, options: TransactionOptions) -> Vec<Value> {
+    filter_operations(batch, address, options.network_id).into_iter()
+        .filter(|(tx, _)| options.success.is_none_or(|success| tx.success() == Some(success)))
+        .flat_map(|(tx, matching)| tx.operation_records(options.network_id, &matching))
+        .map(|operation| to_item(&operation, || json!({
+            "ledger": operation.ledger,
+            "transaction_index": operation.transaction_index,
+            "index": operation.index,
+        })))
+        .collect()
+}
+
+/// Every transaction in a batch
+fn all_transactions(batch: &LedgerCloseMetaBatch, network_id: [u8; 32]) -> impl Iterator<Item = LedgerTransaction<'_>> {
+    batch.ledger_close_metas.iter().flat_map(move |meta| ledger_transactions(meta, network_id))
+}
 
 /// Convert LedgerCloseMetaBatch to JSON based on query type
 pub fn to_json(
     batch: &LedgerCloseMetaBatch,
     query_type: &str,
     address_filter: Option<&str>,
-    name_filter: Option<&str>
+    name_filter: Option<&str>,
+    options: TransactionOptions,
 ) -> Result<String> {
     match query_type {
         "all" => {
@@ -16,31 +92,7 @@ pub fn to_json(
                 .context("Failed to serialize batch to JSON")
         }
         "transactions" => {
-            // Extract just transactions from each ledger in the batch
-            let mut transactions = Vec::new();
-
-            for meta in batch.ledger_close_metas.as_vec() {
-                match meta {
-                    LedgerCloseMeta::V0(v0) => {
-                        for tx in v0.tx_set.txs.as_vec() {
-                            transactions.push(serde_json::to_value(tx)
-                                .context("Failed to serialize transaction")?);
-                        }
-                    }
-                    LedgerCloseMeta::V1(v1) => {
-                        for tx_processing in v1.tx_processing.as_vec() {
-                            transactions.push(serde_json::to_value(tx_processing)
-                                .context("Failed to serialize transaction processing")?);
-                        }
-                    }
-                    LedgerCloseMeta::V2(v2) => {
-                        for tx_processing in v2.tx_processing.as_vec() {
-                            transactions.push(serde_json::to_value(tx_processing)
-                                .context("Failed to serialize transaction processing")?);
-                        }
-                    }
-                }
-            }
+            let transactions = normalize(all_transactions(batch, options.network_id), options);
 
             serde_json::to_string_pretty(&serde_json::json!({
                 "start_sequence": batch.start_sequence,
@@ -52,7 +104,7 @@ pub fn to_json(
         }
         "address" => {
             let address = address_filter.ok_or_else(|| anyhow::anyhow!("Address filter required for 'address' query type"))?;
-            let transactions = filter_by_address(batch, address);
+            let transactions = normalize(filter_by_address(batch, address, options.network_id), options);
 
             serde_json::to_string_pretty(&serde_json::json!({
                 "start_sequence": batch.start_sequence,
@@ -65,7 +117,7 @@ pub fn to_json(
         }
         "contract" => {
             let contract = address_filter.ok_or_else(|| anyhow::anyhow!("Contract address (--address) required for 'contract' query type"))?;
-            let transactions = filter_by_contract(batch, contract);
+            let transactions = normalize(filter_by_contract(batch, contract, options.network_id), options);
 
             serde_json::to_string_pretty(&serde_json::json!({
                 "start_sequence": batch.start_sequence,
@@ -78,7 +130,7 @@ pub fn to_json(
         }
         "function" => {
             let function_name = name_filter.ok_or_else(|| anyhow::anyhow!("Function name (--name) required for 'function' query type"))?;
-            let transactions = filter_by_function(batch, function_name);
+            let transactions = normalize(filter_by_function(batch, function_name, options.network_id), options);
 
             serde_json::to_string_pretty(&serde_json::json!({
                 "start_sequence": batch.start_sequence,
@@ -89,8 +141,284 @@ pub fn to_json(
             }))
             .context("Failed to serialize filtered transactions to JSON")
         }
+        "operations" => {
+            let operations = operation_items(batch, address_filter, options);
+
+            serde_json::to_string_pretty(&serde_json::json!({
+                "start_sequence": batch.start_sequence,
+                "end_sequence": batch.end_sequence,
+                "address": address_filter,
+                "operations": operations,
+                "count": operations.len()
+            }))
+            .context("Failed to serialize operations to JSON")
+        }
         _ => {
-            anyhow::bail!("Unsupported query type: {}. Use 'all', 'transactions', 'address', 'contract', or 'function'", query_type)
+            anyhow::bail!("Unsupported query type: {}. Use 'all', 'transactions', 'address', 'contract', 'function', or 'operations'", query_type)
+        }
+    }
+}
+
+/// Results of a range query from one ledger: full ledger metadata for
+/// "all", operations for "operations", otherwise the matching transactions
+pub fn range_items(
+    batch: &LedgerCloseMetaBatch,
+    query_type: &str,
+    address_filter: Option<&str>,
+    name_filter: Option<&str>,
+    options: TransactionOptions,
+) -> Vec<Value> {
+    match (query_type, address_filter, name_filter) {
+        ("address", Some(address), _) => normalize(filter_by_address(batch, address, options.network_id), options),
+        ("contract", Some(contract), _) => normalize(filter_by_contract(batch, contract, options.network_id), options),
+        ("function", _, Some(function_name)) => normalize(filter_by_function(batch, function_name, options.network_id), options),
+        ("transactions", _, _) => normalize(all_transactions(batch, options.network_id), options),
+        ("operations", address, _) => operation_items(batch, address, options),
+        ("all", _, _) => batch.ledger_close_metas.iter()
+            .map(|meta| to_item(meta, || json!({ "ledger": ledger_sequence(meta) })))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Ledgers a range query could not read, as runs of consecutive ledgers
+/// that failed for the same reason
+///
+/// Listed in results as `missing_ledgers`, so an empty result can be told
+/// apart from one where ledgers were skipped.
+#[derive(Debug, Default)]
+pub struct MissingLedgers {
+    runs: Vec<MissingRun>,
+}
+
+#[derive(Debug)]
+struct MissingRun {
+    start: u64,
+    end: u64,
+    reason: String,
+    retryable: bool,
+    /// Error message of the first ledger in the run
+    error: String,
+}
+
+impl MissingLedgers {
+    /// Add a failed ledger, as described by `FetchError::to_json`
+    pub fn push(&mut self, failure: &Value) {
+        let Some(ledger) = failure["ledger"].as_u64() else { return };
+        let reason = failure["reason"].as_str().unwrap_or("unknown");
+        let retryable = failure["retryable"].as_bool().unwrap_or(false);
+
+        match self.runs.last_mut() {
+            Some(run) if run.end + 1 == ledger && run.reason == reason && run.retryable == retryable => {
+                run.end = ledger;
+            }
+            _ => self.runs.push(MissingRun {
+                start: ledger,
+                end: ledger,
+                reason: reason.to_string(),
+                retryable,
+                error: failure["error"].as_str().unwrap_or_default().to_string(),
+            }),
         }
     }
+
+    /// Number of ledgers missing
+    pub fn count(&self) -> u64 {
+        self.runs.iter().map(|run| run.end - run.start + 1).sum()
+    }
+
+    pub fn to_json(&self) -> Value {
+        self.runs.iter()
+            .map(|run| serde_json::json!({
+                "start": run.start,
+                "end": run.end,
+                "count": run.end - run.start + 1,
+                "reason": run.reason,
+                "retryable": run.retryable,
+                "error": run.error
+            }))
+            .collect()
+    }
+}
+
+/// How many ledgers of a range query each RPC endpoint served
+///
+/// Listed in results as `rpc_endpoints`, so ledgers that didn't come from
+/// the data lake can be traced to where they did come from.
+#[derive(Debug, Default)]
+pub struct RpcEndpoints {
+    ledgers: BTreeMap<String, u64>,
+}
+
+impl RpcEndpoints {
+    /// Count a ledger, if an RPC endpoint served it
+    pub fn push(&mut self, served_by: Option<&str>) {
+        if let Some(url) = served_by {
+            *self.ledgers.entry(url.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        self.ledgers.iter()
+            .map(|(url, ledgers)| serde_json::json!({ "url": url, "ledgers": ledgers }))
+            .collect()
+    }
+}
+
+/// Writes the JSON result of a range query as the ledgers are processed
+///
+/// The fields known up front are written first, then the results one at a
+/// time, then the summary fields (`ledgers_processed`, `count`,
+/// `missing_ledgers`) once the range is done. Nothing is held back, so the
+/// output of a long range starts straight away and memory use doesn't grow
+/// with it. The output is the same pretty printed JSON as
+/// `serde_json::to_string_pretty`, with fields in the order given.
+pub struct RangeWriter<W: Write> {
+    out: W,
+    count: usize,
+}
+
+impl<W: Write> RangeWriter<W> {
+    /// Start the object with `fields`, and open the `items` array
+    pub fn new(mut out: W, fields: &[(&str, Value)], items: &str) -> io::Result<Self> {
+        out.write_all(b"{")?;
+        for (name, value) in fields {
+            write_field(&mut out, name, value)?;
+            out.write_all(b",")?;
+        }
+        write!(out, "\n  {}: [", Value::from(items))?;
+        Ok(RangeWriter { out, count: 0 })
+    }
+
+    /// Add a result to the array
+    pub fn push(&mut self, item: &Value) -> io::Result<()> {
+        if self.count > 0 {
+            self.out.write_all(b",")?;
+        }
+        self.out.write_all(b"\n    ")?;
+        write_indented(&mut self.out, item, "    ")?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Results written so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Pass on what has been written, such as once per ledger
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Close the array and the object, ending with `fields`
+    pub fn finish(mut self, fields: &[(&str, Value)]) -> io::Result<W> {
+        self.out.write_all(if self.count > 0 { b"\n  ]" } else { b"]" })?;
+        for (name, value) in fields {
+            self.out.write_all(b",")?;
+            write_field(&mut self.out, name, value)?;
+        }
+        self.out.write_all(b"\n}\n")?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Write `"name": value` on a line of its own, one level in
+fn write_field(out: &mut impl Write, name: &str, value: &Value) -> io::Result<()> {
+    write!(out, "\n  {}: ", Value::from(name))?;
+    write_indented(out, value, "  ")
+}
+
+/// Pretty print a value whose first line is already indented by `indent`
+fn write_indented(out: &mut impl Write, value: &Value, indent: &str) -> io::Result<()> {
+    let pretty = serde_json::to_string_pretty(value)?;
+    for (i, line) in pretty.lines().enumerate() {
+        if i > 0 {
+            write!(out, "\n{}", indent)?;
+        }
+        out.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_range_writer_matches_pretty_json() {
+        let mut writer = RangeWriter::new(Vec::new(), &[("start_sequence", json!(1)), ("address", json!(null))], "transactions").unwrap();
+        writer.push(&json!({ "hash": "ab", "operations": [1, 2] })).unwrap();
+        writer.push(&json!("second")).unwrap();
+        let count = writer.count();
+        let out = writer.finish(&[("count", json!(count)), ("missing_ledgers", json!([{ "start": 3 }]))]).unwrap();
+
+        let expected = json!({
+            "start_sequence": 1,
+            "address": null,
+            "transactions": [{ "hash": "ab", "operations": [1, 2] }, "second"],
+            "count": 2,
+            "missing_ledgers": [{ "start": 3 }]
+        });
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&out).unwrap(), expected);
+        assert!(out.starts_with("{\n  \"start_sequence\": 1,\n  \"address\": null,\n  \"transactions\": [\n    {\n      \"hash\""));
+    }
+
+    #[test]
+    fn test_items_that_fail_to_serialize_are_reported() {
+        struct Unserializable;
+        impl Serialize for Unserializable {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("bad memo"))
+            }
+        }
+
+        assert_eq!(to_item(&"ok", || json!({ "ledger": 5 })), json!("ok"));
+        assert_eq!(
+            to_item(&Unserializable, || json!({ "ledger": 5, "index": 2 })),
+            json!({ "ledger": 5, "index": 2, "error": "Failed to serialize: bad memo" })
+        );
+    }
+
+    #[test]
+    fn test_missing_ledgers_are_grouped() {
+        let failure = |ledger: u32, reason: &str| json!({ "ledger": ledger, "reason": reason, "retryable": reason != "not_found", "error": format!("ledger {}", ledger) });
+        let mut missing = MissingLedgers::default();
+        for ledger in 100..=102 {
+            missing.push(&failure(ledger, "network"));
+        }
+        missing.push(&failure(103, "not_found"));
+        missing.push(&failure(110, "not_found"));
+
+        assert_eq!(missing.count(), 5);
+        assert_eq!(missing.to_json(), json!([
+            { "start": 100, "end": 102, "count": 3, "reason": "network", "retryable": true, "error": "ledger 100" },
+            { "start": 103, "end": 103, "count": 1, "reason": "not_found", "retryable": false, "error": "ledger 103" },
+            { "start": 110, "end": 110, "count": 1, "reason": "not_found", "retryable": false, "error": "ledger 110" }
+        ]));
+    }
+
+    #[test]
+    fn test_rpc_endpoints_count_ledgers() {
+        let mut endpoints = RpcEndpoints::default();
+        endpoints.push(Some("https://rpc-b.example"));
+        endpoints.push(None);
+        endpoints.push(Some("https://rpc-a.example"));
+        endpoints.push(Some("https://rpc-b.example"));
+
+        assert_eq!(endpoints.to_json(), json!([
+            { "url": "https://rpc-a.example", "ledgers": 1 },
+            { "url": "https://rpc-b.example", "ledgers": 2 }
+        ]));
+    }
+
+    #[test]
+    fn test_range_writer_without_results() {
+        let writer = RangeWriter::new(Vec::new(), &[], "ledgers").unwrap();
+        let out = writer.finish(&[("count", json!(0))]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{\n  \"ledgers\": [],\n  \"count\": 0\n}\n");
+    }
 }
@@ -0,0 +1,187 @@
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use crate::data::metrics::downloaded_bytes;
+
+/// How often the progress line is redrawn on a terminal
+const REDRAW: Duration = Duration::from_millis(200);
+
+/// How often a progress line is written when stderr is not a terminal
+const LOG_EVERY: Duration = Duration::from_secs(10);
+
+/// Progress of a range query, shown on stderr
+///
+/// On a terminal a single line is redrawn in place; otherwise (such as when
+/// stderr goes to a log file) a line is written every few seconds. Stdout
+/// only ever carries the result.
+pub struct Progress {
+    enabled: bool,
+    terminal: bool,
+    /// Ledgers this run will fetch
+    total: u64,
+    done: u64,
+    matches: u64,
+    missing: u64,
+    started: Instant,
+    downloaded_before: u64,
+    drawn: Option<Instant>,
+}
+
+impl Progress {
+    /// Progress of fetching `total` ledgers, counting on from `matches`
+    /// results and `missing` ledgers already found (by a resumed scan)
+    pub fn new(total: u64, matches: usize, missing: usize, enabled: bool) -> Self {
+        Progress {
+            enabled,
+            terminal: io::stderr().is_terminal(),
+            total,
+            done: 0,
+            matches: matches as u64,
+            missing: missing as u64,
+            started: Instant::now(),
+            downloaded_before: downloaded_bytes(),
+            drawn: None,
+        }
+    }
+
+    /// A ledger was processed, with `found` results
+    pub fn ledger(&mut self, found: usize) {
+        self.done += 1;
+        self.matches += found as u64;
+        self.draw(false);
+    }
+
+    /// A ledger could not be fetched
+    pub fn failed(&mut self, message: &str) {
+        self.done += 1;
+        self.missing += 1;
+        self.clear();
+        eprintln!("{}", message);
+        // Put the line back straight away on a terminal
+        self.draw(self.terminal);
+    }
+
+    /// Show the final counts
+    pub fn finish(&mut self) {
+        if self.enabled {
+            self.clear();
+            eprintln!("{}", self.line());
+        }
+    }
+
+    fn draw(&mut self, force: bool) {
+        if !self.enabled {
+            return;
+        }
+        let every = if self.terminal { REDRAW } else { LOG_EVERY };
+        if !force && self.drawn.is_some_and(|drawn| drawn.elapsed() < every) {
+            return;
+        }
+
+        let line = self.line();
+        let mut stderr = io::stderr().lock();
+        let _ = if self.terminal {
+            write!(stderr, "\r\x1b[2K{}", line)
+        } else {
+            writeln!(stderr, "{}", line)
+        };
+        let _ = stderr.flush();
+        self.drawn = Some(Instant::now());
+    }
+
+    /// Clear the progress line, so a message can be written in its place
+    fn clear(&self) {
+        if self.enabled && self.terminal && self.drawn.is_some() {
+            eprint!("\r\x1b[2K");
+        }
+    }
+
+    fn line(&self) -> String {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { self.done as f64 / elapsed } else { 0.0 };
+        let eta = match self.total.saturating_sub(self.done) {
+            0 => "done".to_string(),
+            remaining if rate > 0.0 => format_duration(Duration::from_secs_f64(remaining as f64 / rate)),
+            _ => "-".to_string(),
+        };
+
+        format!(
+            "{}/{} ledgers ({:.1}%) | {:.1} ledgers/s | {} downloaded | {} matches | {} missing | ETA {}",
+            self.done,
+            self.total,
+            percent(self.done, self.total),
+            rate,
+            format_bytes(downloaded_bytes().saturating_sub(self.downloaded_before)),
+            self.matches,
+            self.missing,
+            eta
+        )
+    }
+}
+
+fn percent(done: u64, total: u64) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        done as f64 * 100.0 / total as f64
+    }
+}
+
+/// Bytes in the largest unit that keeps the number above 1, such as "12.3 MB"
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// A duration as hours, minutes and seconds, such as "1h02m03s" or "45s"
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}h{:02}m{:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m{:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(12 * 1024 * 1024 + 300 * 1024), "12.3 MB");
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(45)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(192)), "3m12s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+    }
+
+    #[test]
+    fn test_progress_line() {
+        let mut progress = Progress::new(10, 3, 0, false);
+        progress.ledger(2);
+        progress.failed("Error fetching ledger 2");
+
+        let line = progress.line();
+        assert!(line.starts_with("2/10 ledgers (20.0%)"), "{}", line);
+        assert!(line.contains("| 5 matches | 1 missing |"), "{}", line);
+    }
+}
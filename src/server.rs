@@ -1,27 +1,53 @@
+use std::io::{self, Write};
+use std::sync::Arc;
+
 use axum::{
-    extract::Query,
-    http::StatusCode,
-    response::{Html, IntoResponse, Json},
+    body::Body,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Json, Response},
     routing::get,
     Router,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use tokio::sync::mpsc;
 use tower_http::cors::CorsLayer;
 
 use crate::config::Config;
-use crate::data::{parse_xdr, query_balance, query_price};
-use crate::data::s3::fetch_and_decompress;
-use crate::data::rpc::fetch_from_rpc;
+use crate::data::{query_balance, query_price};
+use crate::data::endpoints::check_all;
+use crate::data::lake;
+use crate::data::pipeline::LedgerStream;
+use crate::data::rpc::check_retention;
+use crate::data::source::{self as sources, LedgerSource};
+use crate::data::manifest::discover_layout;
 use crate::ledger::{get_latest_ledger, LedgerRange};
-use crate::stellar::filters::{filter_by_address, filter_by_contract, filter_by_function};
-use stellar_xdr::curr::LedgerCloseMeta;
+use crate::output::{range_items, MissingLedgers, RangeWriter, RpcEndpoints, TransactionOptions};
+use stellar_xdr::curr::LedgerCloseMetaBatch;
 
 #[derive(Debug, Deserialize)]
 pub struct TransactionsQuery {
     ledger: String,
     #[serde(default)]
     address: Option<String>,
+    #[serde(default)]
+    raw: bool,
+    #[serde(default)]
+    only_failed: bool,
+    #[serde(default)]
+    only_successful: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OperationsQuery {
+    ledger: String,
+    #[serde(default)]
+    address: Option<String>,
+    #[serde(default)]
+    only_failed: bool,
+    #[serde(default)]
+    only_successful: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,12 +59,24 @@ pub struct AllQuery {
 pub struct ContractQuery {
     ledger: String,
     address: String,
+    #[serde(default)]
+    raw: bool,
+    #[serde(default)]
+    only_failed: bool,
+    #[serde(default)]
+    only_successful: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct FunctionQuery {
     ledger: String,
     name: String,
+    #[serde(default)]
+    raw: bool,
+    #[serde(default)]
+    only_failed: bool,
+    #[serde(default)]
+    only_successful: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -63,314 +101,336 @@ impl IntoResponse for ErrorResponse {
     }
 }
 
-/// Handler for /transactions endpoint
-/// Supports both filtered (by address) and unfiltered transaction queries
-pub async fn transactions_handler(
-    Query(params): Query<TransactionsQuery>,
-) -> Result<Json<Value>, ErrorResponse> {
-    let config = Config::default();
+/// Shared state of the API server
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    /// Where range queries read ledgers from
+    pub source: Arc<dyn LedgerSource>,
+}
 
-    // Parse ledger range
-    let latest_ledger = if params.ledger.trim().starts_with('-') {
-        Some(get_latest_ledger().map_err(|e| ErrorResponse {
+impl AppState {
+    /// State reading ledgers from the configured data lake and RPC
+    pub fn new(config: Config) -> Self {
+        let config = Arc::new(config);
+        let source = sources::from_config(Arc::clone(&config), true);
+        AppState { config, source }
+    }
+}
+
+/// Parse the `ledger` parameter, looking up the latest ledger for negative values
+fn parse_range(config: &Config, ledger: &str) -> Result<LedgerRange, ErrorResponse> {
+    let latest_ledger = if ledger.trim().starts_with('-') {
+        Some(get_latest_ledger(config, true).map_err(|e| ErrorResponse {
             error: format!("Failed to get latest ledger: {}", e),
         })?)
     } else {
         None
     };
 
-    let ledger_range = LedgerRange::parse(&params.ledger, latest_ledger).map_err(|e| ErrorResponse {
+    LedgerRange::parse(ledger, latest_ledger).map_err(|e| ErrorResponse {
         error: format!("Invalid ledger range: {}", e),
-    })?;
+    })
+}
 
-    let mut all_transactions = Vec::new();
-    let mut total_processed = 0;
+/// Run blocking data layer work on tokio's blocking thread pool
+///
+/// Fetching uses blocking HTTP clients and worker threads, so handlers must
+/// not run it on the async runtime, where one long range scan would stall
+/// every other request.
+async fn run_blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, ErrorResponse> + Send + 'static,
+) -> Result<T, ErrorResponse> {
+    tokio::task::spawn_blocking(work).await.map_err(|e| ErrorResponse {
+        error: format!("Request failed: {}", e),
+    })?
+}
 
-    // Process each ledger in the range
-    for ledger_seq in ledger_range.iter() {
-        let url = config.generate_url(ledger_seq);
-
-        // Fetch data with RPC fallback
-        let decompressed_data = match fetch_and_decompress(&url, true) {
-            Ok(data) => data,
-            Err(e) => {
-                if e.to_string().contains("HTTP 404") {
-                    match fetch_from_rpc(ledger_seq, true) {
-                        Ok(data) => data,
-                        Err(_) => continue,
-                    }
-                } else {
-                    continue;
-                }
-            }
-        };
+/// Options of the transaction endpoints, from their `raw`, `only_failed`
+/// and `only_successful` parameters
+fn transaction_options(
+    config: &Config,
+    raw: bool,
+    only_failed: bool,
+    only_successful: bool,
+) -> Result<TransactionOptions, ErrorResponse> {
+    let success = match (only_failed, only_successful) {
+        (true, true) => {
+            return Err(ErrorResponse {
+                error: "Use only one of only_failed and only_successful".to_string(),
+            })
+        }
+        (true, false) => Some(false),
+        (false, true) => Some(true),
+        (false, false) => None,
+    };
+    Ok(TransactionOptions { raw, success, ..TransactionOptions::new(config) })
+}
 
-        let batch = match parse_xdr(&decompressed_data) {
-            Ok(batch) => batch,
-            Err(_) => continue,
-        };
+/// Find where the data lake ends, so ledgers past it go straight to RPC,
+/// and fail up front when RPC no longer keeps them
+fn check_range(state: &AppState, range: &LedgerRange) -> Result<(), ErrorResponse> {
+    if !range.is_range() {
+        return Ok(());
+    }
 
-        total_processed += 1;
+    let first_from_rpc = match state.source.last_available(range.start, range.end) {
+        Ok(Some(last)) if last >= range.end => None,
+        Ok(last) => Some(last.map_or(range.start, |last| last + 1)),
+        Err(_) => None,
+    };
+    if let Some(first) = first_from_rpc {
+        check_retention(&state.config, first, range.end).map_err(|e| ErrorResponse {
+            error: e.to_string(),
+        })?;
+    }
+    Ok(())
+}
 
-        // Filter by address if provided, otherwise get all transactions
-        if let Some(ref address) = params.address {
-            let matching = filter_by_address(&batch, address);
-            all_transactions.extend(matching);
-        } else {
-            // Collect all transactions
-            for meta in batch.ledger_close_metas.as_vec() {
-                match meta {
-                    LedgerCloseMeta::V0(v0) => {
-                        for tx in v0.tx_set.txs.as_vec() {
-                            if let Ok(tx_json) = serde_json::to_value(tx) {
-                                all_transactions.push(tx_json);
-                            }
-                        }
-                    }
-                    LedgerCloseMeta::V1(v1) => {
-                        for tx_processing in v1.tx_processing.as_vec() {
-                            if let Ok(tx_json) = serde_json::to_value(tx_processing) {
-                                all_transactions.push(tx_json);
-                            }
-                        }
-                    }
-                    LedgerCloseMeta::V2(v2) => {
-                        for tx_processing in v2.tx_processing.as_vec() {
-                            if let Ok(tx_json) = serde_json::to_value(tx_processing) {
-                                all_transactions.push(tx_json);
-                            }
-                        }
-                    }
-                }
+/// Fetch every ledger of a range in order, passing each one to `visit`
+///
+/// Returns the number of ledgers processed, the ledgers that failed and
+/// the RPC endpoints that served ledgers. Stops early if `visit` fails,
+/// such as when the client has gone away.
+fn scan_range(
+    state: &AppState,
+    range: &LedgerRange,
+    mut visit: impl FnMut(&LedgerCloseMetaBatch) -> io::Result<()>,
+) -> io::Result<(usize, MissingLedgers, RpcEndpoints)> {
+    let mut total_processed = 0;
+    let mut missing_ledgers = MissingLedgers::default();
+    let mut rpc_endpoints = RpcEndpoints::default();
+
+    let ledgers = LedgerStream::new(
+        Arc::clone(&state.source),
+        range.start,
+        range.end,
+        state.config.concurrency as usize,
+    );
+
+    for (ledger_seq, result) in ledgers {
+        // Fetched in parallel with RPC fallback, in ledger order
+        match result {
+            Ok(ledger) => {
+                total_processed += 1;
+                rpc_endpoints.push(ledger.served_by.as_deref());
+                visit(&ledger.batch)?;
             }
+            Err(e) => missing_ledgers.push(&e.to_json(ledger_seq)),
         }
     }
 
-    let result = json!({
-        "start_sequence": ledger_range.start,
-        "end_sequence": ledger_range.end,
-        "ledgers_processed": total_processed,
-        "address": params.address,
-        "transactions": all_transactions,
-        "count": all_transactions.len()
-    });
-
-    Ok(Json(result))
+    Ok((total_processed, missing_ledgers, rpc_endpoints))
 }
 
-/// Handler for /all endpoint
-/// Returns full ledger metadata
-pub async fn all_handler(Query(params): Query<AllQuery>) -> Result<Json<Value>, ErrorResponse> {
-    let config = Config::default();
+/// Chunks of a streamed response held back before the scan waits for the client
+const CHUNKS_AHEAD: usize = 16;
 
-    // Parse ledger range
-    let latest_ledger = if params.ledger.trim().starts_with('-') {
-        Some(get_latest_ledger().map_err(|e| ErrorResponse {
-            error: format!("Failed to get latest ledger: {}", e),
-        })?)
-    } else {
-        None
-    };
+/// Bytes gathered into one chunk of a streamed response
+const CHUNK_BYTES: usize = 64 * 1024;
 
-    let ledger_range = LedgerRange::parse(&params.ledger, latest_ledger).map_err(|e| ErrorResponse {
-        error: format!("Invalid ledger range: {}", e),
-    })?;
+/// Sends what is written as chunks of a streamed response body
+///
+/// Sending waits while the client is behind, so a slow client slows the
+/// scan down rather than letting the response pile up in memory.
+struct BodyWriter {
+    chunks: mpsc::Sender<Vec<u8>>,
+    buf: Vec<u8>,
+}
 
-    let mut all_ledgers = Vec::new();
-    let mut total_processed = 0;
+impl Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= CHUNK_BYTES {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
 
-    // Process each ledger in the range
-    for ledger_seq in ledger_range.iter() {
-        let url = config.generate_url(ledger_seq);
-
-        let decompressed_data = match fetch_and_decompress(&url, true) {
-            Ok(data) => data,
-            Err(e) => {
-                if e.to_string().contains("HTTP 404") {
-                    match fetch_from_rpc(ledger_seq, true) {
-                        Ok(data) => data,
-                        Err(_) => continue,
-                    }
-                } else {
-                    continue;
-                }
-            }
-        };
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.chunks.blocking_send(std::mem::take(&mut self.buf))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Client disconnected"))
+    }
+}
 
-        let batch = match parse_xdr(&decompressed_data) {
-            Ok(batch) => batch,
-            Err(_) => continue,
-        };
+/// Respond to a range query with results written as each ledger is processed
+///
+/// The range is checked before anything is sent, so a bad range is still a
+/// 400 error. After that the JSON is sent as a chunked response: `fields`
+/// and the `items` array from `select` first, then `ledgers_processed`,
+/// `count`, `rpc_endpoints` and `missing_ledgers` once the range is done.
+async fn range_response(
+    state: AppState,
+    ledger: String,
+    fields: Vec<(&'static str, Value)>,
+    items: &'static str,
+    select: impl Fn(&LedgerCloseMetaBatch) -> Vec<Value> + Send + 'static,
+) -> Result<Response, ErrorResponse> {
+    let range = run_blocking({
+        let state = state.clone();
+        move || {
+            let range = parse_range(&state.config, &ledger)?;
+            check_range(&state, &range)?;
+            Ok(range)
+        }
+    })
+    .await?;
+
+    let (chunks, received) = mpsc::channel(CHUNKS_AHEAD);
+    tokio::task::spawn_blocking(move || -> io::Result<()> {
+        let mut header = vec![
+            ("start_sequence", json!(range.start)),
+            ("end_sequence", json!(range.end)),
+        ];
+        header.extend(fields);
+
+        let mut output = RangeWriter::new(BodyWriter { chunks, buf: Vec::new() }, &header, items)?;
+        let (total_processed, missing_ledgers, rpc_endpoints) = scan_range(&state, &range, |batch| {
+            for item in select(batch) {
+                output.push(&item)?;
+            }
+            output.flush()
+        })?;
+
+        let count = output.count();
+        output.finish(&[
+            ("ledgers_processed", json!(total_processed)),
+            ("count", json!(count)),
+            ("rpc_endpoints", rpc_endpoints.to_json()),
+            ("missing_ledgers", missing_ledgers.to_json()),
+        ])?;
+        Ok(())
+    });
 
-        total_processed += 1;
+    let body = futures_util::stream::unfold(received, |mut received| async move {
+        received.recv().await.map(|chunk| (Ok::<_, io::Error>(chunk), received))
+    });
+    Ok(([(header::CONTENT_TYPE, "application/json")], Body::from_stream(body)).into_response())
+}
 
-        // Collect full ledger metadata
-        for meta in batch.ledger_close_metas.as_vec() {
-            if let Ok(meta_json) = serde_json::to_value(meta) {
-                all_ledgers.push(meta_json);
-            }
+/// Handler for /transactions endpoint
+/// Supports both filtered (by address) and unfiltered transaction queries
+pub async fn transactions_handler(
+    State(state): State<AppState>,
+    Query(params): Query<TransactionsQuery>,
+) -> Result<Response, ErrorResponse> {
+    let fields = vec![("address", json!(params.address))];
+    let options = transaction_options(&state.config, params.raw, params.only_failed, params.only_successful)?;
+    range_response(state, params.ledger, fields, "transactions", move |batch| {
+        // Filter by address if provided, otherwise get all transactions
+        match params.address {
+            Some(ref address) => range_items(batch, "address", Some(address), None, options),
+            None => range_items(batch, "transactions", None, None, options),
         }
-    }
+    })
+    .await
+}
 
-    let result = json!({
-        "start_sequence": ledger_range.start,
-        "end_sequence": ledger_range.end,
-        "ledgers_processed": total_processed,
-        "ledgers": all_ledgers,
-        "count": all_ledgers.len()
-    });
+/// Handler for /operations endpoint
+/// Returns one record per operation, optionally those involving an address
+pub async fn operations_handler(
+    State(state): State<AppState>,
+    Query(params): Query<OperationsQuery>,
+) -> Result<Response, ErrorResponse> {
+    let fields = vec![("address", json!(params.address))];
+    let options = transaction_options(&state.config, false, params.only_failed, params.only_successful)?;
+    range_response(state, params.ledger, fields, "operations", move |batch| {
+        range_items(batch, "operations", params.address.as_deref(), None, options)
+    })
+    .await
+}
 
-    Ok(Json(result))
+/// Handler for /all endpoint
+/// Returns full ledger metadata
+pub async fn all_handler(
+    State(state): State<AppState>,
+    Query(params): Query<AllQuery>,
+) -> Result<Response, ErrorResponse> {
+    let options = TransactionOptions::new(&state.config);
+    range_response(state, params.ledger, Vec::new(), "ledgers", move |batch| {
+        range_items(batch, "all", None, None, options)
+    })
+    .await
 }
 
 /// Handler for /contract endpoint
 /// Returns transactions involving a specific contract
 pub async fn contract_handler(
+    State(state): State<AppState>,
     Query(params): Query<ContractQuery>,
-) -> Result<Json<Value>, ErrorResponse> {
-    let config = Config::default();
-
-    let latest_ledger = if params.ledger.trim().starts_with('-') {
-        Some(get_latest_ledger().map_err(|e| ErrorResponse {
-            error: format!("Failed to get latest ledger: {}", e),
-        })?)
-    } else {
-        None
-    };
-
-    let ledger_range = LedgerRange::parse(&params.ledger, latest_ledger).map_err(|e| ErrorResponse {
-        error: format!("Invalid ledger range: {}", e),
-    })?;
-
-    let mut all_transactions = Vec::new();
-    let mut total_processed = 0;
-
-    for ledger_seq in ledger_range.iter() {
-        let url = config.generate_url(ledger_seq);
-
-        let decompressed_data = match fetch_and_decompress(&url, true) {
-            Ok(data) => data,
-            Err(e) => {
-                if e.to_string().contains("HTTP 404") {
-                    match fetch_from_rpc(ledger_seq, true) {
-                        Ok(data) => data,
-                        Err(_) => continue,
-                    }
-                } else {
-                    continue;
-                }
-            }
-        };
-
-        let batch = match parse_xdr(&decompressed_data) {
-            Ok(batch) => batch,
-            Err(_) => continue,
-        };
-
-        total_processed += 1;
-        let matching = filter_by_contract(&batch, &params.address);
-        all_transactions.extend(matching);
-    }
-
-    let result = json!({
-        "start_sequence": ledger_range.start,
-        "end_sequence": ledger_range.end,
-        "ledgers_processed": total_processed,
-        "contract": params.address,
-        "transactions": all_transactions,
-        "count": all_transactions.len()
-    });
-
-    Ok(Json(result))
+) -> Result<Response, ErrorResponse> {
+    let fields = vec![("contract", json!(params.address))];
+    let options = transaction_options(&state.config, params.raw, params.only_failed, params.only_successful)?;
+    range_response(state, params.ledger, fields, "transactions", move |batch| {
+        range_items(batch, "contract", Some(&params.address), None, options)
+    })
+    .await
 }
 
 /// Handler for /function endpoint
 /// Returns transactions calling a specific function
 pub async fn function_handler(
+    State(state): State<AppState>,
     Query(params): Query<FunctionQuery>,
-) -> Result<Json<Value>, ErrorResponse> {
-    let config = Config::default();
-
-    let latest_ledger = if params.ledger.trim().starts_with('-') {
-        Some(get_latest_ledger().map_err(|e| ErrorResponse {
-            error: format!("Failed to get latest ledger: {}", e),
-        })?)
-    } else {
-        None
-    };
-
-    let ledger_range = LedgerRange::parse(&params.ledger, latest_ledger).map_err(|e| ErrorResponse {
-        error: format!("Invalid ledger range: {}", e),
-    })?;
-
-    let mut all_transactions = Vec::new();
-    let mut total_processed = 0;
-
-    for ledger_seq in ledger_range.iter() {
-        let url = config.generate_url(ledger_seq);
-
-        let decompressed_data = match fetch_and_decompress(&url, true) {
-            Ok(data) => data,
-            Err(e) => {
-                if e.to_string().contains("HTTP 404") {
-                    match fetch_from_rpc(ledger_seq, true) {
-                        Ok(data) => data,
-                        Err(_) => continue,
-                    }
-                } else {
-                    continue;
-                }
-            }
-        };
-
-        let batch = match parse_xdr(&decompressed_data) {
-            Ok(batch) => batch,
-            Err(_) => continue,
-        };
-
-        total_processed += 1;
-        let matching = filter_by_function(&batch, &params.name);
-        all_transactions.extend(matching);
-    }
-
-    let result = json!({
-        "start_sequence": ledger_range.start,
-        "end_sequence": ledger_range.end,
-        "ledgers_processed": total_processed,
-        "function": params.name,
-        "transactions": all_transactions,
-        "count": all_transactions.len()
-    });
-
-    Ok(Json(result))
+) -> Result<Response, ErrorResponse> {
+    let fields = vec![("function", json!(params.name))];
+    let options = transaction_options(&state.config, params.raw, params.only_failed, params.only_successful)?;
+    range_response(state, params.ledger, fields, "transactions", move |batch| {
+        range_items(batch, "function", None, Some(&params.name), options)
+    })
+    .await
 }
 
 /// Handler for /balance endpoint
 /// Returns token balance for an address
 pub async fn balance_handler(
+    State(state): State<AppState>,
     Query(params): Query<BalanceQuery>,
 ) -> Result<Json<Value>, ErrorResponse> {
-    // Resolve token shortcut to contract address
-    let token_contract = Config::resolve_token(&params.token)
-        .unwrap_or(&params.token);
-
-    let result = query_balance(&params.address, token_contract).map_err(|e| ErrorResponse {
-        error: format!("Failed to query balance: {}", e),
-    })?;
-
-    Ok(Json(result))
+    run_blocking(move || {
+        // Token registry symbols are resolved by query_balance
+        let result = query_balance(&state.config, &params.address, &params.token).map_err(|e| ErrorResponse {
+            error: format!("Failed to query balance: {}", e),
+        })?;
+
+        Ok(Json(result))
+    })
+    .await
 }
 
 /// Handler for /price endpoint
 /// Returns oracle price for an asset
 pub async fn price_handler(
+    State(state): State<AppState>,
     Query(params): Query<PriceQuery>,
 ) -> Result<Json<Value>, ErrorResponse> {
-    let result = query_price(&params.asset).map_err(|e| ErrorResponse {
-        error: format!("Failed to query price: {}", e),
-    })?;
+    run_blocking(move || {
+        let result = query_price(&state.config, &params.asset).map_err(|e| ErrorResponse {
+            error: format!("Failed to query price: {}", e),
+        })?;
+
+        Ok(Json(result))
+    })
+    .await
+}
 
-    Ok(Json(result))
+/// Handler for /status endpoint
+/// Returns the latest ledger of the network and of the data lake
+pub async fn status_handler(State(state): State<AppState>) -> Result<Json<Value>, ErrorResponse> {
+    run_blocking(move || {
+        let latest_ledger = get_latest_ledger(&state.config, true).map_err(|e| ErrorResponse {
+            error: format!("Failed to get latest ledger: {}", e),
+        })?;
+        let result = lake::status(&state.config, state.source.as_ref(), latest_ledger).map_err(|e| ErrorResponse {
+            error: format!("Failed to find the latest data lake ledger: {}", e),
+        })?;
+
+        Ok(Json(result))
+    })
+    .await
 }
 
 /// Handler for /help endpoint
@@ -569,6 +629,24 @@ pub async fn help_handler() -> Html<String> {
                     <td class="optional">Optional</td>
                     <td>Stellar address to filter transactions (e.g. 'GALPCCZN4YXA3YMJHKL6CVIECKPLJJCTVMSNYWBTKJW4K5HQLYLDMZTB')</td>
                 </tr>
+                <tr>
+                    <td><strong>raw</strong></td>
+                    <td>boolean</td>
+                    <td class="optional">Optional</td>
+                    <td>Set to 'true' to include the envelope, result, fee changes and meta XDR, as JSON, with each transaction</td>
+                </tr>
+                <tr>
+                    <td><strong>only_failed</strong></td>
+                    <td>boolean</td>
+                    <td class="optional">Optional</td>
+                    <td>Set to 'true' for failed transactions only</td>
+                </tr>
+                <tr>
+                    <td><strong>only_successful</strong></td>
+                    <td>boolean</td>
+                    <td class="optional">Optional</td>
+                    <td>Set to 'true' for successful transactions only</td>
+                </tr>
             </tbody>
         </table>
 
@@ -581,6 +659,54 @@ pub async fn help_handler() -> Html<String> {
         </div>
     </div>
 
+    <div class="endpoint">
+        <h2><span class="method">GET</span> /operations</h2>
+        <p>Get one record per operation, like Horizon's /operations, optionally those involving an address. Each record has the ledger, transaction hash, operation index, effective source, type, details and result code.</p>
+
+        <table class="param-table">
+            <thead>
+                <tr>
+                    <th>Parameter</th>
+                    <th>Type</th>
+                    <th>Required</th>
+                    <th>Description</th>
+                </tr>
+            </thead>
+            <tbody>
+                <tr>
+                    <td><strong>ledger</strong></td>
+                    <td>string</td>
+                    <td class="required">Required</td>
+                    <td>Ledger sequence number, range, or negative value</td>
+                </tr>
+                <tr>
+                    <td><strong>address</strong></td>
+                    <td>string</td>
+                    <td class="optional">Optional</td>
+                    <td>Stellar address the operations involve, as source, destination, trustor and so on</td>
+                </tr>
+                <tr>
+                    <td><strong>only_failed</strong></td>
+                    <td>boolean</td>
+                    <td class="optional">Optional</td>
+                    <td>Set to 'true' for operations of failed transactions only</td>
+                </tr>
+                <tr>
+                    <td><strong>only_successful</strong></td>
+                    <td>boolean</td>
+                    <td class="optional">Optional</td>
+                    <td>Set to 'true' for operations of successful transactions only</td>
+                </tr>
+            </tbody>
+        </table>
+
+        <div class="example">
+            <div class="example-title">Examples:</div>
+            <code><a href="/operations?ledger=50000000">/operations?ledger=50000000</a></code>
+            <code><a href="/operations?ledger=-10&address=GALPCCZN4YXA3YMJHKL6CVIECKPLJJCTVMSNYWBTKJW4K5HQLYLDMZTB">/operations?ledger=-10&address=GALPCCZN4YXA3YMJHKL6CVIECKPLJJCTVMSNYWBTKJW4K5HQLYLDMZTB</a></code>
+        </div>
+    </div>
+
     <div class="endpoint">
         <h2><span class="method">GET</span> /all</h2>
         <p>Get complete ledger metadata including all transaction processing details.</p>
@@ -638,6 +764,24 @@ pub async fn help_handler() -> Html<String> {
                     <td class="required">Required</td>
                     <td>Contract address (starts with 'C')</td>
                 </tr>
+                <tr>
+                    <td><strong>raw</strong></td>
+                    <td>boolean</td>
+                    <td class="optional">Optional</td>
+                    <td>Set to 'true' to include the envelope, result, fee changes and meta XDR, as JSON, with each transaction</td>
+                </tr>
+                <tr>
+                    <td><strong>only_failed</strong></td>
+                    <td>boolean</td>
+                    <td class="optional">Optional</td>
+                    <td>Set to 'true' for failed transactions only</td>
+                </tr>
+                <tr>
+                    <td><strong>only_successful</strong></td>
+                    <td>boolean</td>
+                    <td class="optional">Optional</td>
+                    <td>Set to 'true' for successful transactions only</td>
+                </tr>
             </tbody>
         </table>
 
@@ -674,6 +818,24 @@ pub async fn help_handler() -> Html<String> {
                     <td class="required">Required</td>
                     <td>Function name (e.g. 'transfer', 'approve', 'mint')</td>
                 </tr>
+                <tr>
+                    <td><strong>raw</strong></td>
+                    <td>boolean</td>
+                    <td class="optional">Optional</td>
+                    <td>Set to 'true' to include the envelope, result, fee changes and meta XDR, as JSON, with each transaction</td>
+                </tr>
+                <tr>
+                    <td><strong>only_failed</strong></td>
+                    <td>boolean</td>
+                    <td class="optional">Optional</td>
+                    <td>Set to 'true' for failed transactions only</td>
+                </tr>
+                <tr>
+                    <td><strong>only_successful</strong></td>
+                    <td>boolean</td>
+                    <td class="optional">Optional</td>
+                    <td>Set to 'true' for successful transactions only</td>
+                </tr>
             </tbody>
         </table>
 
@@ -753,27 +915,39 @@ pub async fn help_handler() -> Html<String> {
         </div>
     </div>
 
+    <div class="endpoint">
+        <h2><span class="method">GET</span> /status</h2>
+        <p>Get the latest ledger of the network and of the data lake, and how far the data lake lags behind. Ledgers past the end of the data lake are read from RPC.</p>
+        <div class="example">
+            <div class="example-title">Example:</div>
+            <code><a href="/status">/status</a></code>
+        </div>
+    </div>
+
     <div class="notes">
         <h3>Important Notes</h3>
         <ul>
             <li><strong>Ledger Ranges:</strong> Ledger ranges are inclusive. Use '-N' for the last N ledgers (e.g. '-10' for most recent 10 ledgers)</li>
             <li><strong>Data Source:</strong> Data is fetched from AWS S3 public blockchain data lake with automatic fallback to Stellar RPC for recent ledgers</li>
+            <li><strong>Network:</strong> The network profile (pubnet, testnet, futurenet or custom) is selected with <code>--network</code> when the server is started</li>
             <li><strong>Address Format:</strong> Stellar addresses are base32-encoded Ed25519 public keys starting with 'G' (accounts) or 'C' (contracts)</li>
             <li><strong>Token Shortcuts:</strong> Supported token shortcuts: xlm, usdc, usdt, aqua, btc</li>
             <li><strong>Response Format:</strong> All responses (except /help) are JSON with metadata including start_sequence, end_sequence, ledgers_processed, and results</li>
-            <li><strong>Error Handling:</strong> Individual ledger failures in ranges are logged but don't stop processing</li>
+            <li><strong>Error Handling:</strong> Individual ledger failures in ranges don't stop processing; they are listed in <code>missing_ledgers</code> as runs of consecutive ledgers with their reason (<code>not_found</code>, <code>network</code>, <code>throttled</code>, <code>http</code>, <code>rpc</code>, <code>decompress</code>, <code>decode</code>, ...) and the first error message</li>
         </ul>
     </div>
 
     <div class="response-structure">
         <h3>Response Structure</h3>
-        <p>All API endpoints (except /help) return JSON responses with the following general structure:</p>
+        <p>All API endpoints (except /help) return JSON responses with the following general structure. Ledger queries stream their results as a chunked response while the range is processed, so the summary fields come last:</p>
         <pre>{
   "start_sequence": &lt;first ledger in range&gt;,
   "end_sequence": &lt;last ledger in range&gt;,
-  "ledgers_processed": &lt;number of successfully processed ledgers&gt;,
   &lt;endpoint-specific fields&gt;,
-  "count": &lt;number of results&gt;
+  "ledgers_processed": &lt;number of successfully processed ledgers&gt;,
+  "count": &lt;number of results&gt;,
+  "rpc_endpoints": [{"url": &lt;RPC endpoint&gt;, "ledgers": &lt;ledgers it served&gt;}],
+  "missing_ledgers": [{"start": &lt;sequence&gt;, "end": &lt;sequence&gt;, "count": &lt;ledgers&gt;, "reason": &lt;failure kind&gt;, "retryable": &lt;bool&gt;, "error": &lt;message&gt;}]
 }</pre>
     </div>
 </body>
@@ -783,21 +957,45 @@ pub async fn help_handler() -> Html<String> {
 }
 
 /// Create and configure the Axum router
-pub fn create_router() -> Router {
+///
+/// Range queries read ledgers from `state.source`, so the server can be
+/// backed by any `LedgerSource`.
+pub fn create_router(state: AppState) -> Router {
     Router::new()
         .route("/help", get(help_handler))
         .route("/transactions", get(transactions_handler))
+        .route("/operations", get(operations_handler))
         .route("/all", get(all_handler))
         .route("/contract", get(contract_handler))
         .route("/function", get(function_handler))
         .route("/balance", get(balance_handler))
         .route("/price", get(price_handler))
+        .route("/status", get(status_handler))
         .layer(CorsLayer::permissive())
+        .with_state(state)
 }
 
 /// Start the API server
-pub async fn start_server(port: u16) -> anyhow::Result<()> {
-    let app = create_router();
+pub async fn start_server(port: u16, mut config: Config) -> anyhow::Result<()> {
+    // Read the data lake layout from its manifest
+    let config = tokio::task::spawn_blocking(move || discover_layout(&mut config).map(|()| config)).await??;
+
+    let state = AppState::new(config);
+    let network = state.config.network.as_str();
+
+    // Check the RPC endpoints in the background, so requests skip unhealthy ones
+    let health_config = Arc::clone(&state.config);
+    tokio::spawn(async move {
+        let period = std::time::Duration::from_secs(health_config.rpc_health_check_secs.into());
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let config = Arc::clone(&health_config);
+            let _ = tokio::task::spawn_blocking(move || check_all(&config)).await;
+        }
+    });
+    let sources = state.source.name();
+    let app = create_router(state.clone());
 
     let addr = format!("0.0.0.0:{}", port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
@@ -805,14 +1003,20 @@ pub async fn start_server(port: u16) -> anyhow::Result<()> {
     println!("Stellar Data API Server");
     println!("======================");
     println!("Listening on http://{}", addr);
+    println!("Network: {}", network);
+    println!("Ledger sources: {}", sources);
+    println!("Archive RPC: {}", state.config.archive_rpc_urls().join(", "));
+    println!("Soroban RPC: {}", state.config.soroban_rpc_urls().join(", "));
     println!("\nAvailable endpoints:");
     println!("  GET /help");
     println!("  GET /transactions?ledger=<LEDGER>&address=<ADDRESS>");
+    println!("  GET /operations?ledger=<LEDGER>&address=<ADDRESS>");
     println!("  GET /all?ledger=<LEDGER>");
     println!("  GET /contract?ledger=<LEDGER>&address=<CONTRACT>");
     println!("  GET /function?ledger=<LEDGER>&name=<FUNCTION>");
     println!("  GET /balance?address=<ADDRESS>&token=<TOKEN>");
     println!("  GET /price?asset=<ASSET>");
+    println!("  GET /status");
     println!("\nFor detailed API documentation, visit:");
     println!("  http://127.0.0.1:{}/help\n", port);
 
@@ -820,3 +1024,133 @@ pub async fn start_server(port: u16) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    use crate::data::error::FetchError;
+    use crate::data::source::tests::MemorySource;
+    use crate::data::source::LedgerReader;
+    use crate::data::xdr::tests::test_ledger;
+
+    /// A source that holds each fetch until another one is under way, or
+    /// a few seconds have passed, noting whether two ever overlapped
+    struct OverlapSource {
+        ledgers: MemorySource,
+        in_flight: AtomicUsize,
+        overlapped: Arc<AtomicBool>,
+    }
+
+    impl LedgerSource for OverlapSource {
+        fn name(&self) -> String {
+            "overlap".to_string()
+        }
+
+        fn fetch_batch(&self, ledger_seq: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+            if self.in_flight.fetch_add(1, Ordering::SeqCst) > 0 {
+                self.overlapped.store(true, Ordering::SeqCst);
+            }
+            let waiting = Instant::now();
+            while !self.overlapped.load(Ordering::SeqCst) && waiting.elapsed() < Duration::from_secs(5) {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            self.ledgers.fetch_batch(ledger_seq)
+        }
+    }
+
+    /// A source that serves ledgers as an RPC endpoint would
+    struct RpcLikeSource(MemorySource);
+
+    impl LedgerSource for RpcLikeSource {
+        fn name(&self) -> String {
+            "RPC".to_string()
+        }
+
+        fn fetch_batch(&self, ledger_seq: u32) -> Result<LedgerCloseMetaBatch, FetchError> {
+            self.0.fetch_batch(ledger_seq)
+        }
+
+        fn read_batch(&self, ledger_seq: u32, _end: u32) -> Result<LedgerReader, FetchError> {
+            let mut reader = LedgerReader::from(self.fetch_batch(ledger_seq)?);
+            reader.served_by = Some("https://rpc.example/".to_string());
+            Ok(reader)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_range_results_name_rpc_endpoints() {
+        let state = AppState {
+            config: Arc::new(Config { concurrency: 2, ..Config::default() }),
+            source: Arc::new(RpcLikeSource((100..=102).map(test_ledger).collect())),
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, create_router(state)).await });
+
+        let response = reqwest::get(format!("http://{}/transactions?ledger=100-102", addr)).await.unwrap();
+        let result: Value = response.json().await.unwrap();
+        assert_eq!(result["rpc_endpoints"], json!([{ "url": "https://rpc.example/", "ledgers": 3 }]));
+    }
+
+    #[tokio::test]
+    async fn test_range_queries_do_not_block_each_other() {
+        let overlapped = Arc::new(AtomicBool::new(false));
+        let state = AppState {
+            config: Arc::new(Config { concurrency: 1, ..Config::default() }),
+            source: Arc::new(OverlapSource {
+                ledgers: (100..=101).map(test_ledger).collect(),
+                in_flight: AtomicUsize::new(0),
+                overlapped: Arc::clone(&overlapped),
+            }),
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, create_router(state)).await });
+
+        // The test runtime has a single thread, so blocking handlers would run one after the other
+        let client = reqwest::Client::new();
+        let (all, transactions) = tokio::join!(
+            client.get(format!("http://{}/all?ledger=100-101", addr)).send(),
+            client.get(format!("http://{}/transactions?ledger=100-101", addr)).send(),
+        );
+
+        let all: Value = all.unwrap().json().await.unwrap();
+        let transactions: Value = transactions.unwrap().json().await.unwrap();
+        assert_eq!(all["ledgers_processed"], 2);
+        assert_eq!(transactions["ledgers_processed"], 2);
+        // Both requests were fetching ledgers at the same time
+        assert!(overlapped.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_range_results_are_streamed() {
+        let state = AppState {
+            config: Arc::new(Config { concurrency: 1, ..Config::default() }),
+            source: Arc::new((100..=104).map(test_ledger).collect::<MemorySource>()),
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, create_router(state)).await });
+
+        let client = reqwest::Client::new();
+        let response = client.get(format!("http://{}/all?ledger=100-105", addr)).send().await.unwrap();
+        assert_eq!(response.headers()[header::TRANSFER_ENCODING], "chunked");
+
+        let text = response.text().await.unwrap();
+        let result: Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(result["ledgers_processed"], 5);
+        assert_eq!(result["count"], 5);
+        assert_eq!(result["missing_ledgers"][0]["start"], 105);
+        assert_eq!(result["missing_ledgers"][0]["reason"], "not_found");
+        // The summary comes after the results
+        assert!(text.find("\"ledgers\"").unwrap() < text.find("\"ledgers_processed\"").unwrap());
+
+        // Bad ranges are rejected before anything is streamed
+        let response = client.get(format!("http://{}/all?ledger=200-100", addr)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
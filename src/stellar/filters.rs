@@ -1,5 +1,6 @@
-use stellar_xdr::curr::{LedgerCloseMetaBatch, LedgerCloseMeta, TransactionEnvelope, OperationBody};
+use stellar_xdr::curr::{LedgerCloseMetaBatch, TransactionEnvelope, Operation, OperationBody};
 use crate::stellar::address::{muxed_account_to_string, account_id_to_string};
+use crate::stellar::transaction::{ledger_transactions, source_and_operations, LedgerTransaction};
 
 /// Check if a transaction involves a specific address
 pub fn transaction_involves_address(tx_envelope: &TransactionEnvelope, target_address: &str) -> bool {
@@ -109,6 +110,14 @@ pub fn operation_involves_address(body: &OperationBody, target_address: &str) ->
     }
 }
 
+/// Check if an operation involves a specific address, as its effective
+/// source (its own source account, or else `tx_source`) or in its body
+pub fn operation_involves(op: &Operation, tx_source: &str, target_address: &str) -> bool {
+    let source = op.source_account.as_ref().map(muxed_account_to_string);
+    source.as_deref().unwrap_or(tx_source) == target_address
+        || operation_involves_address(&op.body, target_address)
+}
+
 /// Check if a transaction involves a specific contract address
 pub fn transaction_involves_contract(tx_envelope: &TransactionEnvelope, contract_address: &str) -> bool {
     use TransactionEnvelope::*;
@@ -188,122 +197,72 @@ pub fn transaction_calls_function(tx_envelope: &TransactionEnvelope, function_na
 }
 
 /// Filter transactions in a batch by address
-pub fn filter_by_address(batch: &LedgerCloseMetaBatch, address: &str) -> Vec<serde_json::Value> {
-    let mut matching_transactions = Vec::new();
-
-    for meta in batch.ledger_close_metas.as_vec() {
-        match meta {
-            LedgerCloseMeta::V0(v0) => {
-                for tx in v0.tx_set.txs.as_vec() {
-                    if transaction_involves_address(tx, address) {
-                        if let Ok(tx_json) = serde_json::to_value(tx) {
-                            matching_transactions.push(tx_json);
-                        }
-                    }
-                }
-            }
-            LedgerCloseMeta::V1(v1) => {
-                for tx_result in v1.tx_processing.as_vec() {
-                    // V1 contains the full tx_set, we need to cross reference
-                    // For simplicity, just serialize all transactions for V1
-                    if let Ok(tx_json) = serde_json::to_value(tx_result) {
-                        matching_transactions.push(tx_json);
-                    }
-                }
-            }
-            LedgerCloseMeta::V2(v2) => {
-                for tx_result in v2.tx_processing.as_vec() {
-                    // V2 is similar to V1
-                    if let Ok(tx_json) = serde_json::to_value(tx_result) {
-                        matching_transactions.push(tx_json);
-                    }
-                }
-            }
-        }
-    }
-
-    matching_transactions
+pub fn filter_by_address<'a>(batch: &'a LedgerCloseMetaBatch, address: &str, network_id: [u8; 32]) -> Vec<LedgerTransaction<'a>> {
+    filter_transactions(batch, network_id, |tx| transaction_involves_address(tx, address))
 }
 
 /// Filter transactions in a batch by contract address
-pub fn filter_by_contract(batch: &LedgerCloseMetaBatch, contract_address: &str) -> Vec<serde_json::Value> {
-    let mut matching_transactions = Vec::new();
-
-    for meta in batch.ledger_close_metas.as_vec() {
-        match meta {
-            LedgerCloseMeta::V0(v0) => {
-                for tx in v0.tx_set.txs.as_vec() {
-                    if transaction_involves_contract(tx, contract_address) {
-                        if let Ok(tx_json) = serde_json::to_value(tx) {
-                            matching_transactions.push(tx_json);
-                        }
-                    }
-                }
-            }
-            LedgerCloseMeta::V1(v1) => {
-                for tx_result in v1.tx_processing.as_vec() {
-                    if let Ok(tx_json) = serde_json::to_value(tx_result) {
-                        matching_transactions.push(tx_json);
-                    }
-                }
-            }
-            LedgerCloseMeta::V2(v2) => {
-                for tx_result in v2.tx_processing.as_vec() {
-                    if let Ok(tx_json) = serde_json::to_value(tx_result) {
-                        matching_transactions.push(tx_json);
-                    }
-                }
-            }
-        }
-    }
-
-    matching_transactions
+pub fn filter_by_contract<'a>(batch: &'a LedgerCloseMetaBatch, contract_address: &str, network_id: [u8; 32]) -> Vec<LedgerTransaction<'a>> {
+    filter_transactions(batch, network_id, |tx| transaction_involves_contract(tx, contract_address))
 }
 
 /// Filter transactions in a batch by function name
-pub fn filter_by_function(batch: &LedgerCloseMetaBatch, function_name: &str) -> Vec<serde_json::Value> {
-    let mut matching_transactions = Vec::new();
-
-    for meta in batch.ledger_close_metas.as_vec() {
-        match meta {
-            LedgerCloseMeta::V0(v0) => {
-                for tx in v0.tx_set.txs.as_vec() {
-                    if transaction_calls_function(tx, function_name) {
-                        if let Ok(tx_json) = serde_json::to_value(tx) {
-                            matching_transactions.push(tx_json);
-                        }
-                    }
-                }
-            }
-            LedgerCloseMeta::V1(v1) => {
-                for tx_result in v1.tx_processing.as_vec() {
-                    if let Ok(tx_json) = serde_json::to_value(tx_result) {
-                        matching_transactions.push(tx_json);
-                    }
-                }
-            }
-            LedgerCloseMeta::V2(v2) => {
-                for tx_result in v2.tx_processing.as_vec() {
-                    if let Ok(tx_json) = serde_json::to_value(tx_result) {
-                        matching_transactions.push(tx_json);
-                    }
-                }
-            }
-        }
-    }
+pub fn filter_by_function<'a>(batch: &'a LedgerCloseMetaBatch, function_name: &str, network_id: [u8; 32]) -> Vec<LedgerTransaction<'a>> {
+    filter_transactions(batch, network_id, |tx| transaction_calls_function(tx, function_name))
+}
 
-    matching_transactions
+/// Operations in a batch involving an address, or all of them
+///
+/// Returns each transaction with operations to keep, along with the
+/// positions of those operations in the transaction.
+pub fn filter_operations<'a>(
+    batch: &'a LedgerCloseMetaBatch,
+    address: Option<&str>,
+    network_id: [u8; 32],
+) -> Vec<(LedgerTransaction<'a>, Vec<usize>)> {
+    batch.ledger_close_metas.iter()
+        .flat_map(|meta| ledger_transactions(meta, network_id))
+        .filter_map(|tx| {
+            let (source, operations) = source_and_operations(tx.envelope?);
+            let matching: Vec<usize> = operations.iter().enumerate()
+                .filter(|(_, op)| address.is_none_or(|address| operation_involves(op, &source, address)))
+                .map(|(index, _)| index)
+                .collect();
+            (!matching.is_empty()).then_some((tx, matching))
+        })
+        .collect()
+}
+
+/// Transactions in a batch whose envelope matches, with their results
+fn filter_transactions<'a>(
+    batch: &'a LedgerCloseMetaBatch,
+    network_id: [u8; 32],
+    matches: impl Fn(&TransactionEnvelope) -> bool,
+) -> Vec<LedgerTransaction<'a>> {
+    batch.ledger_close_metas.iter()
+        .flat_map(|meta| ledger_transactions(meta, network_id))
+        .filter(|tx| tx.envelope.is_some_and(&matches))
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::tokens::network_id;
+    use crate::output::{range_items, TransactionOptions};
+    use crate::stellar::hash::transaction_hash;
     use stellar_xdr::curr::{
-        TransactionV0, TransactionV0Envelope, Memo,
+        TransactionV0, TransactionV0Envelope, TransactionV1Envelope, Memo,
         SequenceNumber, Uint256, CreateAccountOp, PaymentOp, Asset,
-        VecM, AccountId, PublicKey, MuxedAccount
+        VecM, AccountId, PublicKey, MuxedAccount, FeeBumpTransaction, FeeBumpTransactionEnvelope,
+        FeeBumpTransactionExt, FeeBumpTransactionInnerTx, InnerTransactionResult, InnerTransactionResultExt,
+        InnerTransactionResultPair, InnerTransactionResultResult, LedgerCloseMeta, OperationResult,
+        OperationResultTr, PaymentResult, TransactionMeta, TransactionResult, TransactionResultExt,
+        TransactionResultMeta, TransactionResultPair, TransactionResultResult
     };
 
+    const PUBNET: &str = "Public Global Stellar Network ; September 2015";
+
     // Helper function to create a test Uint256
     fn create_test_uint256() -> Uint256 {
         Uint256([
@@ -323,6 +282,47 @@ mod tests {
         ])
     }
 
+    // Helper to create a payment, with its own source account if given
+    fn create_payment(source: Option<Uint256>, destination: Uint256) -> stellar_xdr::curr::Operation {
+        stellar_xdr::curr::Operation {
+            source_account: source.map(MuxedAccount::Ed25519),
+            body: OperationBody::Payment(PaymentOp {
+                destination: MuxedAccount::Ed25519(destination),
+                asset: Asset::Native,
+                amount: 1,
+            }),
+        }
+    }
+
+    // Helper to create a TxV1 envelope
+    fn create_test_tx_v1_envelope(source_account: Uint256, seq: i64, operations: Vec<stellar_xdr::curr::Operation>) -> TransactionV1Envelope {
+        TransactionV1Envelope {
+            tx: stellar_xdr::curr::Transaction {
+                source_account: MuxedAccount::Ed25519(source_account),
+                fee: 100,
+                seq_num: SequenceNumber(seq),
+                cond: stellar_xdr::curr::Preconditions::None,
+                memo: Memo::None,
+                operations: operations.try_into().unwrap(),
+                ext: stellar_xdr::curr::TransactionExt::V0,
+            },
+            signatures: VecM::default(),
+        }
+    }
+
+    // Helper to create the result of an applied transaction
+    fn create_result_meta(envelope: &TransactionEnvelope, result: TransactionResultResult) -> TransactionResultMeta {
+        let hash = transaction_hash(envelope, network_id(PUBNET)).unwrap();
+        TransactionResultMeta {
+            result: TransactionResultPair {
+                transaction_hash: hash.parse().unwrap(),
+                result: TransactionResult { fee_charged: 100, result, ext: TransactionResultExt::V0 },
+            },
+            fee_processing: Default::default(),
+            tx_apply_processing: TransactionMeta::V0(Default::default()),
+        }
+    }
+
     // Helper to create a simple TxV0 envelope
     fn create_test_tx_v0_envelope(source_account: Uint256) -> TransactionEnvelope {
         let tx = TransactionV0 {
@@ -495,4 +495,101 @@ mod tests {
         // Native asset has no issuer, so should not match
         assert!(!operation_involves_address(&op_body, target_address));
     }
+
+    #[test]
+    fn test_operation_involves_effective_source() {
+        let target_address = "GB67AEDGTIDPDMJEX7KYVAUJAS7EV6ITRGZM3GBW2RLT5DBPDYHVSVCR";
+        let other_address = "GA7QYNF7SOWQ3GLR2BGMZEHXAVIRZA4KVWLTJJFC7MGXUA74P7UJVSGZ";
+        let mut op = stellar_xdr::curr::Operation { source_account: None, body: OperationBody::Inflation };
+
+        // Without a source account of its own, the transaction's applies
+        assert!(operation_involves(&op, target_address, target_address));
+        assert!(!operation_involves(&op, other_address, target_address));
+
+        op.source_account = Some(MuxedAccount::Ed25519(create_test_uint256()));
+        assert!(operation_involves(&op, other_address, target_address));
+        op.source_account = Some(MuxedAccount::Ed25519(create_different_uint256()));
+        assert!(!operation_involves(&op, target_address, target_address));
+    }
+
+    #[test]
+    fn test_operations_of_a_batch() {
+        let target_address = "GB67AEDGTIDPDMJEX7KYVAUJAS7EV6ITRGZM3GBW2RLT5DBPDYHVSVCR";
+        let (target, other) = (create_test_uint256(), create_different_uint256());
+        let paid = || OperationResult::OpInner(OperationResultTr::Payment(PaymentResult::Success));
+
+        // Paid to someone else, then with the address as the operation
+        // source, then paid to the address
+        let payments = TransactionEnvelope::Tx(create_test_tx_v1_envelope(other.clone(), 1, vec![
+            create_payment(None, other.clone()),
+            create_payment(Some(target.clone()), other.clone()),
+            create_payment(None, target.clone()),
+        ]));
+        // The address is the source of the wrapped transaction, not the fee source
+        let fee_bump = TransactionEnvelope::TxFeeBump(FeeBumpTransactionEnvelope {
+            tx: FeeBumpTransaction {
+                fee_source: MuxedAccount::Ed25519(other.clone()),
+                fee: 200,
+                inner_tx: FeeBumpTransactionInnerTx::Tx(create_test_tx_v1_envelope(target.clone(), 2, vec![
+                    create_payment(None, other.clone()),
+                ])),
+                ext: FeeBumpTransactionExt::V0,
+            },
+            signatures: VecM::default(),
+        });
+        let unrelated = TransactionEnvelope::Tx(create_test_tx_v1_envelope(other.clone(), 3, vec![
+            create_payment(None, other.clone()),
+        ]));
+
+        let mut meta = match crate::data::xdr::tests::test_ledger(10) {
+            LedgerCloseMeta::V0(v0) => v0,
+            _ => unreachable!(),
+        };
+        meta.tx_processing = vec![
+            create_result_meta(&payments, TransactionResultResult::TxSuccess(vec![paid(), paid(), paid()].try_into().unwrap())),
+            create_result_meta(&fee_bump, TransactionResultResult::TxFeeBumpInnerFailed(InnerTransactionResultPair {
+                transaction_hash: stellar_xdr::curr::Hash([1; 32]),
+                result: InnerTransactionResult {
+                    fee_charged: 100,
+                    result: InnerTransactionResultResult::TxFailed(
+                        vec![OperationResult::OpInner(OperationResultTr::Payment(PaymentResult::Underfunded))].try_into().unwrap(),
+                    ),
+                    ext: InnerTransactionResultExt::V0,
+                },
+            })),
+            create_result_meta(&unrelated, TransactionResultResult::TxSuccess(vec![paid()].try_into().unwrap())),
+        ].try_into().unwrap();
+        meta.tx_set.txs = vec![payments, fee_bump.clone(), unrelated].try_into().unwrap();
+        let batch = LedgerCloseMetaBatch {
+            start_sequence: 10,
+            end_sequence: 10,
+            ledger_close_metas: vec![LedgerCloseMeta::V0(meta)].try_into().unwrap(),
+        };
+
+        let id = network_id(PUBNET);
+        let matching: Vec<(usize, Vec<usize>)> = filter_operations(&batch, Some(target_address), id).into_iter()
+            .map(|(tx, operations)| (tx.index, operations))
+            .collect();
+        assert_eq!(matching, vec![(0, vec![1, 2]), (1, vec![0])]);
+        assert_eq!(filter_operations(&batch, None, id).len(), 3);
+
+        let options = |success| TransactionOptions { network_id: id, raw: false, success };
+        let operations = range_items(&batch, "operations", Some(target_address), None, options(None));
+        let positions: Vec<_> = operations.iter().map(|op| (op["transaction_index"].clone(), op["index"].clone())).collect();
+        assert_eq!(positions, vec![(0.into(), 1.into()), (0.into(), 2.into()), (1.into(), 0.into())]);
+        assert_eq!(operations[0]["source"], target_address);
+        assert_eq!(operations[0]["result_code"], "op_success");
+
+        // The fee-bumped operation is the wrapped transaction's, under the outer hash
+        let fee_bumped = &operations[2];
+        assert_eq!(fee_bumped["source"], target_address);
+        assert_eq!(fee_bumped["transaction_hash"], transaction_hash(&fee_bump, id).unwrap());
+        assert_eq!(fee_bumped["result_code"], "op_underfunded");
+
+        // Only the fee-bumped transaction failed
+        let failed = range_items(&batch, "operations", Some(target_address), None, options(Some(false)));
+        assert_eq!(failed, vec![fee_bumped.clone()]);
+        let successful = range_items(&batch, "operations", Some(target_address), None, options(Some(true)));
+        assert_eq!(successful, operations[..2]);
+    }
 }
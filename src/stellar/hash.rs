@@ -0,0 +1,93 @@
+use stellar_xdr::curr::{FeeBumpTransactionInnerTx, Hash, TransactionEnvelope};
+
+/// Hex hash of a transaction on the network with id `network_id` (the
+/// SHA-256 of its passphrase)
+///
+/// This is the hash explorers and Horizon know a transaction by. V0
+/// envelopes hash as the equivalent V1 transaction, and for a fee-bump
+/// envelope it is the hash of the fee-bump transaction.
+pub fn transaction_hash(envelope: &TransactionEnvelope, network_id: [u8; 32]) -> Result<String, stellar_xdr::curr::Error> {
+    envelope.hash(network_id).map(|hash| Hash(hash).to_string())
+}
+
+/// Hex hash of the transaction wrapped by a fee-bump envelope, if it is one
+pub fn inner_transaction_hash(envelope: &TransactionEnvelope, network_id: [u8; 32]) -> Option<Result<String, stellar_xdr::curr::Error>> {
+    match envelope {
+        TransactionEnvelope::TxFeeBump(env) => match &env.tx.inner_tx {
+            FeeBumpTransactionInnerTx::Tx(inner_env) => Some(inner_env.hash(network_id).map(|hash| Hash(hash).to_string())),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tokens::network_id;
+    use stellar_xdr::curr::{
+        FeeBumpTransaction, FeeBumpTransactionEnvelope, FeeBumpTransactionExt, Memo, MuxedAccount,
+        Preconditions, SequenceNumber, Transaction, TransactionExt, TransactionV0, TransactionV0Envelope,
+        TransactionV0Ext, TransactionV1Envelope, Uint256, VecM,
+    };
+
+    const PUBNET: &str = "Public Global Stellar Network ; September 2015";
+    const TESTNET: &str = "Test SDF Network ; September 2015";
+
+    fn v1_envelope() -> TransactionEnvelope {
+        TransactionEnvelope::Tx(TransactionV1Envelope {
+            tx: Transaction {
+                source_account: MuxedAccount::Ed25519(Uint256([7; 32])),
+                fee: 100,
+                seq_num: SequenceNumber(42),
+                cond: Preconditions::None,
+                memo: Memo::None,
+                operations: VecM::default(),
+                ext: TransactionExt::V0,
+            },
+            signatures: VecM::default(),
+        })
+    }
+
+    #[test]
+    fn test_v0_hashes_as_v1() {
+        let v0 = TransactionEnvelope::TxV0(TransactionV0Envelope {
+            tx: TransactionV0 {
+                source_account_ed25519: Uint256([7; 32]),
+                fee: 100,
+                seq_num: SequenceNumber(42),
+                time_bounds: None,
+                memo: Memo::None,
+                operations: VecM::default(),
+                ext: TransactionV0Ext::V0,
+            },
+            signatures: VecM::default(),
+        });
+
+        let hash = transaction_hash(&v0, network_id(PUBNET)).unwrap();
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, transaction_hash(&v1_envelope(), network_id(PUBNET)).unwrap());
+        // The same transaction has another hash on another network
+        assert_ne!(hash, transaction_hash(&v1_envelope(), network_id(TESTNET)).unwrap());
+        assert!(inner_transaction_hash(&v0, network_id(PUBNET)).is_none());
+    }
+
+    #[test]
+    fn test_fee_bump_hashes() {
+        let TransactionEnvelope::Tx(inner) = v1_envelope() else { unreachable!() };
+        let fee_bump = TransactionEnvelope::TxFeeBump(FeeBumpTransactionEnvelope {
+            tx: FeeBumpTransaction {
+                fee_source: MuxedAccount::Ed25519(Uint256([9; 32])),
+                fee: 400,
+                inner_tx: FeeBumpTransactionInnerTx::Tx(inner),
+                ext: FeeBumpTransactionExt::V0,
+            },
+            signatures: VecM::default(),
+        });
+
+        let id = network_id(PUBNET);
+        let hash = transaction_hash(&fee_bump, id).unwrap();
+        let inner_hash = inner_transaction_hash(&fee_bump, id).unwrap().unwrap();
+        assert_eq!(inner_hash, transaction_hash(&v1_envelope(), id).unwrap());
+        assert_ne!(hash, inner_hash);
+    }
+}
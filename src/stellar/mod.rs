@@ -1,2 +1,4 @@
 pub mod address;
 pub mod filters;
+pub mod hash;
+pub mod transaction;
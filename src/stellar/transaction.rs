@@ -0,0 +1,677 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use stellar_xdr::curr::{
+    FeeBumpTransactionInnerTx, GeneralizedTransactionSet, InnerTransactionResultResult, LedgerCloseMeta,
+    LedgerEntryChanges, Memo, Operation, OperationResult, OperationResultTr, Preconditions, Transaction, TransactionEnvelope, TransactionMeta, TransactionPhase,
+    TransactionResultMeta, TransactionResultMetaV1, TransactionResultPair, TransactionResultResult,
+    TxSetComponent, VecM,
+};
+
+use crate::data::xdr::ledger_sequence;
+use crate::stellar::address::muxed_account_to_string;
+use crate::stellar::hash::{inner_transaction_hash, transaction_hash};
+
+/// A transaction as ledger metadata holds it: its envelope from the
+/// transaction set, with its result, fee changes and meta once applied
+#[derive(Debug, Clone, Copy)]
+pub struct LedgerTransaction<'a> {
+    pub ledger: u32,
+    /// Position in the order transactions were applied
+    pub index: usize,
+    pub envelope: Option<&'a TransactionEnvelope>,
+    pub result: Option<ResultMeta<'a>>,
+}
+
+/// Result and metadata of an applied transaction, in the form the ledger
+/// version stores it
+#[derive(Debug, Clone, Copy)]
+pub enum ResultMeta<'a> {
+    /// V0 and V1 ledgers
+    V0(&'a TransactionResultMeta),
+    /// V2 ledgers, which add fee changes made after the transaction was applied
+    V1(&'a TransactionResultMetaV1),
+}
+
+impl<'a> ResultMeta<'a> {
+    /// The transaction hash and result
+    pub fn pair(&self) -> &'a TransactionResultPair {
+        match self {
+            ResultMeta::V0(meta) => &meta.result,
+            ResultMeta::V1(meta) => &meta.result,
+        }
+    }
+
+    /// Ledger entry changes from charging the fee, before the transaction was applied
+    pub fn fee_changes(&self) -> &'a LedgerEntryChanges {
+        match self {
+            ResultMeta::V0(meta) => &meta.fee_processing,
+            ResultMeta::V1(meta) => &meta.fee_processing,
+        }
+    }
+
+    /// What applying the transaction changed
+    pub fn meta(&self) -> &'a TransactionMeta {
+        match self {
+            ResultMeta::V0(meta) => &meta.tx_apply_processing,
+            ResultMeta::V1(meta) => &meta.tx_apply_processing,
+        }
+    }
+
+    /// Ledger entry changes from fee refunds after the transaction was applied
+    pub fn post_apply_fee_changes(&self) -> Option<&'a LedgerEntryChanges> {
+        match self {
+            ResultMeta::V0(_) => None,
+            ResultMeta::V1(meta) => Some(&meta.post_tx_apply_fee_processing),
+        }
+    }
+}
+
+/// Transactions of a ledger, in the order they were applied
+///
+/// Transaction sets don't list envelopes in the order they were applied, so
+/// each result is paired with the envelope of the same hash, computed for
+/// the network with id `network_id`. With the wrong network, nothing pairs
+/// up: results come without envelopes, followed by envelopes without
+/// results.
+pub fn ledger_transactions(meta: &LedgerCloseMeta, network_id: [u8; 32]) -> Vec<LedgerTransaction<'_>> {
+    let ledger = ledger_sequence(meta);
+    let (envelopes, results): (Vec<&TransactionEnvelope>, Vec<ResultMeta>) = match meta {
+        LedgerCloseMeta::V0(v0) => (v0.tx_set.txs.iter().collect(), v0.tx_processing.iter().map(ResultMeta::V0).collect()),
+        LedgerCloseMeta::V1(v1) => (set_envelopes(&v1.tx_set), v1.tx_processing.iter().map(ResultMeta::V0).collect()),
+        LedgerCloseMeta::V2(v2) => (set_envelopes(&v2.tx_set), v2.tx_processing.iter().map(ResultMeta::V1).collect()),
+    };
+
+    let hashed: Vec<(Option<String>, &TransactionEnvelope)> = envelopes.into_iter()
+        .map(|envelope| (transaction_hash(envelope, network_id).ok(), envelope))
+        .collect();
+    let mut by_hash: HashMap<&str, &TransactionEnvelope> = hashed.iter()
+        .filter_map(|(hash, envelope)| Some((hash.as_deref()?, *envelope)))
+        .collect();
+
+    let mut transactions: Vec<LedgerTransaction> = results.into_iter().enumerate()
+        .map(|(index, result)| LedgerTransaction {
+            ledger,
+            index,
+            envelope: by_hash.remove(result.pair().transaction_hash.to_string().as_str()),
+            result: Some(result),
+        })
+        .collect();
+
+    // A valid ledger has a result for every envelope, but keep any left over
+    for (hash, envelope) in &hashed {
+        if hash.as_deref().is_none_or(|hash| by_hash.remove(hash).is_some()) {
+            let index = transactions.len();
+            transactions.push(LedgerTransaction { ledger, index, envelope: Some(envelope), result: None });
+        }
+    }
+    transactions
+}
+
+/// Envelopes of a generalized transaction set, in the order it lists them
+///
+/// The classic phase holds components of envelopes; the Soroban phase holds
+/// them either the same way or, from protocol 23, as parallel execution
+/// stages of dependent clusters.
+pub fn set_envelopes(tx_set: &GeneralizedTransactionSet) -> Vec<&TransactionEnvelope> {
+    let mut envelopes = Vec::new();
+    match tx_set {
+        GeneralizedTransactionSet::V1(set) => {
+            for phase in set.phases.iter() {
+                match phase {
+                    TransactionPhase::V0(components) => {
+                        for component in components.iter() {
+                            match component {
+                                TxSetComponent::TxsetCompTxsMaybeDiscountedFee(component) => envelopes.extend(component.txs.iter()),
+                            }
+                        }
+                    }
+                    TransactionPhase::V1(parallel) => {
+                        for stage in parallel.execution_stages.iter() {
+                            for cluster in stage.0.iter() {
+                                envelopes.extend(cluster.0.iter());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    envelopes
+}
+
+/// A transaction in the same shape whatever the ledger version
+///
+/// Fields the ledger metadata doesn't hold for a transaction are null.
+/// Fields only some transactions have, such as those of fee-bump
+/// transactions, are left out when they don't apply.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NormalizedTransaction {
+    pub ledger: u32,
+    pub index: usize,
+    /// Hex transaction hash on the network queried
+    pub hash: Option<String>,
+    /// Hash of the transaction a fee-bump transaction wraps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_hash: Option<String>,
+    pub source: Option<String>,
+    /// Account paying the fee: the fee-bump fee source, otherwise the source
+    pub fee_source: Option<String>,
+    pub fee_charged: Option<i64>,
+    pub memo: Option<TransactionMemo>,
+    pub time_bounds: Option<TimeBounds>,
+    pub operations: Vec<NormalizedOperation>,
+    pub success: Option<bool>,
+    /// Result code, such as `tx_success` or `tx_bad_seq`
+    pub result_code: Option<String>,
+    /// Result code of the transaction a fee-bump transaction wraps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_result_code: Option<String>,
+    /// The envelope, result, fee changes and meta XDR as JSON, when asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<Value>,
+}
+
+/// Memo of a transaction, such as `{"type": "text", "value": "hello"}`
+///
+/// Hashes are hex; ids are strings, as they may not fit a JSON number.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TransactionMemo {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub value: String,
+}
+
+/// Unix times a transaction is valid between; a `max_time` of 0 means no limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TimeBounds {
+    pub min_time: u64,
+    pub max_time: u64,
+}
+
+/// An operation of a transaction
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NormalizedOperation {
+    /// Source account set on the operation itself, if any
+    pub source: Option<String>,
+    /// Operation type, such as `payment` or `invoke_host_function`
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The operation's fields as XDR JSON
+    pub details: Value,
+    /// Result code, such as `op_success` or `op_underfunded`; null when
+    /// the transaction failed before its operations were applied
+    pub result_code: Option<String>,
+}
+
+/// An operation with the transaction it belongs to, one per record in
+/// `--query operations`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OperationRecord {
+    pub ledger: u32,
+    pub transaction_hash: Option<String>,
+    /// Position of the transaction in the order transactions were applied
+    pub transaction_index: usize,
+    /// Position of the operation in its transaction
+    pub index: usize,
+    /// Operation source account, or the transaction's if it has none
+    pub source: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub details: Value,
+    pub result_code: Option<String>,
+}
+
+impl LedgerTransaction<'_> {
+    /// Whether the transaction succeeded, if it has a result
+    pub fn success(&self) -> Option<bool> {
+        self.result.map(|result| matches!(
+            result.pair().result.result,
+            TransactionResultResult::TxSuccess(_) | TransactionResultResult::TxFeeBumpInnerSuccess(_)
+        ))
+    }
+
+    /// The transaction as a `NormalizedTransaction`, with the XDR it was
+    /// read from as `raw` if asked for
+    ///
+    /// Hashes of envelopes are computed for the network with id
+    /// `network_id`; results carry their hash.
+    pub fn normalize(&self, network_id: [u8; 32], raw: bool) -> NormalizedTransaction {
+        let mut tx = NormalizedTransaction {
+            ledger: self.ledger,
+            index: self.index,
+            hash: None,
+            inner_hash: None,
+            source: None,
+            fee_source: None,
+            fee_charged: None,
+            memo: None,
+            time_bounds: None,
+            operations: Vec::new(),
+            success: self.success(),
+            result_code: None,
+            inner_result_code: None,
+            raw: None,
+        };
+
+        if let Some(envelope) = self.envelope {
+            tx.set_envelope(envelope);
+        }
+        if let Some(result) = self.result {
+            tx.set_result(result.pair());
+        }
+        if let (None, Some(envelope)) = (&tx.hash, self.envelope) {
+            tx.hash = transaction_hash(envelope, network_id).ok();
+            tx.inner_hash = inner_transaction_hash(envelope, network_id).and_then(Result::ok);
+        }
+        if raw {
+            tx.raw = Some(json!({
+                "envelope": self.envelope,
+                "result": self.result.map(|result| &result.pair().result),
+                "fee_changes": self.result.map(|result| result.fee_changes()),
+                "meta": self.result.map(|result| result.meta()),
+                "post_apply_fee_changes": self.result.and_then(|result| result.post_apply_fee_changes()),
+            }));
+        }
+        tx
+    }
+
+    /// The operations at positions `indexes` as records of their own
+    ///
+    /// Only those operations are normalized, so picking a few operations
+    /// out of a large transaction doesn't pay for the rest.
+    pub fn operation_records(&self, network_id: [u8; 32], indexes: &[usize]) -> Vec<OperationRecord> {
+        let Some(envelope) = self.envelope else { return Vec::new() };
+        let (source, operations) = source_and_operations(envelope);
+        let hash = match self.result {
+            Some(result) => Some(result.pair().transaction_hash.to_string()),
+            None => transaction_hash(envelope, network_id).ok(),
+        };
+        let results = self.result.map_or(&[][..], |result| operation_results(&result.pair().result.result));
+
+        indexes.iter()
+            .filter_map(|&index| {
+                let operation = normalize_operation(operations.get(index)?);
+                Some(OperationRecord {
+                    ledger: self.ledger,
+                    transaction_hash: hash.clone(),
+                    transaction_index: self.index,
+                    index,
+                    source: operation.source.or_else(|| Some(source.clone())),
+                    kind: operation.kind,
+                    details: operation.details,
+                    result_code: results.get(index).map(operation_result_code),
+                })
+            })
+            .collect()
+    }
+}
+
+impl NormalizedTransaction {
+    fn set_envelope(&mut self, envelope: &TransactionEnvelope) {
+        match envelope {
+            TransactionEnvelope::TxV0(env) => {
+                let source = stellar_strkey::ed25519::PublicKey(env.tx.source_account_ed25519.0).to_string();
+                let time_bounds = env.tx.time_bounds.as_ref().map(|bounds| TimeBounds {
+                    min_time: bounds.min_time.0,
+                    max_time: bounds.max_time.0,
+                });
+                self.set_body(source, &env.tx.memo, time_bounds, &env.tx.operations);
+            }
+            TransactionEnvelope::Tx(env) => self.set_transaction(&env.tx),
+            TransactionEnvelope::TxFeeBump(env) => {
+                match &env.tx.inner_tx {
+                    FeeBumpTransactionInnerTx::Tx(inner_env) => self.set_transaction(&inner_env.tx),
+                }
+                self.fee_source = Some(muxed_account_to_string(&env.tx.fee_source));
+            }
+        }
+    }
+
+    fn set_result(&mut self, pair: &TransactionResultPair) {
+        let outcome = &pair.result.result;
+        self.hash = Some(pair.transaction_hash.to_string());
+        self.fee_charged = Some(pair.result.fee_charged);
+        self.result_code = Some(snake_case(outcome.name()));
+
+        if let TransactionResultResult::TxFeeBumpInnerSuccess(inner) | TransactionResultResult::TxFeeBumpInnerFailed(inner) = outcome {
+            self.inner_hash = Some(inner.transaction_hash.to_string());
+            self.inner_result_code = Some(snake_case(inner.result.result.name()));
+        }
+        for (operation, result) in self.operations.iter_mut().zip(operation_results(outcome)) {
+            operation.result_code = Some(operation_result_code(result));
+        }
+    }
+
+    fn set_transaction(&mut self, tx: &Transaction) {
+        let time_bounds = match &tx.cond {
+            Preconditions::None => None,
+            Preconditions::Time(bounds) => Some(bounds),
+            Preconditions::V2(conditions) => conditions.time_bounds.as_ref(),
+        };
+        let time_bounds = time_bounds.map(|bounds| TimeBounds {
+            min_time: bounds.min_time.0,
+            max_time: bounds.max_time.0,
+        });
+        self.set_body(muxed_account_to_string(&tx.source_account), &tx.memo, time_bounds, &tx.operations);
+    }
+
+    fn set_body(&mut self, source: String, memo: &Memo, time_bounds: Option<TimeBounds>, operations: &VecM<Operation, 100>) {
+        self.fee_source = Some(source.clone());
+        self.source = Some(source);
+        self.memo = normalize_memo(memo);
+        self.time_bounds = time_bounds;
+        self.operations = operations.iter().map(normalize_operation).collect();
+    }
+}
+
+/// Source account and operations of a transaction (the wrapped transaction
+/// for fee-bumps)
+pub fn source_and_operations(envelope: &TransactionEnvelope) -> (String, &[Operation]) {
+    match envelope {
+        TransactionEnvelope::TxV0(env) => (
+            stellar_strkey::ed25519::PublicKey(env.tx.source_account_ed25519.0).to_string(),
+            env.tx.operations.as_slice(),
+        ),
+        TransactionEnvelope::Tx(env) => (muxed_account_to_string(&env.tx.source_account), env.tx.operations.as_slice()),
+        TransactionEnvelope::TxFeeBump(env) => match &env.tx.inner_tx {
+            FeeBumpTransactionInnerTx::Tx(inner_env) => (
+                muxed_account_to_string(&inner_env.tx.source_account),
+                inner_env.tx.operations.as_slice(),
+            ),
+        },
+    }
+}
+
+/// Results of the operations of a transaction (the wrapped transaction for
+/// fee-bumps), empty when it failed before they were applied
+fn operation_results(outcome: &TransactionResultResult) -> &[OperationResult] {
+    match outcome {
+        TransactionResultResult::TxSuccess(results) | TransactionResultResult::TxFailed(results) => results.as_slice(),
+        TransactionResultResult::TxFeeBumpInnerSuccess(inner) | TransactionResultResult::TxFeeBumpInnerFailed(inner) => {
+            match &inner.result.result {
+                InnerTransactionResultResult::TxSuccess(results) | InnerTransactionResultResult::TxFailed(results) => results.as_slice(),
+                _ => &[],
+            }
+        }
+        _ => &[],
+    }
+}
+
+fn normalize_memo(memo: &Memo) -> Option<TransactionMemo> {
+    let (kind, value) = match memo {
+        Memo::None => return None,
+        Memo::Text(text) => ("text", text.to_utf8_string_lossy()),
+        Memo::Id(id) => ("id", id.to_string()),
+        Memo::Hash(hash) => ("hash", hash.to_string()),
+        Memo::Return(hash) => ("return", hash.to_string()),
+    };
+    Some(TransactionMemo { kind, value })
+}
+
+fn normalize_operation(op: &Operation) -> NormalizedOperation {
+    // Operation bodies serialize as `{"<type>": {...fields}}`, or just
+    // `"<type>"` for those without fields
+    let details = match serde_json::to_value(&op.body) {
+        Ok(Value::Object(body)) => body.into_iter().next().map(|(_, fields)| fields).unwrap_or(Value::Null),
+        _ => Value::Null,
+    };
+
+    NormalizedOperation {
+        source: op.source_account.as_ref().map(muxed_account_to_string),
+        kind: snake_case(op.body.name()),
+        details,
+        result_code: None,
+    }
+}
+
+/// Result code of an operation, such as `op_success`, `op_underfunded` or
+/// `op_no_account`, as Horizon names them
+pub fn operation_result_code(result: &OperationResult) -> String {
+    use OperationResultTr::*;
+
+    let name = match result {
+        OperationResult::OpInner(result) => match result {
+            CreateAccount(result) => result.name(),
+            Payment(result) => result.name(),
+            PathPaymentStrictReceive(result) => result.name(),
+            ManageSellOffer(result) => result.name(),
+            CreatePassiveSellOffer(result) => result.name(),
+            SetOptions(result) => result.name(),
+            ChangeTrust(result) => result.name(),
+            AllowTrust(result) => result.name(),
+            AccountMerge(result) => result.name(),
+            Inflation(result) => result.name(),
+            ManageData(result) => result.name(),
+            BumpSequence(result) => result.name(),
+            ManageBuyOffer(result) => result.name(),
+            PathPaymentStrictSend(result) => result.name(),
+            CreateClaimableBalance(result) => result.name(),
+            ClaimClaimableBalance(result) => result.name(),
+            BeginSponsoringFutureReserves(result) => result.name(),
+            EndSponsoringFutureReserves(result) => result.name(),
+            RevokeSponsorship(result) => result.name(),
+            Clawback(result) => result.name(),
+            ClawbackClaimableBalance(result) => result.name(),
+            SetTrustLineFlags(result) => result.name(),
+            LiquidityPoolDeposit(result) => result.name(),
+            LiquidityPoolWithdraw(result) => result.name(),
+            InvokeHostFunction(result) => result.name(),
+            ExtendFootprintTtl(result) => result.name(),
+            RestoreFootprint(result) => result.name(),
+        },
+        // Failures that apply to any operation are already named `Op...`
+        other => return snake_case(other.name()),
+    };
+    format!("op_{}", snake_case(name))
+}
+
+/// An XDR variant name in snake case, such as `tx_bad_seq` for `TxBadSeq`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tokens::network_id;
+    use stellar_xdr::curr::{
+        DependentTxCluster, Hash, LedgerCloseMetaV2, MuxedAccount, OperationBody, ParallelTxExecutionStage,
+        ParallelTxsComponent, PaymentOp, Asset, SequenceNumber, TimePoint, TransactionExt, TransactionResult,
+        TransactionResultExt, TransactionSetV1, TransactionV1Envelope, TxSetComponentTxsMaybeDiscountedFee,
+        Uint256,
+    };
+
+    const SOURCE: &str = "GB67AEDGTIDPDMJEX7KYVAUJAS7EV6ITRGZM3GBW2RLT5DBPDYHVSVCR";
+    const PUBNET: &str = "Public Global Stellar Network ; September 2015";
+
+    fn source_key() -> Uint256 {
+        Uint256([
+            0x7d, 0xf0, 0x10, 0x66, 0x9a, 0x06, 0xf1, 0xb1,
+            0x24, 0xbf, 0xd5, 0x8a, 0x82, 0x89, 0x04, 0xbe,
+            0x4a, 0xf9, 0x13, 0x89, 0xb2, 0xcd, 0x98, 0x36,
+            0xd4, 0x57, 0x3e, 0x8c, 0x2f, 0x1e, 0x0f, 0x59,
+        ])
+    }
+
+    fn result_meta(hash: &str, result: TransactionResultResult) -> TransactionResultMetaV1 {
+        TransactionResultMetaV1 {
+            result: TransactionResultPair {
+                transaction_hash: hash.parse().unwrap(),
+                result: TransactionResult { fee_charged: 100, result, ext: TransactionResultExt::V0 },
+            },
+            ..Default::default()
+        }
+    }
+
+    fn payment_envelope() -> TransactionEnvelope {
+        TransactionEnvelope::Tx(TransactionV1Envelope {
+            tx: Transaction {
+                source_account: MuxedAccount::Ed25519(source_key()),
+                fee: 100,
+                seq_num: SequenceNumber(7),
+                cond: Preconditions::Time(stellar_xdr::curr::TimeBounds {
+                    min_time: TimePoint(0),
+                    max_time: TimePoint(1_700_000_000),
+                }),
+                memo: Memo::Text("hello".try_into().unwrap()),
+                operations: vec![Operation {
+                    source_account: None,
+                    body: OperationBody::Payment(PaymentOp {
+                        destination: MuxedAccount::Ed25519(source_key()),
+                        asset: Asset::Native,
+                        amount: 10_000_000,
+                    }),
+                }].try_into().unwrap(),
+                ext: TransactionExt::V0,
+            },
+            signatures: VecM::default(),
+        })
+    }
+
+    #[test]
+    fn test_normalize_envelope() {
+        let envelope = payment_envelope();
+        let ledger_tx = LedgerTransaction { ledger: 5, index: 0, envelope: Some(&envelope), result: None };
+        let tx = ledger_tx.normalize(network_id(PUBNET), false);
+
+        assert_eq!(tx.source.as_deref(), Some(SOURCE));
+        assert_eq!(tx.fee_source, tx.source);
+        assert_eq!(tx.memo, Some(TransactionMemo { kind: "text", value: "hello".to_string() }));
+        assert_eq!(tx.time_bounds, Some(TimeBounds { min_time: 0, max_time: 1_700_000_000 }));
+        assert_eq!(tx.operations.len(), 1);
+        assert_eq!(tx.operations[0].kind, "payment");
+        assert_eq!(tx.operations[0].details["amount"], json!("10000000"));
+        assert_eq!(tx.success, None);
+
+        assert_eq!(tx.hash, Some(transaction_hash(&envelope, network_id(PUBNET)).unwrap()));
+        assert_eq!(tx.inner_hash, None);
+
+        // The shape stays the same, with nulls for what isn't known
+        let json = serde_json::to_value(&tx).unwrap();
+        assert_eq!(json["fee_charged"], Value::Null);
+        assert!(json.get("raw").is_none());
+        assert!(json.get("inner_hash").is_none());
+        assert!(json.get("inner_result_code").is_none());
+
+        let operations = ledger_tx.operation_records(network_id(PUBNET), &[0, 1]);
+        assert_eq!(operations.len(), 1);
+        assert_eq!((operations[0].ledger, operations[0].index), (5, 0));
+        assert_eq!(operations[0].source.as_deref(), Some(SOURCE));
+        assert_eq!(operations[0].transaction_hash, tx.hash);
+        assert_eq!(operations[0].details, tx.operations[0].details);
+    }
+
+    #[test]
+    fn test_normalize_result() {
+        let result = TransactionResultMeta {
+            result: TransactionResultPair {
+                transaction_hash: Hash([0xab; 32]),
+                result: TransactionResult {
+                    fee_charged: 100,
+                    result: TransactionResultResult::TxBadSeq,
+                    ext: TransactionResultExt::V0,
+                },
+            },
+            fee_processing: Default::default(),
+            tx_apply_processing: Default::default(),
+        };
+        let tx = LedgerTransaction { ledger: 5, index: 3, envelope: None, result: Some(ResultMeta::V0(&result)) }.normalize(network_id(PUBNET), true);
+
+        assert_eq!(tx.hash, Some("ab".repeat(32)));
+        assert_eq!(tx.fee_charged, Some(100));
+        assert_eq!(tx.success, Some(false));
+        assert_eq!(tx.result_code.as_deref(), Some("tx_bad_seq"));
+        let raw = tx.raw.unwrap();
+        assert_eq!(raw["envelope"], Value::Null);
+        assert!(raw["result"].is_object());
+    }
+
+    #[test]
+    fn test_operation_result_codes() {
+        let envelope = payment_envelope();
+        let underfunded = OperationResult::OpInner(OperationResultTr::Payment(stellar_xdr::curr::PaymentResult::Underfunded));
+        let result = result_meta(&"cd".repeat(32), TransactionResultResult::TxFailed(vec![underfunded].try_into().unwrap()));
+        let tx = LedgerTransaction { ledger: 5, index: 0, envelope: Some(&envelope), result: Some(ResultMeta::V1(&result)) };
+
+        assert_eq!(tx.success(), Some(false));
+        let tx = tx.normalize(network_id(PUBNET), false);
+        assert_eq!(tx.result_code.as_deref(), Some("tx_failed"));
+        assert_eq!(tx.operations[0].result_code.as_deref(), Some("op_underfunded"));
+        assert_eq!(operation_result_code(&OperationResult::OpNoAccount), "op_no_account");
+        assert_eq!(
+            operation_result_code(&OperationResult::OpInner(OperationResultTr::InvokeHostFunction(
+                stellar_xdr::curr::InvokeHostFunctionResult::Success(Hash([0; 32]))
+            ))),
+            "op_success"
+        );
+    }
+
+    #[test]
+    fn test_envelopes_are_joined_with_results() {
+        let id = network_id(PUBNET);
+        let classic = payment_envelope();
+        let mut soroban = payment_envelope();
+        if let TransactionEnvelope::Tx(ref mut env) = soroban {
+            env.tx.seq_num = SequenceNumber(8);
+        }
+        let classic_hash = transaction_hash(&classic, id).unwrap();
+        let soroban_hash = transaction_hash(&soroban, id).unwrap();
+
+        let meta = LedgerCloseMeta::V2(LedgerCloseMetaV2 {
+            ledger_header: match crate::data::xdr::tests::test_ledger(9) {
+                LedgerCloseMeta::V0(v0) => v0.ledger_header,
+                _ => unreachable!(),
+            },
+            tx_set: GeneralizedTransactionSet::V1(TransactionSetV1 {
+                previous_ledger_hash: Hash([0; 32]),
+                phases: vec![
+                    TransactionPhase::V0(vec![TxSetComponent::TxsetCompTxsMaybeDiscountedFee(
+                        TxSetComponentTxsMaybeDiscountedFee { base_fee: None, txs: vec![classic].try_into().unwrap() },
+                    )].try_into().unwrap()),
+                    TransactionPhase::V1(ParallelTxsComponent {
+                        base_fee: None,
+                        execution_stages: vec![ParallelTxExecutionStage(vec![
+                            DependentTxCluster(vec![soroban].try_into().unwrap()),
+                        ].try_into().unwrap())].try_into().unwrap(),
+                    }),
+                ].try_into().unwrap(),
+            }),
+            // Applied in another order than the set lists them
+            tx_processing: vec![
+                result_meta(&soroban_hash, TransactionResultResult::TxSuccess(VecM::default())),
+                result_meta(&classic_hash, TransactionResultResult::TxFailed(VecM::default())),
+            ].try_into().unwrap(),
+            ..Default::default()
+        });
+
+        let transactions = ledger_transactions(&meta, id);
+        assert_eq!(transactions.len(), 2);
+        let normalized: Vec<_> = transactions.iter().map(|tx| tx.normalize(id, true)).collect();
+        assert_eq!(normalized[0].hash.as_deref(), Some(soroban_hash.as_str()));
+        assert_eq!(normalized[0].success, Some(true));
+        assert_eq!(normalized[1].hash.as_deref(), Some(classic_hash.as_str()));
+        assert_eq!(normalized[1].result_code.as_deref(), Some("tx_failed"));
+        assert!(normalized.iter().all(|tx| tx.ledger == 9 && tx.source.as_deref() == Some(SOURCE)));
+        let raw = normalized[0].raw.as_ref().unwrap();
+        assert!(raw["envelope"].is_object() && raw["meta"].is_object());
+        assert!(raw["post_apply_fee_changes"].is_array());
+
+        // With another network's passphrase nothing pairs up
+        let unpaired = ledger_transactions(&meta, network_id("Test SDF Network ; September 2015"));
+        assert_eq!(unpaired.len(), 4);
+        assert!(unpaired.iter().all(|tx| tx.envelope.is_none() != tx.result.is_none()));
+    }
+}
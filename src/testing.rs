@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::config::Config;
+use crate::data::xdr::tests::test_batch_xdr;
+
+/// A directory of its own under the system temp directory, deleted on drop
+///
+/// Names are unique per process and per call, so tests running in parallel
+/// never share one, and the directory is removed even when a test panics.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "stellar-data-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Config for a local data lake in `dir`
+pub fn lake_config(dir: &Path, ledgers_per_batch: u32, batches_per_partition: u32) -> Config {
+    Config {
+        base_url: dir.display().to_string(),
+        ledgers_per_batch,
+        batches_per_partition,
+        ..Config::default()
+    }
+}
+
+/// Write zstd compressed `data` as the batch file holding a ledger
+pub fn write_object(config: &Config, ledger_seq: u32, data: &[u8]) {
+    let path = PathBuf::from(config.generate_url(ledger_seq));
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, zstd::encode_all(data, 0).unwrap()).unwrap();
+}
+
+/// Write the batch files holding the ledgers `start..=end`
+pub fn write_lake(config: &Config, start: u32, end: u32) {
+    let mut seq = config.batch_start(start);
+    while seq <= end {
+        let last = seq + config.ledgers_per_batch - 1;
+        write_object(config, seq, &test_batch_xdr(seq, last));
+        seq = last + 1;
+    }
+}